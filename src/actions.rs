@@ -1,13 +1,21 @@
 use rand::Rng;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
+use crate::UInt;
+use crate::config::{Config, core_config};
 use crate::goods::Good;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Action {
     ProduceGood(Good),
+    /// Produce a good on each of the given number of consecutive days. The agent still
+    /// consumes its daily requirement, and its stock degrades, after each day's production.
+    ProduceGoodFor(Good, UInt),
     Leisure,
+    Consume,
 }
 
 impl From<ActionFlattened> for Action {
@@ -21,12 +29,16 @@ impl From<ActionFlattened> for Action {
             ActionFlattened::ProduceBoat => Action::ProduceGood(Good::Boat),
             ActionFlattened::ProduceTimber => Action::ProduceGood(Good::Timber),
             ActionFlattened::ProduceAxe => Action::ProduceGood(Good::Axe),
+            ActionFlattened::ProduceGoodFor(good, days) => Action::ProduceGoodFor(good, days),
             ActionFlattened::Leisure => Action::Leisure,
+            ActionFlattened::Consume => Action::Consume,
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, EnumIter)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, EnumIter,
+)]
 pub enum ActionFlattened {
     ProduceBerries,
     ProduceFish,
@@ -37,6 +49,11 @@ pub enum ActionFlattened {
     ProduceTimber,
     ProduceAxe,
     Leisure,
+    Consume,
+    /// A batch of production (see `Action::ProduceGoodFor`). Batches are not iterated, so are
+    /// not part of the action space of the RL model.
+    #[strum(disabled)]
+    ProduceGoodFor(Good, UInt),
 }
 
 impl From<Action> for ActionFlattened {
@@ -50,27 +67,61 @@ impl From<Action> for ActionFlattened {
             Action::ProduceGood(Good::Boat) => ActionFlattened::ProduceBoat,
             Action::ProduceGood(Good::Timber) => ActionFlattened::ProduceTimber,
             Action::ProduceGood(Good::Axe) => ActionFlattened::ProduceAxe,
+            Action::ProduceGoodFor(good, days) => ActionFlattened::ProduceGoodFor(good, days),
             Action::Leisure => ActionFlattened::Leisure,
+            Action::Consume => ActionFlattened::Consume,
         }
     }
 }
 
+impl ActionFlattened {
+    /// Returns every action enabled under the default config (see `Action::is_enabled_with`).
+    pub fn enabled() -> Vec<ActionFlattened> {
+        Self::enabled_with(&core_config())
+    }
+
+    /// Returns every action enabled under the given config (see `Action::is_enabled_with`).
+    pub fn enabled_with(config: &Config) -> Vec<ActionFlattened> {
+        ActionFlattened::iter()
+            .filter(|action| Action::from(*action).is_enabled_with(config))
+            .collect()
+    }
+}
+
 impl Action {
-    pub fn random<R: Rng + ?Sized>(rng: &mut R) -> Self {
-        match rng.random_range(0..=8) {
-            0 => Action::Leisure,
-            1 => Action::ProduceGood(Good::Berries),
-            2 => Action::ProduceGood(Good::Fish),
-            3 => Action::ProduceGood(Good::Basket),
-            4 => Action::ProduceGood(Good::Spear),
-            5 => Action::ProduceGood(Good::Smoker),
-            6 => Action::ProduceGood(Good::Boat),
-            7 => Action::ProduceGood(Good::Timber),
-            8 => Action::ProduceGood(Good::Axe),
-            _ => unreachable!(),
+    /// Returns the good produced by this action, if any.
+    pub fn good(&self) -> Option<Good> {
+        match self {
+            Action::ProduceGood(good) | Action::ProduceGoodFor(good, _) => Some(*good),
+            Action::Leisure | Action::Consume => None,
         }
     }
 
+    /// Returns true if this action is available under the given config: that is, it does
+    /// not involve a disabled good, and it is not `Consume` when consumption is automatic.
+    pub fn is_enabled_with(&self, config: &Config) -> bool {
+        match self {
+            Action::Consume => !config.auto_consume,
+            _ => self
+                .good()
+                .is_none_or(|good| config.enabled_goods.contains(&good)),
+        }
+    }
+
+    pub fn random<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        Self::random_with(rng, &core_config())
+    }
+
+    /// Returns an action chosen uniformly at random from those enabled under the given config.
+    pub fn random_with<R: Rng + ?Sized>(rng: &mut R, config: &Config) -> Self {
+        let actions: Vec<Action> = std::iter::once(Action::Leisure)
+            .chain(Good::iter().map(Action::ProduceGood))
+            .chain(std::iter::once(Action::Consume))
+            .filter(|action| action.is_enabled_with(config))
+            .collect();
+        actions[rng.random_range(0..actions.len())]
+    }
+
     pub fn random_weighted<R: Rng + ?Sized>(rng: &mut R, prob_produce_berries: f64) -> Self {
         if rng.random::<f64>() < prob_produce_berries {
             Action::ProduceGood(Good::Berries)
@@ -78,4 +129,76 @@ impl Action {
             Action::Leisure
         }
     }
+
+    /// Returns an action chosen at random with probability proportional to its weight.
+    /// Actions without a weight, or with a weight that is not positive, are never chosen.
+    /// If no action has a positive weight, an action is chosen uniformly at random.
+    pub fn random_from_weights<R: Rng + ?Sized>(
+        rng: &mut R,
+        weights: &HashMap<ActionFlattened, f64>,
+    ) -> Self {
+        // Iterate in a fixed order so that the choice is reproducible for a seeded rng.
+        let weighted: Vec<(ActionFlattened, f64)> = ActionFlattened::iter()
+            .filter_map(|action| weights.get(&action).map(|weight| (action, *weight)))
+            .filter(|(_, weight)| *weight > 0.0 && weight.is_finite())
+            .collect();
+        let total: f64 = weighted.iter().map(|(_, weight)| weight).sum();
+        if weighted.is_empty() || !total.is_finite() {
+            return Self::random(rng);
+        }
+        let mut r = rng.random::<f64>() * total;
+        for (action, weight) in &weighted {
+            if r < *weight {
+                return Action::from(*action);
+            }
+            r -= weight;
+        }
+        // Guard against rounding error in the cumulative sum.
+        Action::from(weighted.last().expect("there is at least one weight").0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_batch_conversion() {
+        let batch = Action::ProduceGoodFor(Good::Fish, 3);
+        let flattened = ActionFlattened::from(batch);
+        assert_eq!(flattened, ActionFlattened::ProduceGoodFor(Good::Fish, 3));
+        assert_eq!(Action::from(flattened), batch);
+        // Batches are not part of the action space.
+        assert!(
+            ActionFlattened::iter()
+                .all(|action| !matches!(action, ActionFlattened::ProduceGoodFor(..)))
+        );
+    }
+
+    #[test]
+    fn test_random_from_weights() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let weights = HashMap::from([
+            (ActionFlattened::ProduceFish, 1.0),
+            (ActionFlattened::Leisure, 0.0),
+        ]);
+        for _ in 0..100 {
+            let action = Action::random_from_weights(&mut rng, &weights);
+            assert_eq!(action, Action::ProduceGood(Good::Fish));
+        }
+
+        // Without any positive weight, every action remains possible.
+        let weights = HashMap::from([(ActionFlattened::Leisure, 0.0)]);
+        let sampled: HashSet<Action> = (0..1000)
+            .map(|_| Action::random_from_weights(&mut rng, &weights))
+            .collect();
+        assert!(sampled.len() > 1);
+        let sampled: HashSet<Action> = (0..1000)
+            .map(|_| Action::random_from_weights(&mut rng, &HashMap::new()))
+            .collect();
+        assert!(sampled.len() > 1);
+    }
 }
@@ -2,69 +2,283 @@ use enum_dispatch::enum_dispatch;
 use rand::SeedableRng;
 use rand::rngs::StdRng;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use strum::IntoEnumIterator;
 
 use crate::actions::{Action, ActionFlattened};
+use crate::config::{AgentKind, AgentSpec, NutritionRequirement, RewardConfig, SharedConfig};
 use crate::goods::{Good, GoodsUnit, PartialGoodsUnit, Productivity};
 use crate::learning::agent_state::DiscrRep;
+use crate::learning::history::SAR;
 use crate::learning::learning_agent::LearningAgent;
 use crate::learning::reward::Reward;
-use crate::stock::Stock;
+use crate::resource::{Resources, SharedResources};
+use crate::season::Season;
+use crate::stock::{self, Stock, StockError};
 use crate::valuation::RationalAgent;
-use crate::{Model, NEGATIVE_REWARD, POSITIVE_REWARD, UInt};
+use crate::{AgentHistory, Int, Model, NEGATIVE_REWARD, POSITIVE_REWARD, UInt};
 
 #[enum_dispatch]
 pub trait Agent {
     fn get_id(&self) -> u64;
     fn get_name(&self) -> &str;
+    /// The state common to every kind of agent.
+    fn core(&self) -> &AgentCore;
+    /// Mutable access to the state common to every kind of agent.
+    fn core_mut(&mut self) -> &mut AgentCore;
+    /// The config under which the agent acts.
+    fn config(&self) -> &SharedConfig {
+        &self.core().config
+    }
+    /// Sets the config under which the agent acts (e.g. that of its simulation).
+    fn set_config(&mut self, config: SharedConfig) {
+        self.core_mut().config = config;
+    }
+    /// The season in which the agent currently acts, or None if seasons are not configured.
+    fn season(&self) -> Option<Season> {
+        self.core().season
+    }
+    /// Sets the season in which the agent acts (e.g. that of its simulation at the current time).
+    fn set_season(&mut self, season: Option<Season>) {
+        self.core_mut().season = season;
+    }
+    /// The renewable resources available to the agent, as of its most recent time step.
+    fn resources(&self) -> &Resources {
+        &self.core().resources
+    }
+    /// Sets the renewable resources available to the agent (e.g. those of its simulation).
+    fn set_resources(&mut self, resources: Resources) {
+        self.core_mut().resources = resources;
+    }
+    /// Returns the multiplier of the agent's productivity of a good in the current season.
+    fn season_multiplier(&self, good: &Good) -> f32 {
+        match (&self.config().seasons, self.season()) {
+            (Some(seasons), Some(season)) => seasons.multiplier(season, good),
+            _ => 1.0,
+        }
+    }
     /// The stock of goods currently held by the agent.
     fn stock(&self) -> &Stock;
-    /// The marginal productivity of the agent, given their current stock.
+    /// Mutable access to the stock of goods currently held by the agent.
     fn stock_mut(&mut self) -> &mut Stock;
     fn set_stock(&mut self, stock: Stock);
-    fn acquire(&mut self, goods_unit: GoodsUnit, quantity: UInt);
-    fn acquire_partial(&mut self, partial_goods_unit: PartialGoodsUnit);
+    fn acquire(&mut self, goods_unit: GoodsUnit, quantity: UInt) -> Result<(), StockError>;
+    fn acquire_partial(&mut self, partial_goods_unit: PartialGoodsUnit) -> Result<(), StockError>;
+    /// Acquire newly-produced units of a good, each with its lifetime jittered by up to
+    /// `lifetime_jitter` days (see `GoodsUnit::new_with_jitter`).
+    fn acquire_produced(
+        &mut self,
+        good: &Good,
+        quantity: UInt,
+        lifetime_jitter: UInt,
+    ) -> Result<(), StockError> {
+        if lifetime_jitter == 0 || quantity == 0 {
+            return self.acquire(GoodsUnit::new(good), quantity);
+        }
+        for _ in 0..quantity {
+            let goods_unit = GoodsUnit::new_with_jitter(good, lifetime_jitter, self.rng());
+            self.acquire(goods_unit, 1)?;
+        }
+        Ok(())
+    }
     fn get_partial(&self, good: Good) -> Option<PartialGoodsUnit>;
+    /// The agent's random number generator, used in choosing actions.
+    fn rng(&mut self) -> &mut StdRng {
+        &mut self.core_mut().rng
+    }
     /// Returns the number of units of the good produced per day,
-    /// given the agent's existing stock.
+    /// given the agent's existing stock, in the current season.
     fn productivity(&self, good: &Good) -> Productivity {
-        // TODO: make configurable.
         // Note: can modify default productivity for different agents (for specialisation).
-        good.default_productivity(&self.stock())
+        let productivity = good
+            .configured_productivity(self.stock(), &[], self.build_time(good), self.config())
+            .scaled(self.season_multiplier(good));
+        self.depleted(good, productivity)
+    }
+    /// Returns the given productivity of a good reduced by the depletion of any renewable
+    /// resource from which the good is harvested.
+    fn depleted(&self, good: &Good, productivity: Productivity) -> Productivity {
+        match (productivity, self.resources().get(good)) {
+            (Productivity::Immediate(qty), Some(resource)) => match resource.yield_of(qty) {
+                0 => Productivity::None,
+                qty => Productivity::Immediate(qty),
+            },
+            _ => productivity,
+        }
+    }
+    /// Returns the number of units of the good produced per day, given the agent's existing
+    /// stock and the shared goods currently available to it, in the current season.
+    fn productivity_with_shared(&self, good: &Good, shared: &SharedResources) -> Productivity {
+        let shared_tools = shared.available_tools();
+        let productivity = good
+            .configured_productivity(
+                self.stock(),
+                &shared_tools,
+                self.build_time(good),
+                self.config(),
+            )
+            .scaled(self.season_multiplier(good));
+        self.depleted(good, productivity)
+    }
+    /// Returns the shared goods that the agent would use to produce the good: the single
+    /// shared tool that alone gives the productivity available with all of them (e.g. the
+    /// better of a shared Spear and Boat), or else every shared input it lacks. Returns no
+    /// goods if the shared goods do not improve on the agent's own stock.
+    fn shared_tools_used(&self, good: &Good, shared: &SharedResources) -> Vec<Good> {
+        let productivity = self.productivity_with_shared(good, shared);
+        if productivity == self.productivity(good) {
+            return vec![];
+        }
+        let candidates: Vec<Good> = shared
+            .available_tools()
+            .into_iter()
+            .filter(|tool| good.is_produced_using(tool) && !self.stock().contains(tool))
+            .collect();
+        let sufficient = candidates.iter().find(|tool| {
+            let only = SharedResources::new(BTreeMap::from([(**tool, 1)]));
+            self.productivity_with_shared(good, &only) == productivity
+        });
+        match sufficient {
+            Some(tool) => vec![*tool],
+            None => candidates,
+        }
+    }
+    /// Returns the input whose shortage ultimately prevents the agent from producing the good,
+    /// following any missing input back to one that the agent could produce from its stock
+    /// (e.g. the Axe needed to make the Timber for a Boat). Returns None if the agent has
+    /// the inputs to produce the good.
+    fn bottleneck(&self, good: &Good) -> Option<Good> {
+        let mut bottleneck = self.missing_input(good)?;
+        while let Some(input) = self.missing_input(&bottleneck) {
+            bottleneck = input;
+        }
+        Some(bottleneck)
+    }
+    /// Returns the first input required to produce the good that is missing from the agent's
+    /// stock, given the inputs needed for every remaining timestep of its production.
+    fn missing_input(&self, good: &Good) -> Option<Good> {
+        good.missing_input_with(self.stock(), self.build_time(good), self.config())
+    }
+    /// Returns a snapshot of the agent's decision-relevant state, omitting its histories.
+    fn decision_snapshot(&self) -> DecisionState {
+        DecisionState {
+            stock: self.stock().clone(),
+            daily_nutrition: self.daily_nutrition(),
+        }
+    }
+    /// The marginal productivity of the agent for every good, given their current stock.
+    fn productivity_vector(&self) -> HashMap<Good, Productivity> {
+        Good::iter()
+            .map(|good| (good, self.productivity(&good)))
+            .collect()
+    }
+    /// Returns the goods the agent can currently produce, given its stock.
+    ///
+    /// Consumer goods are producible whenever the agent has non-zero productivity. Capital goods
+    /// additionally require enough materials in stock to complete production, so that a build
+    /// is not abandoned for lack of materials.
+    fn producible_goods(&self) -> Vec<Good> {
+        Good::iter()
+            .filter(|good| self.productivity(good) != Productivity::None)
+            .filter(|good| {
+                good.is_consumer()
+                    || good.can_begin_build_with(
+                        self.stock(),
+                        &[],
+                        self.build_time(good),
+                        self.config(),
+                    )
+            })
+            .collect()
+    }
+    /// Returns the number of timesteps the agent takes to complete production of a good,
+    /// or None if the good is produced in a single timestep.
+    fn build_time(&self, good: &Good) -> Option<UInt> {
+        good.multiple_timesteps_to_complete_with(&self.config().build_times)
     }
     // fn productivity(&self, good: Good) -> (UInt, bool);
     /// The agent's choice of action in the next time step.
     fn choose_action(&mut self) -> Action;
     /// The agent's choice of action in the next time step.
     fn choose_action_with_model(&mut self, model: &Model) -> Action;
+    /// The number of nutritional units the agent requires per time step.
+    fn daily_nutrition(&self) -> UInt {
+        self.core().daily_nutrition
+    }
+    /// The stock held by the agent when it most recently failed to meet its nutritional
+    /// requirement, before `on_death` was called.
+    fn death_snapshot(&self) -> Option<&Stock> {
+        self.core().death_snapshot.as_ref()
+    }
+    fn death_snapshot_mut(&mut self) -> &mut Option<Stock> {
+        &mut self.core_mut().death_snapshot
+    }
     /// Consume nutritional units for one time step and return false if insufficient were unavailable.
     fn consume(&mut self, nutritional_units: UInt) -> bool {
-        let consumables = self.stock().next_consumables();
-        if consumables.is_empty() {
+        self.consume_detailed(nutritional_units).is_sufficient
+    }
+    /// Consume nutritional units for one time step, returning the units consumed and whether
+    /// they were sufficient.
+    fn consume_detailed(&mut self, nutritional_units: UInt) -> ConsumeResult {
+        let config = self.config().clone();
+        self.consume_detailed_with(nutritional_units, &config.nutrition)
+    }
+    /// Consume nutritional units for one time step, meeting the given category requirements,
+    /// and return false if insufficient were available.
+    fn consume_with(
+        &mut self,
+        nutritional_units: UInt,
+        requirement: &NutritionRequirement,
+    ) -> bool {
+        self.consume_detailed_with(nutritional_units, requirement)
+            .is_sufficient
+    }
+    /// Consume nutritional units for one time step, meeting the given category requirements,
+    /// and return the units consumed and whether they were sufficient.
+    fn consume_detailed_with(
+        &mut self,
+        nutritional_units: UInt,
+        requirement: &NutritionRequirement,
+    ) -> ConsumeResult {
+        if self.stock().next_consumables().is_empty() {
             // println!("DEBUG: consume - no consumables available");
-            return false;
-        }
-
-        let mut outstanding_nutritional_units = nutritional_units;
-        let mut stock_change: Vec<_> = vec![];
-        for (good, qty) in consumables {
-            if *qty > outstanding_nutritional_units {
-                stock_change.push((good.clone(), outstanding_nutritional_units));
-                outstanding_nutritional_units = 0;
-                break;
-            } else {
-                stock_change.push((good.clone(), *qty));
-                outstanding_nutritional_units -= *qty;
-            }
+            return ConsumeResult::default();
         }
-        // Update stock
-        for (good, qty) in stock_change {
-            self.stock_mut().remove(&good, qty);
-        }
-        // Returns false if the agent dies from lack of nutrients
-        if outstanding_nutritional_units > 0 {
-            return false;
+
+        let config = self.config().clone();
+        let (spoilage, tie_break) = (&config.spoilage, config.consume_tie_break);
+        let mut result = ConsumeResult {
+            consumed: vec![],
+            is_sufficient: true,
+        };
+        let mut outstanding_nutritional_units = nutritional_units as f32;
+        // Meet each category requirement first, as these units also count towards the total.
+        for category_requirement in &requirement.categories {
+            let (provided, units) = self.stock_mut().consume_nutrition_units_with(
+                category_requirement.quantity as f32,
+                |good| {
+                    good.nutrition_categories()
+                        .contains(&category_requirement.category)
+                },
+                spoilage,
+                tie_break,
+            );
+            if !stock::is_sufficient(provided, category_requirement.quantity as f32) {
+                result.is_sufficient = false;
+            }
+            result.consumed.extend(units);
+            outstanding_nutritional_units = f32::max(0.0, outstanding_nutritional_units - provided);
         }
-        true
+        let (provided, units) = self.stock_mut().consume_nutrition_units_with(
+            outstanding_nutritional_units,
+            |_| true,
+            spoilage,
+            tie_break,
+        );
+        result.consumed.extend(units);
+        // The agent dies from lack of nutrients if insufficient are provided.
+        result.is_sufficient &= stock::is_sufficient(provided, outstanding_nutritional_units);
+        result
     }
 
     /// Get the complete history of agent actions.
@@ -79,70 +293,374 @@ pub trait Agent {
     fn stock_history_mut(&mut self) -> &mut Vec<Stock>;
     /// Get the reward history.
     fn reward_history_mut(&mut self) -> &mut Vec<Reward>;
-    /// Return true if the agent is still alive.
+    /// The number of units of each good the agent has ever produced.
+    fn production_totals(&self) -> &HashMap<Good, UInt> {
+        &self.core().production_totals
+    }
+    fn production_totals_mut(&mut self) -> &mut HashMap<Good, UInt> {
+        &mut self.core_mut().production_totals
+    }
+    /// The goods for which the agent has already received the first-production bonus.
+    fn produced_goods(&self) -> &HashSet<Good> {
+        &self.core().produced_goods
+    }
+    fn produced_goods_mut(&mut self) -> &mut HashSet<Good> {
+        &mut self.core_mut().produced_goods
+    }
+    /// The number of consecutive days of leisure the agent has most recently taken.
+    fn leisure_streak(&self) -> UInt {
+        self.core().leisure_streak
+    }
+    fn leisure_streak_mut(&mut self) -> &mut UInt {
+        &mut self.core_mut().leisure_streak
+    }
+    /// Records the production of the given quantity of a good.
+    fn record_production(&mut self, good: Good, quantity: UInt) {
+        *self.production_totals_mut().entry(good).or_default() += quantity;
+    }
+    /// Returns the mean reward over the most recent `window` steps (or the whole history,
+    /// if shorter), or zero if the history is empty.
+    fn trailing_avg_reward(&self, window: usize) -> f32 {
+        let recent = self.reward_history().iter().rev().take(window);
+        let count = recent.len();
+        if count == 0 {
+            return 0.0;
+        }
+        recent.map(|reward| reward.val as f32).sum::<f32>() / count as f32
+    }
+    /// Returns the value of the stock at each step of the stock history, at the given fixed
+    /// prices (see `Stock::value`).
+    fn wealth_trajectory(&self, prices: &HashMap<Good, f32>) -> Vec<f32> {
+        self.stock_history()
+            .iter()
+            .map(|stock| stock.value(prices))
+            .collect()
+    }
+    /// Discard all but the most recent `window` entries of each history.
+    fn truncate_history(&mut self, window: usize) {
+        let excess = self.action_history().len().saturating_sub(window);
+        self.action_history_mut().drain(..excess);
+        let excess = self.stock_history().len().saturating_sub(window);
+        self.stock_history_mut().drain(..excess);
+        let excess = self.reward_history().len().saturating_sub(window);
+        self.reward_history_mut().drain(..excess);
+    }
+    /// Record the given stock in the stock history.
     fn update_stock_history(&mut self, stock: &Stock) {
         self.stock_history_mut().push(stock.clone());
     }
     fn update_reward_history(&mut self, action: Action, is_alive: bool) {
-        let reward = match (action, is_alive) {
-            (Action::ProduceGood(_), true) => Reward::new(0),
-            (Action::Leisure, true) => Reward::new(POSITIVE_REWARD),
+        let config = self.config().clone();
+        self.update_reward_history_with(action, is_alive, &config.reward)
+    }
+    /// Records the reward for the given action, including a one-time bonus the first time
+    /// each good is produced and a penalty for each partially complete good abandoned as the
+    /// stock steps forward. The reward for leisure halves on each day beyond the given
+    /// number of consecutive days of leisure.
+    fn update_reward_history_with(
+        &mut self,
+        action: Action,
+        is_alive: bool,
+        config: &RewardConfig,
+    ) {
+        *self.leisure_streak_mut() = match (action, is_alive) {
+            (Action::Leisure, true) => self.leisure_streak() + 1,
+            _ => 0,
+        };
+        let mut reward = match (action, is_alive) {
+            (Action::ProduceGood(_) | Action::ProduceGoodFor(..) | Action::Consume, true) => {
+                Reward::new(0)
+            }
+            (Action::Leisure, true) => {
+                let excess_days = config
+                    .leisure_decay_after
+                    .map_or(0, |days| self.leisure_streak().saturating_sub(days));
+                Reward::new(POSITIVE_REWARD.checked_shr(excess_days).unwrap_or(0))
+            }
             (_, false) => Reward::new(NEGATIVE_REWARD),
         };
+        if let (Action::ProduceGood(good) | Action::ProduceGoodFor(good, _), true) =
+            (action, is_alive)
+        {
+            // A multi-step good is produced only once its final step is complete.
+            if self.production_totals().contains_key(&good)
+                && self.produced_goods_mut().insert(good)
+            {
+                reward.val += config.first_production_bonus;
+            }
+        }
+        // The stock of an agent that dies is lost, rather than abandoned.
+        if is_alive {
+            let grace = self.config().partial_abandon_grace;
+            let abandoned = self.stock().abandoned_partials_with(action, grace).len() as Int;
+            reward.val += abandoned * config.abandon_penalty;
+        }
         self.reward_history_mut().push(reward);
     }
-    fn is_alive(&self) -> bool;
-    fn set_liveness(&mut self, value: bool);
+    /// Return true if the agent is still alive.
+    fn is_alive(&self) -> bool {
+        self.core().is_alive
+    }
+    fn set_liveness(&mut self, value: bool) {
+        self.core_mut().is_alive = value;
+    }
     /// Execture the given action.
     // fn act(&mut self, action: Action);
     fn act(&mut self, action: Action) {
+        self.act_with_resources(
+            action,
+            &mut self.resources().clone(),
+            &mut SharedResources::default(),
+        )
+    }
+    /// Execute the given action, harvesting from any renewable resource for the good produced
+    /// and claiming any shared goods that production relies on.
+    fn act_with_resources(
+        &mut self,
+        action: Action,
+        resources: &mut Resources,
+        shared: &mut SharedResources,
+    ) {
+        // Productivity reflects the current depletion of the resources.
+        self.set_resources(resources.clone());
         match action {
+            // Each day of a batch is produced in turn, without consumption in between
+            // (see `step_forward_with_resources`).
+            Action::ProduceGoodFor(good, days) => {
+                for _ in 0..days {
+                    self.act_with_resources(Action::ProduceGood(good), resources, shared);
+                }
+            }
             Action::ProduceGood(good) => {
-                let productivity = self.productivity(&good);
+                let productivity = self.productivity_with_shared(&good, shared);
+                // Claim the shared goods used in production for the rest of the time step.
+                for tool in self.shared_tools_used(&good, shared) {
+                    shared.claim(&tool);
+                }
+                // The yield of immediate production varies at random, if configured.
+                let noise = self.config().agent.production_noise;
+                let productivity = productivity.with_noise(noise, self.rng());
                 match productivity {
-                    Productivity::Immediate(qty) => self.acquire(GoodsUnit::new(&good), qty),
+                    Productivity::Immediate(qty) => {
+                        // The yield (already reduced for depletion) is taken from the resource.
+                        let qty = match resources.get_mut(&good) {
+                            Some(resource) => resource.take(qty),
+                            None => qty,
+                        };
+                        self.set_resources(resources.clone());
+                        let jitter = self.config().agent.lifetime_jitter;
+                        if self.acquire_produced(&good, qty, jitter).is_err() {
+                            return; // Nothing produced.
+                        }
+                        self.record_production(good, qty);
+                    }
                     Productivity::Delayed(_) => {
-                        if let Some(mut partial_good) = self.get_partial(good) {
-                            // If a partial good already exists, do the next step of production.
-                            partial_good.increment_production();
-                        } else {
-                            // Otherwise create a new partial good.
-                            self.acquire_partial(PartialGoodsUnit::new(&good).expect(
-                                "Delayed productivity implies multiple timesteps to produce.",
-                            ))
+                        // If no partial good already exists, create a new one.
+                        if self.get_partial(good).is_none() {
+                            let build_time = self.build_time(&good);
+                            let created = PartialGoodsUnit::new_with_build_time(&good, build_time)
+                                .and_then(|partial| self.acquire_partial(partial));
+                            if created.is_err() {
+                                return; // Production could not begin.
+                            }
+                        }
+                        // Do the next step of production.
+                        self.stock_mut().progress_partial(&good);
+                        // The partial good is removed from the stock on completion.
+                        if self.get_partial(good).is_none() {
+                            self.record_production(good, 1);
                         }
                     }
-                    Productivity::None => {} // Wasted action.
+                    Productivity::None => return, // Wasted action.
                 }
+                // Use up any materials required for this step of production.
+                let config = self.config().clone();
+                self.stock_mut()
+                    .consume_material_inputs_with(&good, &config);
             }
             Action::Leisure => (),
+            // The daily requirement is consumed when stepping forward.
+            Action::Consume => (),
         }
     }
     /// Step the agent forward by one time step.
-    fn step_forward(&mut self, action: Option<Action>) {
+    /// Returns false if the agent did not meet its nutritional requirement.
+    fn step_forward(&mut self, action: Option<Action>) -> bool {
+        self.step_forward_with_resources(
+            action,
+            &mut self.resources().clone(),
+            &mut SharedResources::default(),
+        )
+    }
+    /// Step the agent forward by one time step, harvesting from the given renewable resources
+    /// and using the given shared goods. Returns false if the agent did not meet its
+    /// nutritional requirement.
+    fn step_forward_with_resources(
+        &mut self,
+        action: Option<Action>,
+        resources: &mut Resources,
+        shared: &mut SharedResources,
+    ) -> bool {
         // Select action if not given.
         let action = match action {
             Some(a) => a,
             None => self.choose_action(),
         };
+        // Step through a batch one day at a time, stopping if the agent starves. Like any
+        // other action, the batch is recorded as a single step: with the stock on its last
+        // day and the total reward of its days.
+        if let Action::ProduceGoodFor(good, days) = action {
+            let (stocks, rewards) = (self.stock_history().len(), self.reward_history().len());
+            let is_alive = (0..days).all(|_| {
+                self.step_forward_with_resources(Some(Action::ProduceGood(good)), resources, shared)
+            });
+            let last_stock = self.stock_history_mut().pop();
+            self.stock_history_mut().truncate(stocks);
+            self.stock_history_mut().extend(last_stock);
+            let total = self
+                .reward_history_mut()
+                .drain(rewards..)
+                .map(|r| r.val)
+                .sum();
+            self.reward_history_mut().push(Reward::new(total));
+            return is_alive;
+        }
+        // Capital goods are used only if the stock before production suffices to produce.
+        let stock_before = self.stock().clone();
         // Perform action, which updates the agent's stock
-        self.act(action);
+        self.act_with_resources(action, resources, shared);
 
         // Consume stock, which updates whether the agent is alive
-        // TODO: make required nutritional_units per time unit configurable.
-        let is_alive = self.consume(1);
-
-        // TODO: removed set_liveness for now, can be made configurable later.
-        // self.set_liveness(is_alive);
+        let is_alive = match (self.auto_consume(), action) {
+            (true, _) | (false, Action::Consume) => {
+                self.core_mut().days_without_eating = 0;
+                self.consume(self.daily_nutrition())
+            }
+            // Without automatic consumption, the agent is only fed by choosing to consume,
+            // and starves once it has gone hungry for longer than the configured limit.
+            (false, _) => {
+                self.core_mut().days_without_eating += 1;
+                self.core().days_without_eating <= self.config().hunger_limit
+            }
+        };
 
         // Degrade the agent's stock.
         self.update_stock_history(&self.stock().clone());
         self.update_reward_history(action, is_alive);
 
         // Update the stock
+        let config = self.config().clone();
+        let build_time = action.good().and_then(|good| self.build_time(&good));
         match is_alive {
-            true => self.set_stock(self.stock().step_forward(action)),
-            false => self.set_stock(Stock::default()),
+            true => self.set_stock(self.stock().step_forward_with_usage(
+                action,
+                |good| stock_before.is_used_with(good, action, build_time, &config),
+                config.partial_abandon_grace,
+            )),
+            false => {
+                *self.death_snapshot_mut() = Some(self.stock().clone());
+                self.core_mut().days_without_eating = 0;
+                // A simulation can only stop once all agents are dead if death is permanent.
+                if config.agent.permanent_death || config.stop_when_all_dead {
+                    self.set_liveness(false);
+                }
+                self.on_death(action)
+            }
+        }
+        is_alive
+    }
+    /// Returns the risk of starvation in [0, 1], based on the number of days the agent's
+    /// stock can sustain it relative to the configured horizon.
+    fn starvation_risk(&self) -> f32 {
+        let survival_days = self.stock().survival_days(self.daily_nutrition());
+        starvation_risk(survival_days, self.config().agent.starvation_horizon)
+    }
+    /// Returns true if the daily requirement is consumed automatically at every time step,
+    /// rather than only when the agent chooses `Action::Consume`.
+    fn auto_consume(&self) -> bool {
+        self.config().auto_consume
+    }
+    /// Called when the agent fails to meet its nutritional requirement after taking the
+    /// given action. By default, the agent respawns with a fresh stock: empty but for the
+    /// configured partial endowment, as at the start of the simulation.
+    fn on_death(&mut self, _action: Action) {
+        self.set_stock(Stock::default());
+        for partial in self.config().partial_endowment.clone() {
+            self.acquire_partial(partial)
+                .expect("partial endowment should be valid");
+        }
+    }
+}
+
+/// Returns the risk of starvation given the number of days of survival and the horizon.
+fn starvation_risk(survival_days: UInt, horizon: UInt) -> f32 {
+    if horizon == 0 {
+        return 0.0;
+    }
+    1.0 - f32::min(1.0, survival_days as f32 / horizon as f32)
+}
+
+/// The units consumed by an agent in a time step, and whether they met its nutritional
+/// requirement.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsumeResult {
+    /// The quantity of each unit consumed, in the order in which they were consumed.
+    pub consumed: Vec<(GoodsUnit, UInt)>,
+    pub is_sufficient: bool,
+}
+
+/// The state of an agent relevant to its decisions, without its histories, from which a
+/// scratch agent can be constructed for planning and rollouts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecisionState {
+    pub stock: Stock,
+    pub daily_nutrition: UInt,
+}
+
+/// The state common to every kind of agent, besides its stock and histories. Each agent
+/// embeds one, through which the `Agent` trait provides its accessors.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentCore {
+    pub is_alive: bool,
+    pub production_totals: HashMap<Good, UInt>,
+    pub produced_goods: HashSet<Good>,
+    pub leisure_streak: UInt,
+    pub daily_nutrition: UInt,
+    /// The number of consecutive days on which the agent has not chosen to consume (when
+    /// consumption is not automatic).
+    #[serde(default)]
+    pub days_without_eating: UInt,
+    pub death_snapshot: Option<Stock>,
+    #[serde(skip, default = "StdRng::from_os_rng")]
+    pub rng: StdRng,
+    /// The config under which the agent acts. It is not serialized, so must be set again
+    /// after resuming (see `Simulation::resume`).
+    #[serde(skip)]
+    pub config: SharedConfig,
+    /// The season in which the agent currently acts, set by its simulation each time step.
+    #[serde(default)]
+    pub season: Option<Season>,
+    /// The renewable resources available to the agent, as of its most recent time step.
+    #[serde(default)]
+    pub resources: Resources,
+}
+
+impl AgentCore {
+    /// Returns the state of a living agent that requires the given number of nutritional
+    /// units per time step, acting under the default config.
+    pub fn new(daily_nutrition: UInt) -> Self {
+        AgentCore {
+            is_alive: true,
+            production_totals: HashMap::new(),
+            produced_goods: HashSet::new(),
+            leisure_streak: 0,
+            daily_nutrition,
+            days_without_eating: 0,
+            death_snapshot: None,
+            rng: StdRng::from_os_rng(),
+            config: SharedConfig::default(),
+            season: None,
+            resources: Resources::new(),
         }
     }
 }
@@ -151,21 +669,28 @@ pub trait Agent {
 pub struct CrusoeAgent {
     pub id: u64,
     pub stock: Stock,
-    pub is_alive: bool,
     pub action_history: Vec<Action>,
     stock_history: Vec<Stock>,
     pub reward_history: Vec<Reward>,
+    #[serde(flatten)]
+    pub core: AgentCore,
 }
 
 impl CrusoeAgent {
+    /// Returns an agent that requires one nutritional unit per time step.
     pub fn new(id: u64) -> Self {
+        Self::new_with_nutrition(id, 1)
+    }
+
+    /// Returns an agent that requires the given number of nutritional units per time step.
+    pub fn new_with_nutrition(id: u64, daily_nutrition: UInt) -> Self {
         CrusoeAgent {
             id,
             stock: Stock::default(),
-            is_alive: true,
             action_history: vec![],
             stock_history: vec![],
             reward_history: vec![],
+            core: AgentCore::new(daily_nutrition),
         }
     }
 }
@@ -179,6 +704,14 @@ impl Agent for CrusoeAgent {
         "Crusoe"
     }
 
+    fn core(&self) -> &AgentCore {
+        &self.core
+    }
+
+    fn core_mut(&mut self) -> &mut AgentCore {
+        &mut self.core
+    }
+
     fn stock(&self) -> &Stock {
         &self.stock
     }
@@ -206,9 +739,8 @@ impl Agent for CrusoeAgent {
     // TODO: consider moving teh action_history update into act method, so
     // self can be immutable here.
     fn choose_action(&mut self) -> Action {
-        // let action = Action::random_weighted(&mut StdRng::from_os_rng(), 0.5);
-        let action = Action::random(&mut StdRng::from_os_rng());
-        // let action = Action::random(&mut StdRng::seed_from_u64(self.id));
+        // let action = Action::random_weighted(&mut self.rng, 0.5);
+        let action = Action::random_with(&mut self.core.rng, &self.core.config);
         self.action_history.push(action);
         action
     }
@@ -216,8 +748,8 @@ impl Agent for CrusoeAgent {
     // TODO: consider moving teh action_history update into act method, so
     // self can be immutable here.
     fn choose_action_with_model(&mut self, model: &Model) -> Action {
-        let action =
-            model.sample_action_by_id(0, &self.stock.representation(), &mut StdRng::from_os_rng());
+        let state = self.stock.representation_with(&self.core.config);
+        let action = model.sample_action_by_id(self.id as u32, &state, &mut self.core.rng);
         self.action_history.push(action.into());
         action.into()
     }
@@ -241,20 +773,13 @@ impl Agent for CrusoeAgent {
         &mut self.reward_history
     }
 
-    fn is_alive(&self) -> bool {
-        self.is_alive
-    }
-
-    fn set_liveness(&mut self, value: bool) {
-        self.is_alive = value;
-    }
-
-    fn acquire(&mut self, goods_unit: GoodsUnit, quantity: UInt) {
-        self.stock.add(goods_unit, quantity);
+    fn acquire(&mut self, goods_unit: GoodsUnit, quantity: UInt) -> Result<(), StockError> {
+        self.stock.add(goods_unit, quantity)
     }
 
-    fn acquire_partial(&mut self, partial_goods_unit: PartialGoodsUnit) {
-        self.stock.add_partial(partial_goods_unit);
+    fn acquire_partial(&mut self, partial_goods_unit: PartialGoodsUnit) -> Result<(), StockError> {
+        let limit = self.core.config.agent.max_concurrent_builds;
+        self.stock.add_partial_with_limit(partial_goods_unit, limit)
     }
 
     fn get_partial(&self, good: Good) -> Option<PartialGoodsUnit> {
@@ -271,6 +796,57 @@ pub enum AgentType {
 }
 
 impl AgentType {
+    /// Returns the agents described by the given specs, with ids numbered from zero.
+    pub fn from_specs(specs: &[AgentSpec]) -> Vec<AgentType> {
+        let mut agents = Vec::new();
+        for spec in specs {
+            for _ in 0..spec.count {
+                let id = agents.len() as u64;
+                let agent = match spec.kind {
+                    AgentKind::Rl => {
+                        AgentType::Rl(LearningAgent::new_with_nutrition(id, spec.nutrition))
+                    }
+                    AgentKind::Crusoe => {
+                        AgentType::Crusoe(CrusoeAgent::new_with_nutrition(id, spec.nutrition))
+                    }
+                    AgentKind::Rational => {
+                        AgentType::Rational(RationalAgent::new(id, spec.nutrition))
+                    }
+                };
+                agents.push(agent);
+            }
+        }
+        agents
+    }
+
+    /// Steps the agent forward by one time step, harvesting from the given renewable resources
+    /// and using the given shared goods, and records the resulting state, action and reward
+    /// in the given history. Returns false if the agent did not meet its nutritional
+    /// requirement.
+    ///
+    /// If no action is given, the agent chooses one. A given action is assumed to have been
+    /// recorded in the agent's action history when it was chosen (e.g. by
+    /// `choose_action_with_model`).
+    pub fn step_and_record(
+        &mut self,
+        action: Option<Action>,
+        hist: &mut AgentHistory,
+        resources: &mut Resources,
+        shared: &mut SharedResources,
+    ) -> bool {
+        let action = match action {
+            Some(action) => action,
+            None => self.choose_action(),
+        };
+        let is_alive = self.step_forward_with_resources(Some(action), resources, shared);
+        hist.push(SAR::new(
+            self.stock().clone(),
+            action.into(),
+            *Agent::reward_history(self).last().unwrap(),
+        ));
+        is_alive
+    }
+
     pub fn action_history(&self) -> Vec<ActionFlattened> {
         match self {
             AgentType::Crusoe(agent) => {
@@ -290,74 +866,828 @@ impl AgentType {
             AgentType::Rational(agent) => agent.reward_history().to_vec(),
         }
     }
+
+    /// Returns the number of times each action was taken over the action history.
+    pub fn action_distribution(&self) -> HashMap<ActionFlattened, UInt> {
+        let mut counts = HashMap::new();
+        for action in self.action_history() {
+            *counts.entry(action).or_default() += 1;
+        }
+        counts
+    }
+
+    /// Returns the fraction of the action history in which each action was taken.
+    pub fn action_frequencies(&self) -> HashMap<ActionFlattened, f32> {
+        let distribution = self.action_distribution();
+        let total: UInt = distribution.values().sum();
+        distribution
+            .into_iter()
+            .map(|(action, count)| (action, count as f32 / total as f32))
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*; // Import the functions from the parent module
+    use crate::config::{AgentConfig, CategoryRequirement, Config, ResourceConfig};
+    use crate::goods::NutritionCategory;
+    use crate::resource::ResourceState;
 
     #[test]
     fn test_consume() {
         let mut agent = CrusoeAgent::new(1);
-        agent.stock.add(
-            GoodsUnit {
-                good: Good::Berries,
-                remaining_lifetime: 10,
-            },
-            5,
-        );
+        agent
+            .stock
+            .add(
+                GoodsUnit {
+                    good: Good::Berries,
+                    remaining_lifetime: 10,
+                },
+                5,
+            )
+            .unwrap();
         agent.consume(3);
         // Expected stock after consumption is 2 units of berries
         // (three units were consumed) with remaining lifetime 10.
         let mut expected = Stock::default();
-        expected.add(
-            GoodsUnit {
-                good: Good::Berries,
-                remaining_lifetime: 10,
-            },
-            2,
-        );
+        expected
+            .add(
+                GoodsUnit {
+                    good: Good::Berries,
+                    remaining_lifetime: 10,
+                },
+                2,
+            )
+            .unwrap();
         assert_eq!(agent.stock, expected);
         agent.consume(2);
         // Expected stock after consumption of the remaining 2 units
         // of berries is empty.
-        assert!(agent.stock.stock.is_empty());
+        assert!(agent.stock.is_empty());
+    }
+
+    #[test]
+    fn test_consume_detailed() {
+        let mut agent = CrusoeAgent::new(1);
+        agent.acquire(GoodsUnit::new(&Good::Berries), 2).unwrap();
+        agent.acquire(GoodsUnit::new(&Good::Fish), 1).unwrap();
+        let result = agent.consume_detailed(3);
+        assert!(result.is_sufficient);
+        // The fish expires sooner, so is consumed first.
+        assert_eq!(
+            result.consumed,
+            vec![
+                (GoodsUnit::new(&Good::Fish), 1),
+                (GoodsUnit::new(&Good::Berries), 2)
+            ]
+        );
+        assert!(agent.stock.is_empty());
+
+        // Nothing is consumed from an empty stock.
+        assert_eq!(agent.consume_detailed(1), ConsumeResult::default());
+    }
+
+    #[test]
+    fn test_boat_build_material_depletion() {
+        let mut agent = CrusoeAgent::new(1);
+        agent.acquire(GoodsUnit::new(&Good::Timber), 10).unwrap();
+        agent.acquire(GoodsUnit::new(&Good::Berries), 10).unwrap();
+        let action = Action::ProduceGood(Good::Boat);
+        let build_time = Good::Boat.multiple_timesteps_to_complete().unwrap();
+
+        // One unit of timber is used up at every step of the build.
+        for step in 1..build_time {
+            agent.step_forward(Some(action));
+            assert_eq!(agent.stock.count_units(&Good::Timber), 10 - step);
+            assert_eq!(
+                agent
+                    .stock
+                    .get_partial(Good::Boat)
+                    .unwrap()
+                    .time_to_completion,
+                build_time - step
+            );
+            assert!(!agent.stock.contains(&Good::Boat));
+        }
+        // The final step completes the boat, having used up all of the timber.
+        agent.step_forward(Some(action));
+        assert_eq!(agent.stock.count_units(&Good::Timber), 0);
+        assert_eq!(agent.stock.get_partial(Good::Boat), None);
+        assert_eq!(agent.stock.count_units(&Good::Boat), 1);
+
+        // With no timber remaining, another boat cannot be started.
+        agent.step_forward(Some(action));
+        assert_eq!(agent.stock.get_partial(Good::Boat), None);
+    }
+
+    #[test]
+    fn test_failed_build_does_not_degrade_tools() {
+        let mut agent = CrusoeAgent::new(1);
+        let tools = [Good::Axe, Good::Basket, Good::Spear];
+        for tool in tools {
+            agent.acquire(GoodsUnit::new(&tool), 1).unwrap();
+        }
+        agent.acquire(GoodsUnit::new(&Good::Timber), 2).unwrap();
+        agent.acquire(GoodsUnit::new(&Good::Berries), 10).unwrap();
+
+        // Too little timber to build a smoker, so production does not take place.
+        agent.step_forward(Some(Action::ProduceGood(Good::Smoker)));
+        assert_eq!(agent.stock.get_partial(Good::Smoker), None);
+        for tool in tools {
+            assert_eq!(agent.stock.oldest_unit(&tool), Some(GoodsUnit::new(&tool)));
+        }
+    }
+
+    #[test]
+    fn test_production_totals() {
+        let mut agent = CrusoeAgent::new(1);
+        agent.acquire(GoodsUnit::new(&Good::Timber), 10).unwrap();
+        agent.acquire(GoodsUnit::new(&Good::Berries), 10).unwrap();
+
+        // Berries are produced and consumed, but the totals count every unit produced.
+        for _ in 0..3 {
+            agent.step_forward(Some(Action::ProduceGood(Good::Berries)));
+        }
+        let berries = agent.production_totals()[&Good::Berries];
+        assert_eq!(berries, 3 * 4);
+        assert!(agent.stock().count_units(&Good::Berries) < 10 + berries);
+
+        // A partial good is only counted once it is complete.
+        let build_time = Good::Boat.multiple_timesteps_to_complete().unwrap();
+        for _ in 1..build_time {
+            agent.step_forward(Some(Action::ProduceGood(Good::Boat)));
+        }
+        assert_eq!(agent.production_totals().get(&Good::Boat), None);
+        agent.step_forward(Some(Action::ProduceGood(Good::Boat)));
+        assert_eq!(agent.production_totals()[&Good::Boat], 1);
+        assert_eq!(agent.production_totals()[&Good::Berries], berries);
+    }
+
+    #[test]
+    fn test_producible_goods_agree_with_productivity() {
+        // Including for an agent whose config overrides the Boat build time.
+        let overridden = SharedConfig::new(Config {
+            build_times: HashMap::from([(Good::Boat, 4)]),
+            ..Config::default()
+        });
+        for config in [SharedConfig::default(), overridden] {
+            for good in [Good::Smoker, Good::Boat] {
+                for timber in 0..=12 {
+                    for berries in [0, 6] {
+                        let mut agent = CrusoeAgent::new(1);
+                        agent.set_config(config.clone());
+                        if timber > 0 {
+                            agent
+                                .acquire(GoodsUnit::new(&Good::Timber), timber)
+                                .unwrap();
+                        }
+                        if berries > 0 {
+                            agent
+                                .acquire(GoodsUnit::new(&Good::Berries), berries)
+                                .unwrap();
+                        }
+                        let can_begin = good.can_begin_build_with(
+                            agent.stock(),
+                            &[],
+                            agent.build_time(&good),
+                            &config,
+                        );
+                        assert_eq!(agent.producible_goods().contains(&good), can_begin);
+                        assert_eq!(agent.productivity(&good) != Productivity::None, can_begin);
+                        // The overridden build time needs less timber than the default.
+                        if good == Good::Boat && timber == 4 {
+                            assert_eq!(can_begin, agent.build_time(&good) == Some(4));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_bottleneck() {
+        let mut agent = CrusoeAgent::new(1);
+        assert_eq!(agent.bottleneck(&Good::Boat), Some(Good::Axe));
+        assert_eq!(agent.bottleneck(&Good::Berries), None);
+        agent.acquire(GoodsUnit::new(&Good::Axe), 1).unwrap();
+        assert_eq!(agent.bottleneck(&Good::Boat), Some(Good::Timber));
+        assert_eq!(agent.bottleneck(&Good::Timber), None);
+        // Timber is needed for every timestep of building the boat.
+        agent.acquire(GoodsUnit::new(&Good::Timber), 1).unwrap();
+        assert_eq!(agent.bottleneck(&Good::Boat), Some(Good::Timber));
+        let build_time = agent.build_time(&Good::Boat).unwrap();
+        agent
+            .acquire(GoodsUnit::new(&Good::Timber), build_time - 1)
+            .unwrap();
+        assert_eq!(agent.bottleneck(&Good::Boat), None);
+    }
+
+    #[test]
+    fn test_leisure_decay() {
+        let mut agent = CrusoeAgent::new(1);
+        let config = RewardConfig {
+            leisure_decay_after: Some(2),
+            ..Default::default()
+        };
+        let mut step = |action: Action| {
+            agent.update_reward_history_with(action, true, &config);
+            agent.reward_history().last().unwrap().val
+        };
+
+        // The leisure reward declines beyond two consecutive days of leisure.
+        let rewards: Vec<_> = (0..4).map(|_| step(Action::Leisure)).collect();
+        assert_eq!(
+            rewards,
+            vec![POSITIVE_REWARD, POSITIVE_REWARD, POSITIVE_REWARD / 2, 0]
+        );
+
+        // Any production resets the count of consecutive days of leisure.
+        assert_eq!(step(Action::ProduceGood(Good::Berries)), 0);
+        assert_eq!(step(Action::Leisure), POSITIVE_REWARD);
+        assert_eq!(agent.leisure_streak(), 1);
+    }
+
+    #[test]
+    fn test_abandon_penalty() {
+        let mut agent = CrusoeAgent::new(1);
+        let build_time = Good::Boat.multiple_timesteps_to_complete().unwrap();
+        agent
+            .acquire_partial(PartialGoodsUnit {
+                good: Good::Boat,
+                time_to_completion: build_time / 2,
+                build_time,
+            })
+            .unwrap();
+        let config = RewardConfig {
+            abandon_penalty: -50,
+            ..Default::default()
+        };
+        let mut step = |action: Action| {
+            agent.update_reward_history_with(action, true, &config);
+            agent.set_stock(agent.stock().step_forward(action));
+            agent.reward_history().last().unwrap().val
+        };
+
+        // The half-built boat loses a day of progress on each day of leisure, until it is
+        // abandoned on the day after no progress remains.
+        let rewards: Vec<_> = (0..=build_time / 2)
+            .map(|_| step(Action::Leisure))
+            .collect();
+        let mut expected = vec![POSITIVE_REWARD; (build_time / 2 + 1) as usize];
+        *expected.last_mut().unwrap() += config.abandon_penalty;
+        assert_eq!(rewards, expected);
+        assert_eq!(step(Action::Leisure), POSITIVE_REWARD);
+        assert_eq!(agent.get_partial(Good::Boat), None);
+    }
+
+    #[test]
+    fn test_first_production_bonus() {
+        let mut agent = CrusoeAgent::new(1);
+        agent.acquire(GoodsUnit::new(&Good::Timber), 10).unwrap();
+        let bonus = 50;
+        let step = |agent: &mut CrusoeAgent, action: Action| {
+            agent.act(action);
+            let config = RewardConfig {
+                first_production_bonus: bonus,
+                ..Default::default()
+            };
+            agent.update_reward_history_with(action, true, &config);
+            agent.reward_history().last().unwrap().val
+        };
+
+        // The bonus is received only for the first production of berries.
+        assert_eq!(step(&mut agent, Action::ProduceGood(Good::Berries)), bonus);
+        for _ in 0..3 {
+            assert_eq!(step(&mut agent, Action::ProduceGood(Good::Berries)), 0);
+        }
+        assert_eq!(step(&mut agent, Action::Leisure), POSITIVE_REWARD);
+
+        // A multi-step good receives the bonus once, on completion.
+        let build_time = Good::Boat.multiple_timesteps_to_complete().unwrap();
+        for _ in 1..build_time {
+            assert_eq!(step(&mut agent, Action::ProduceGood(Good::Boat)), 0);
+        }
+        assert_eq!(step(&mut agent, Action::ProduceGood(Good::Boat)), bonus);
+        assert_eq!(
+            agent.produced_goods(),
+            &HashSet::from([Good::Berries, Good::Boat])
+        );
     }
 
     #[test]
     fn test_consume_different_goods() {
         let mut agent = CrusoeAgent::new(1);
         // Add 2 units of berries and 1 unit of fish.
-        agent.acquire(GoodsUnit::new(&Good::Berries), 2);
-        agent.acquire(GoodsUnit::new(&Good::Fish), 1);
+        agent.acquire(GoodsUnit::new(&Good::Berries), 2).unwrap();
+        agent.acquire(GoodsUnit::new(&Good::Fish), 1).unwrap();
 
         // The agent can successfully consume 3 nutritional units.
         assert!(agent.consume(3));
-        assert!(agent.stock.stock.is_empty());
+        assert!(agent.stock.is_empty());
     }
 
     #[test]
-    fn test_step_forward() {
+    fn test_producible_goods() {
+        // From an empty stock, only consumer goods and capital goods without inputs are producible.
         let mut agent = CrusoeAgent::new(1);
-        agent.stock.add(
-            GoodsUnit {
-                good: Good::Berries,
-                remaining_lifetime: 10,
-            },
-            5,
+        assert_eq!(
+            agent.producible_goods(),
+            vec![
+                Good::Berries,
+                Good::Fish,
+                Good::Basket,
+                Good::Spear,
+                Good::Axe
+            ]
         );
+
+        // Building a Smoker requires timber for every step of production.
+        agent.acquire(GoodsUnit::new(&Good::Timber), 2).unwrap();
+        assert!(!agent.producible_goods().contains(&Good::Smoker));
+        agent.acquire(GoodsUnit::new(&Good::Timber), 1).unwrap();
+        assert!(agent.producible_goods().contains(&Good::Smoker));
+        assert!(!agent.producible_goods().contains(&Good::Boat));
+    }
+
+    #[test]
+    fn test_productivity_vector() {
+        let mut agent = CrusoeAgent::new(1);
+        agent.acquire(GoodsUnit::new(&Good::Spear), 1).unwrap();
+        let productivity = agent.productivity_vector();
+        assert_eq!(productivity.len(), Good::iter().count());
+        assert_eq!(productivity[&Good::Fish], Productivity::Immediate(10));
+        assert_eq!(productivity[&Good::Berries], Productivity::Immediate(4));
+        assert_eq!(productivity[&Good::Timber], Productivity::None);
+    }
+
+    #[test]
+    fn test_step_forward() {
+        let mut agent = CrusoeAgent::new(1);
+        agent
+            .stock
+            .add(
+                GoodsUnit {
+                    good: Good::Berries,
+                    remaining_lifetime: 10,
+                },
+                5,
+            )
+            .unwrap();
         agent.step_forward(Some(Action::Leisure));
         // Expected stock after one step forward is 4 units of berries
         // (one unit was consumed) with remaining lifetime 9.
         let mut expected = Stock::default();
-        expected.add(
-            GoodsUnit {
-                good: Good::Berries,
-                remaining_lifetime: 9,
+        expected
+            .add(
+                GoodsUnit {
+                    good: Good::Berries,
+                    remaining_lifetime: 9,
+                },
+                4,
+            )
+            .unwrap();
+        assert_eq!(agent.stock, expected);
+    }
+
+    #[test]
+    fn test_fuel() {
+        let config = SharedConfig::new(Config {
+            fuel: HashMap::from([(Good::Fish, 2)]),
+            ..Config::default()
+        });
+        // Without a Smoker, fish are not smoked and so need no fuel.
+        let mut agent = CrusoeAgent::new(1);
+        agent.set_config(config.clone());
+        agent.acquire(GoodsUnit::new(&Good::Timber), 1).unwrap();
+        assert_eq!(agent.productivity(&Good::Fish), Productivity::Immediate(2));
+        assert_eq!(agent.missing_input(&Good::Fish), None);
+        agent.act(Action::ProduceGood(Good::Fish));
+        assert_eq!(agent.stock().count_units(&Good::Fish), 2);
+        assert_eq!(agent.stock().count_units(&Good::Timber), 1);
+
+        // Fish are smoked over burning timber, so cannot be produced without enough timber,
+        // even with a Smoker.
+        let mut agent = CrusoeAgent::new(1);
+        agent.set_config(config);
+        agent.acquire(GoodsUnit::new(&Good::Smoker), 1).unwrap();
+        agent.acquire(GoodsUnit::new(&Good::Timber), 1).unwrap();
+        assert_eq!(agent.productivity(&Good::Fish), Productivity::None);
+        assert_eq!(agent.missing_input(&Good::Fish), Some(Good::Timber));
+        agent.act(Action::ProduceGood(Good::Fish));
+        assert_eq!(agent.stock().count_units(&Good::Fish), 0);
+        assert_eq!(agent.stock().count_units(&Good::Timber), 1);
+
+        // With enough timber, fish are produced and the fuel is burned.
+        agent.acquire(GoodsUnit::new(&Good::Timber), 1).unwrap();
+        agent.act(Action::ProduceGood(Good::Fish));
+        assert_eq!(agent.stock().count_units(&Good::Fish), 2);
+        assert_eq!(agent.stock().count_units(&Good::Timber), 0);
+    }
+
+    #[test]
+    fn test_transform_good() {
+        // Configure fish as a transform good, made by using up every unit of berries held.
+        let mut agent = CrusoeAgent::new(1);
+        agent.set_config(SharedConfig::new(Config {
+            transforms: HashMap::from([(Good::Fish, Good::Berries)]),
+            ..Config::default()
+        }));
+        agent.acquire(GoodsUnit::new(&Good::Berries), 5).unwrap();
+        assert_eq!(agent.productivity(&Good::Fish), Productivity::Immediate(5));
+        agent.act(Action::ProduceGood(Good::Fish));
+        assert_eq!(agent.stock().count_units(&Good::Berries), 0);
+        assert_eq!(agent.stock().count_units(&Good::Fish), 5);
+        // With no berries left, there is nothing to transform.
+        assert_eq!(agent.productivity(&Good::Fish), Productivity::None);
+    }
+
+    #[test]
+    fn test_consume_action() {
+        let manual = SharedConfig::new(Config {
+            auto_consume: false,
+            hunger_limit: 2,
+            ..Config::default()
+        });
+        // Without automatic consumption, an agent survives a day without consuming and
+        // keeps its food.
+        let mut agent = CrusoeAgent::new(1);
+        agent.set_config(manual.clone());
+        agent.acquire(GoodsUnit::new(&Good::Berries), 10).unwrap();
+        assert!(agent.step_forward(Some(Action::Leisure)));
+        assert_eq!(agent.stock().count_units(&Good::Berries), 10);
+
+        // An agent that never chooses to consume starves once past the hunger limit, despite
+        // having food.
+        assert!(agent.step_forward(Some(Action::Leisure)));
+        assert!(!agent.step_forward(Some(Action::Leisure)));
+        assert_eq!(agent.stock(), &Stock::default());
+
+        // Choosing to consume meets the requirement.
+        let mut agent = CrusoeAgent::new(1);
+        agent.set_config(manual);
+        agent.acquire(GoodsUnit::new(&Good::Berries), 10).unwrap();
+        assert!(agent.step_forward(Some(Action::Consume)));
+        assert_eq!(agent.stock().count_units(&Good::Berries), 9);
+    }
+
+    #[test]
+    fn test_trailing_avg_reward() {
+        let mut agent = CrusoeAgent::new(1);
+        assert_eq!(agent.trailing_avg_reward(3), 0.0);
+        agent.reward_history = [1, 2, 3, 4].into_iter().map(Reward::new).collect();
+        // Larger than the history length.
+        assert_eq!(agent.trailing_avg_reward(10), 2.5);
+        // Equal to the history length.
+        assert_eq!(agent.trailing_avg_reward(4), 2.5);
+        // Smaller than the history length, using the most recent rewards.
+        assert_eq!(agent.trailing_avg_reward(2), 3.5);
+        assert_eq!(agent.trailing_avg_reward(0), 0.0);
+    }
+
+    #[test]
+    fn test_wealth_trajectory() {
+        let mut agent = CrusoeAgent::new(1);
+        let prices = HashMap::from([(Good::Berries, 1.0), (Good::Spear, 5.0)]);
+        assert!(agent.wealth_trajectory(&prices).is_empty());
+        agent.stock_history = vec![
+            Stock::default(),
+            Stock::from_goods(&[(Good::Berries, 4)]),
+            Stock::from_goods(&[(Good::Berries, 2), (Good::Spear, 1), (Good::Fish, 3)]),
+        ];
+        let agent = AgentType::Crusoe(agent);
+        let expected: Vec<f32> = agent
+            .stock_history()
+            .iter()
+            .map(|stock| stock.value(&prices))
+            .collect();
+        // Goods without a price (here, fish) do not contribute.
+        assert_eq!(expected, vec![0.0, 4.0, 7.0]);
+        assert_eq!(agent.wealth_trajectory(&prices), expected);
+    }
+
+    #[test]
+    fn test_season_productivity() {
+        use crate::config::SeasonConfig;
+
+        let mut agent = CrusoeAgent::new(0);
+        agent.set_config(SharedConfig::new(Config {
+            seasons: Some(SeasonConfig {
+                period: 2,
+                multipliers: HashMap::from([(Season::Winter, HashMap::from([(Good::Fish, 0.5)]))]),
+            }),
+            ..Config::default()
+        }));
+        let base = agent.productivity(&Good::Fish);
+        assert_eq!(base, Productivity::Immediate(2));
+        agent.set_season(Some(Season::Summer));
+        assert_eq!(agent.productivity(&Good::Fish), base);
+        // Fish are half as plentiful in winter, for valuation as well as production.
+        agent.set_season(Some(Season::Winter));
+        assert_eq!(agent.productivity(&Good::Fish), base.scaled(0.5));
+        assert_eq!(
+            agent.productivity_vector()[&Good::Fish],
+            Productivity::Immediate(1)
+        );
+        assert_eq!(
+            agent.productivity(&Good::Berries),
+            Productivity::Immediate(4)
+        );
+    }
+
+    #[test]
+    fn test_build_time() {
+        let default_build_time = Good::Boat.multiple_timesteps_to_complete().unwrap();
+        let build_times = HashMap::from([(Good::Boat, default_build_time / 2)]);
+        assert_eq!(
+            Good::Boat.multiple_timesteps_to_complete_with(&build_times),
+            Some(default_build_time / 2)
+        );
+        assert_eq!(
+            Good::Berries.multiple_timesteps_to_complete_with(&build_times),
+            None
+        );
+
+        // An agent whose config halves the Boat build time.
+        let agent_with = |build_times: HashMap<Good, UInt>| {
+            let mut agent = CrusoeAgent::new(0);
+            agent.set_config(SharedConfig::new(Config {
+                build_times,
+                ..Config::default()
+            }));
+            agent.acquire(GoodsUnit::new(&Good::Berries), 30).unwrap();
+            agent
+        };
+        let mut agent = agent_with(build_times.clone());
+        assert_eq!(agent.build_time(&Good::Boat), Some(default_build_time / 2));
+
+        // Half the usual timber suffices to begin (and complete) the build.
+        agent
+            .acquire(GoodsUnit::new(&Good::Timber), default_build_time / 2)
+            .unwrap();
+        assert_eq!(
+            agent.productivity(&Good::Boat),
+            Productivity::Delayed(default_build_time / 2)
+        );
+        assert!(agent.producible_goods().contains(&Good::Boat));
+        let mut default_agent = agent_with(HashMap::new());
+        default_agent
+            .acquire(GoodsUnit::new(&Good::Timber), default_build_time / 2)
+            .unwrap();
+        assert_eq!(default_agent.productivity(&Good::Boat), Productivity::None);
+
+        // Count the steps taken to build a boat.
+        let steps_to_build = |mut agent: CrusoeAgent| {
+            agent
+                .acquire(GoodsUnit::new(&Good::Timber), default_build_time / 2)
+                .unwrap();
+            let mut steps = 0;
+            while !agent.stock().contains(&Good::Boat) {
+                agent.step_forward(Some(Action::ProduceGood(Good::Boat)));
+                steps += 1;
+            }
+            steps
+        };
+        assert_eq!(steps_to_build(default_agent), default_build_time);
+        assert_eq!(steps_to_build(agent), default_build_time / 2);
+    }
+
+    #[test]
+    fn test_action_distribution() {
+        let mut crusoe = CrusoeAgent::new(1);
+        crusoe.action_history = vec![
+            Action::ProduceGood(Good::Berries),
+            Action::Leisure,
+            Action::ProduceGood(Good::Berries),
+            Action::ProduceGood(Good::Berries),
+        ];
+        let agent = AgentType::Crusoe(crusoe);
+        let distribution = agent.action_distribution();
+        assert_eq!(distribution.len(), 2);
+        assert_eq!(distribution[&ActionFlattened::ProduceBerries], 3);
+        assert_eq!(distribution[&ActionFlattened::Leisure], 1);
+
+        let frequencies = agent.action_frequencies();
+        assert_eq!(frequencies[&ActionFlattened::ProduceBerries], 0.75);
+        assert_eq!(frequencies[&ActionFlattened::Leisure], 0.25);
+        assert!(
+            AgentType::Crusoe(CrusoeAgent::new(2))
+                .action_frequencies()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_step_and_record() {
+        let mut agent = AgentType::Crusoe(CrusoeAgent::new(1));
+        agent.acquire(GoodsUnit::new(&Good::Berries), 10).unwrap();
+        let mut hist = AgentHistory::new();
+        assert!(agent.step_and_record(
+            Some(Action::Leisure),
+            &mut hist,
+            &mut Resources::new(),
+            &mut SharedResources::default()
+        ));
+        assert_eq!(hist.len(), 1);
+        assert_eq!(hist.trajectory[0].action, ActionFlattened::Leisure);
+        assert_eq!(&hist.trajectory[0].state, agent.stock());
+
+        // If no action is given, the agent chooses one.
+        agent.step_and_record(
+            None,
+            &mut hist,
+            &mut Resources::new(),
+            &mut SharedResources::default(),
+        );
+        assert_eq!(hist.len(), 2);
+        assert_eq!(agent.action_history().len(), 1);
+    }
+
+    #[test]
+    fn test_batch_production() {
+        let new_agent = || {
+            let mut agent = CrusoeAgent::new(1);
+            *agent.rng() = StdRng::seed_from_u64(0);
+            agent.acquire(GoodsUnit::new(&Good::Fish), 6).unwrap();
+            agent
+        };
+        // A three-day batch of berries is equivalent to three single days of production.
+        let mut batched = new_agent();
+        assert!(batched.step_forward(Some(Action::ProduceGoodFor(Good::Berries, 3))));
+        let mut single = new_agent();
+        for _ in 0..3 {
+            assert!(single.step_forward(Some(Action::ProduceGood(Good::Berries))));
+        }
+        assert_eq!(batched.stock, single.stock);
+        // The batch is recorded as a single step, with the stock and total reward of its days.
+        assert_eq!(batched.stock_history(), &single.stock_history()[2..]);
+        let total: i32 = single.reward_history().iter().map(|r| r.val).sum();
+        assert_eq!(batched.reward_history(), &[Reward::new(total)]);
+        let mut hist = AgentHistory::new();
+        let mut agent = AgentType::Crusoe(new_agent());
+        let batch = Action::ProduceGoodFor(Good::Berries, 3);
+        agent.step_and_record(
+            Some(batch),
+            &mut hist,
+            &mut Resources::new(),
+            &mut SharedResources::default(),
+        );
+        assert_eq!(hist.len(), 1);
+        assert_eq!(hist.trajectory[0].action, ActionFlattened::from(batch));
+        assert_eq!(hist.trajectory[0].reward, Reward::new(total));
+
+        // The agent consumes every day of the batch, which ends if it starves.
+        let mut agent = CrusoeAgent::new(1);
+        assert!(!agent.step_forward(Some(Action::ProduceGoodFor(Good::Spear, 3))));
+        assert_eq!(agent.reward_history().len(), 1);
+    }
+
+    #[test]
+    fn test_acquire_produced_jitter() {
+        let mut agent = CrusoeAgent::new(1);
+        *agent.rng() = StdRng::seed_from_u64(7);
+        agent.acquire_produced(&Good::Berries, 8, 2).unwrap();
+        assert_eq!(agent.stock.count_units(&Good::Berries), 8);
+        // Units produced together have varied lifetimes.
+        assert!(agent.stock.num_distinct_units() > 1);
+
+        let mut agent = CrusoeAgent::new(1);
+        agent.acquire_produced(&Good::Berries, 8, 0).unwrap();
+        assert_eq!(agent.stock.num_distinct_units(), 1);
+    }
+
+    #[test]
+    fn test_act_with_resources() {
+        let mut resources = ResourceState::from_configs(&[ResourceConfig {
+            good: Good::Berries,
+            capacity: 20.0,
+            regeneration_rate: 0.0,
+        }]);
+        let mut agent = CrusoeAgent::new(1);
+        let action = Action::ProduceGood(Good::Berries);
+        let mut yields = vec![];
+        for _ in 0..4 {
+            let before = agent.stock.count_units(&Good::Berries);
+            agent.act_with_resources(action, &mut resources, &mut SharedResources::default());
+            yields.push(agent.stock.count_units(&Good::Berries) - before);
+        }
+        // Sustained harvesting reduces the daily yield.
+        assert_eq!(yields, vec![4, 3, 2, 2]);
+        // Productivity, on which valuation is based, reflects the depletion.
+        assert_eq!(
+            agent.productivity(&Good::Berries),
+            Productivity::Immediate(1)
+        );
+        let mut rational = RationalAgent::new(2, 3);
+        assert_eq!(
+            rational.productivity(&Good::Berries),
+            Productivity::Immediate(4)
+        );
+        rational.set_resources(resources);
+        assert_eq!(
+            rational.productivity(&Good::Berries),
+            Productivity::Immediate(1)
+        );
+    }
+
+    #[test]
+    fn test_starvation_risk() {
+        let mut agent = CrusoeAgent::new(1);
+        assert_eq!(agent.starvation_risk(), 1.0);
+        agent.acquire(GoodsUnit::new(&Good::Berries), 100).unwrap();
+        assert!(agent.starvation_risk() < 0.1);
+
+        assert_eq!(starvation_risk(5, 10), 0.5);
+        assert_eq!(starvation_risk(20, 10), 0.0);
+    }
+
+    #[test]
+    fn test_on_death() {
+        // The default behaviour on death is to continue with an empty stock.
+        let mut agent = CrusoeAgent::new(1);
+        agent.acquire(GoodsUnit::new(&Good::Basket), 1).unwrap();
+        assert!(!agent.step_forward(Some(Action::Leisure)));
+        assert_eq!(agent.stock, Stock::default());
+
+        // An agent respawns with the configured partial endowment.
+        let partial = PartialGoodsUnit {
+            good: Good::Boat,
+            time_to_completion: 5,
+            build_time: 10,
+        };
+        let mut agent = CrusoeAgent::new(1);
+        agent.set_config(SharedConfig::new(Config {
+            partial_endowment: vec![partial],
+            ..Config::default()
+        }));
+        agent.acquire(GoodsUnit::new(&Good::Basket), 1).unwrap();
+        assert!(!agent.step_forward(Some(Action::Leisure)));
+        assert_eq!(agent.stock().count_units(&Good::Basket), 0);
+        assert_eq!(agent.get_partial(Good::Boat), Some(partial));
+
+        // A learning agent overrides the behaviour on death, keeping its stock.
+        let mut agent = LearningAgent::new(1);
+        agent.acquire(GoodsUnit::new(&Good::Basket), 1).unwrap();
+        assert!(!agent.step_forward(Some(Action::Leisure)));
+        assert_eq!(agent.stock().count_units(&Good::Basket), 1);
+    }
+
+    #[test]
+    fn test_death_snapshot() {
+        let mut agent = CrusoeAgent::new(1);
+        agent.acquire(GoodsUnit::new(&Good::Basket), 1).unwrap();
+        agent.acquire(GoodsUnit::new(&Good::Berries), 1).unwrap();
+        assert!(agent.step_forward(Some(Action::Leisure)));
+        assert_eq!(agent.death_snapshot(), None);
+
+        // With the berries eaten, the agent dies holding only the basket, which the snapshot
+        // retains after the stock is replaced on death.
+        let last_stock = agent.stock().clone();
+        assert_eq!(last_stock.count_units(&Good::Basket), 1);
+        assert!(!agent.step_forward(Some(Action::Leisure)));
+        assert_eq!(agent.death_snapshot(), Some(&last_stock));
+        assert_eq!(agent.stock().count_units(&Good::Basket), 0);
+        // By default, an agent continues after death.
+        assert!(agent.is_alive());
+
+        // With permanent death, the agent is marked dead.
+        let mut agent = CrusoeAgent::new(1);
+        agent.set_config(SharedConfig::new(Config {
+            agent: AgentConfig {
+                permanent_death: true,
+                ..AgentConfig::default()
             },
-            4,
+            ..Config::default()
+        }));
+        agent.acquire(GoodsUnit::new(&Good::Basket), 1).unwrap();
+        assert!(!agent.step_forward(Some(Action::Leisure)));
+        assert!(!agent.is_alive());
+        assert_eq!(
+            agent.death_snapshot().unwrap().count_units(&Good::Basket),
+            1
         );
-        assert_eq!(agent.stock, expected);
+    }
+
+    #[test]
+    fn test_consume_with_protein_requirement() {
+        let requirement = NutritionRequirement {
+            categories: vec![CategoryRequirement {
+                category: NutritionCategory::Protein,
+                quantity: 1,
+            }],
+        };
+        // An agent with only berries starves under a protein requirement.
+        let mut agent = CrusoeAgent::new(1);
+        agent.acquire(GoodsUnit::new(&Good::Berries), 10).unwrap();
+        assert!(!agent.consume_with(3, &requirement));
+
+        // With a unit of fish, the requirement is met and counts towards the total.
+        let mut agent = CrusoeAgent::new(1);
+        agent.acquire(GoodsUnit::new(&Good::Berries), 10).unwrap();
+        agent.acquire(GoodsUnit::new(&Good::Fish), 1).unwrap();
+        assert!(agent.consume_with(3, &requirement));
+        assert_eq!(agent.stock.count_units(&Good::Fish), 0);
+        assert_eq!(agent.stock.count_units(&Good::Berries), 8);
     }
 
     #[test]
@@ -369,26 +1699,30 @@ mod tests {
         let mut stock = Stock::default();
         assert_eq!(agent.stock(), &stock);
 
-        agent.acquire(goods_unit, 2);
+        agent.acquire(goods_unit, 2).unwrap();
 
-        stock.add(
-            GoodsUnit {
-                good: Good::Berries,
-                remaining_lifetime: 10,
-            },
-            2,
-        );
+        stock
+            .add(
+                GoodsUnit {
+                    good: Good::Berries,
+                    remaining_lifetime: 10,
+                },
+                2,
+            )
+            .unwrap();
         assert_eq!(agent.stock(), &stock);
 
-        agent.acquire(goods_unit, 1);
+        agent.acquire(goods_unit, 1).unwrap();
 
-        stock.add(
-            GoodsUnit {
-                good: Good::Berries,
-                remaining_lifetime: 10,
-            },
-            1,
-        );
+        stock
+            .add(
+                GoodsUnit {
+                    good: Good::Berries,
+                    remaining_lifetime: 10,
+                },
+                1,
+            )
+            .unwrap();
         assert_eq!(agent.stock(), &stock);
     }
 }
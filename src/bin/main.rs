@@ -1,51 +1,83 @@
 use crusoe::{
-    actions::ActionFlattened as Action,
+    agent::Agent,
     config::Config,
-    goods::GoodsUnitLevel,
-    learning::tabular_rl::SARSAModel,
-    simulation::Simulation,
-    stock::{InvLevel, Stock},
+    simulation::{Simulation, TRAILING_REWARD_WINDOW},
+    stock::Stock,
 };
-use strum::IntoEnumIterator;
+use std::path::Path;
+
+/// Environment variable holding the path to a config file, used if no path is given as an argument.
+const CONFIG_PATH_ENV_VAR: &str = "CRUSOE_CONFIG";
+/// Number of timesteps between printing the values of each action in the empty-stock state.
+const ACTION_VALUES_INTERVAL: u32 = 10000;
 
 fn main() {
-    let mut sim = Simulation::new(
-        Config {
+    // Load the config from the path given as the first argument (or environment variable),
+    // falling back to the defaults.
+    let config_path = std::env::args()
+        .nth(1)
+        .or_else(|| std::env::var(CONFIG_PATH_ENV_VAR).ok());
+    let config = match config_path {
+        Some(path) => Config::from_toml_path(Path::new(&path))
+            .unwrap_or_else(|err| panic!("Failed to load config from {}: {}", path, err)),
+        None => Config {
             max_time: 1000000,
             daily_nutrition: 3,
             ..Config::default()
         },
-        true,
-    );
-    let num_agents = 1u32;
-    let multi_policy = false;
-    let mut model: SARSAModel<Stock, _, _, _> = SARSAModel::new(
-        (0..num_agents).collect(),
-        GoodsUnitLevel::iter().collect::<Vec<GoodsUnitLevel>>(),
-        InvLevel::iter().collect::<Vec<InvLevel>>(),
-        Action::iter().collect::<Vec<Action>>(),
-        multi_policy,
-    );
-    println!("Model initialized with {} agents", num_agents);
+    };
+    let checkpoint = config.checkpoint.clone();
+    // Resume from an existing checkpoint, if any, or else build the population of agents
+    // from the config.
+    let resumed = checkpoint
+        .as_ref()
+        .filter(|checkpoint| checkpoint.dir.exists())
+        .map(|checkpoint| {
+            Simulation::resume(&checkpoint.dir).unwrap_or_else(|err| {
+                panic!(
+                    "Failed to resume from {}: {}",
+                    checkpoint.dir.display(),
+                    err
+                )
+            })
+        });
+    let (mut sim, mut model) = match resumed {
+        Some((sim, model)) => {
+            println!("Resumed from checkpoint at time {}", sim.time);
+            (sim, model)
+        }
+        None => {
+            let sim = Simulation::new(config, true);
+            let model = sim.new_model();
+            println!("Model initialized with {} agents", sim.agents.len());
+            (sim, model)
+        }
+    };
 
     while sim.time < sim.config.max_time {
         sim.step_forward(&model);
         if sim.time % 1000 == 0 {
-            let n_steps = 10000;
-            let avg_reward = sim.agents[0]
-                .reward_history()
-                .iter()
-                .rev()
-                .take(n_steps)
-                .map(|el| el.val as f32)
-                .sum::<f32>()
-                / n_steps as f32;
+            let avg_reward = sim.agents[0].trailing_avg_reward(TRAILING_REWARD_WINDOW);
             println!("Time: {}, Avg. Reward: {}", sim.time, avg_reward)
         }
+        if sim.time % ACTION_VALUES_INTERVAL == 0 {
+            let empty_stock = Stock::default().discretise_with(&sim.config.agent);
+            println!(
+                "Action values (empty stock): {:?}",
+                model.action_values(0, &empty_stock)
+            );
+        }
         sim.time += 1;
 
         // Update model given agent history
         model.step(sim.time as i32, &sim.agent_hist);
+
+        if let Some(checkpoint) = &checkpoint
+            && sim.time % checkpoint.interval == 0
+        {
+            sim.checkpoint(&model, &checkpoint.dir)
+                .unwrap_or_else(|err| eprintln!("Failed to write checkpoint: {}", err));
+        }
     }
     // println!("Actions:  {0:?}", sim.agents[0]);
 
@@ -1,21 +1,75 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use strum::IntoEnumIterator;
 
-use crate::UInt;
+use crate::{
+    Int, UInt,
+    goods::{Good, NutritionCategory, PartialGoodsUnit},
+    season::Season,
+    stock::Stock,
+};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub max_time: UInt,
     pub daily_nutrition: UInt, // Number of units (of any consumer good) required per day.
+    pub auto_consume: bool, // Consume the daily requirement every step (otherwise only on `Action::Consume`).
+    pub hunger_limit: UInt, // Consecutive days without consuming that an agent survives, when consumption is not automatic.
+    pub nutrition: NutritionRequirement,
+    pub spoilage: SpoilageModel,
+    pub consume_tie_break: ConsumeTieBreak, // Order of consumption among units expiring together.
+    pub resources: Vec<ResourceConfig>,     // Renewable resources that deplete when harvested.
+    pub seasons: Option<SeasonConfig>, // Seasonal modulation of productivity (None for no seasons).
+    pub enabled_goods: HashSet<Good>,  // Goods that exist in the simulation (all by default).
+    pub build_times: HashMap<Good, UInt>, // Timesteps to complete multi-step goods (overriding the defaults).
+    pub tool_boosts: HashMap<Good, HashMap<Good, UInt>>, // Units of each good produced per timestep using each tool (overriding the defaults).
+    pub materials: HashMap<Good, HashMap<Good, UInt>>, // Units of each material built into each good per timestep of production (overriding the defaults).
+    pub transforms: HashMap<Good, Good>, // Goods made by transforming every available unit of another good (overriding the defaults).
+    pub fuel: HashMap<Good, UInt>, // Units of timber burned as fuel per timestep of producing each good.
+    pub partial_abandon_grace: UInt, // Extra idle days before a partially complete good is abandoned.
+    pub shared_tools: HashMap<Good, UInt>, // Units of capital goods shared by all agents, each usable by one agent per timestep.
+    pub partial_endowment: Vec<PartialGoodsUnit>, // Partially complete goods held by each agent at the start.
+    pub stop_when_all_dead: bool,                 // Halt the simulation once no agent is alive.
+    pub record_events: bool, // Record a log of events (production, spoilage, death) in the simulation.
+    pub history_window: Option<usize>, // Number of most recent steps of history retained (None for all).
+    pub seed: Option<u64>, // Seed for the agents' random number generators (None for OS entropy).
+    pub agents: Vec<AgentSpec>, // The population of agents in the simulation.
+    pub checkpoint: Option<CheckpointConfig>, // Periodic checkpointing of the simulation and model.
     pub agent: AgentConfig,
+    pub reward: RewardConfig,
     pub rl: RLConfig,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AgentConfig {
     pub inv_level_low: UInt,
     pub inv_level_med: UInt,
     pub inv_level_high: UInt,
-    // pub remaining_level_high: UInt,
+    /// Remaining lifetime at or above which a unit of a perishable good is considered fresh.
+    pub remaining_level_high: UInt,
+    /// Marginal benefit of leisure, against which a rational agent compares the benefit of production.
+    pub leisure_value: f32,
+    /// Number of days of survival at or beyond which an agent is not at risk of starvation.
+    pub starvation_horizon: UInt,
+    /// Maximum number of days by which the lifetime of a newly-produced perishable unit is
+    /// randomly shortened or extended, so that units produced together do not all spoil together.
+    pub lifetime_jitter: UInt,
+    /// Maximum fraction by which the yield of immediate production is randomly reduced or
+    /// increased, so that foraging and fishing are stochastic (0 for deterministic yields).
+    pub production_noise: f32,
+    /// Maximum number of partially complete goods an agent may have in progress at once
+    /// (None for no limit).
+    pub max_concurrent_builds: Option<UInt>,
+    /// Whether an agent that fails to meet its nutritional requirement is marked dead and
+    /// takes no further actions, rather than continuing as set out by `Agent::on_death`.
+    /// Death is always permanent if the simulation stops when all agents are dead.
+    pub permanent_death: bool,
 }
 
 impl Default for AgentConfig {
@@ -24,30 +78,194 @@ impl Default for AgentConfig {
             inv_level_low: 5,
             inv_level_med: 10,
             inv_level_high: 20,
-            // remaining_level_high: 5,
+            remaining_level_high: 5,
+            leisure_value: 0.0,
+            starvation_horizon: 10,
+            lifetime_jitter: 0,
+            production_noise: 0.0,
+            max_concurrent_builds: None,
+            permanent_death: false,
         }
     }
 }
 
+/// Where and how often to checkpoint a running simulation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointConfig {
+    /// Number of time steps between checkpoints.
+    pub interval: UInt,
+    /// Directory to which checkpoints are written (and from which they are resumed).
+    pub dir: PathBuf,
+}
+
+/// The kind of an agent in the simulation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AgentKind {
+    Rl,
+    Crusoe,
+    Rational,
+}
+
+/// A number of agents of the same kind and nutritional requirement.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AgentSpec {
+    pub kind: AgentKind,
+    pub count: UInt,
+    /// Number of nutritional units required per day by each agent.
+    pub nutrition: UInt,
+}
+
+impl Default for AgentSpec {
+    fn default() -> Self {
+        AgentSpec {
+            kind: AgentKind::Rl,
+            count: 1,
+            nutrition: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct RewardConfig {
+    /// One-time reward for the first production of each good, to encourage discovery of the
+    /// production graph.
+    pub first_production_bonus: Int,
+    /// Number of consecutive days of leisure after which the reward for each further day of
+    /// leisure halves, so that idling indefinitely is not rewarded (None for no decay).
+    pub leisure_decay_after: Option<UInt>,
+    /// Reward (typically negative) for each partially complete good abandoned, so that the
+    /// materials lost in an unfinished build are not lost without penalty.
+    pub abandon_penalty: Int,
+}
+
 impl Default for Config {
     fn default() -> Self {
         Config {
             max_time: 100,
             daily_nutrition: 3,
+            auto_consume: true,
+            hunger_limit: 3,
+            stop_when_all_dead: false,
+            record_events: false,
+            history_window: None,
+            seed: None,
+            agents: vec![AgentSpec::default()],
+            checkpoint: None,
+            nutrition: NutritionRequirement::default(),
+            spoilage: SpoilageModel::default(),
+            consume_tie_break: ConsumeTieBreak::default(),
+            resources: vec![],
+            seasons: None,
+            enabled_goods: Good::iter().collect(),
+            build_times: HashMap::new(),
+            tool_boosts: HashMap::new(),
+            materials: HashMap::new(),
+            transforms: HashMap::new(),
+            fuel: HashMap::new(),
+            partial_abandon_grace: 0,
+            shared_tools: HashMap::new(),
+            partial_endowment: vec![],
             rl: RLConfig::default(),
             agent: AgentConfig::default(),
+            reward: RewardConfig::default(),
         }
     }
 }
 
+/// A renewable resource from which a good is harvested.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResourceConfig {
+    pub good: Good,
+    /// The maximum level of the resource.
+    pub capacity: f32,
+    /// The fraction of the gap between the current level and capacity regenerated per time step.
+    pub regeneration_rate: f32,
+}
+
+/// A cycle of seasons, each scaling the productivity of particular goods.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SeasonConfig {
+    /// The number of timesteps in each season.
+    pub period: UInt,
+    /// The multiplier of the productivity of each good in each season. Goods not listed for
+    /// a season are unaffected in that season.
+    #[serde(default)]
+    pub multipliers: HashMap<Season, HashMap<Good, f32>>,
+}
+
+impl SeasonConfig {
+    /// Returns the season at the given time.
+    pub fn season_at(&self, time: UInt) -> Season {
+        Season::at(time, self.period)
+    }
+
+    /// Returns the multiplier of the productivity of a good in the given season.
+    pub fn multiplier(&self, season: Season, good: &Good) -> f32 {
+        self.multipliers
+            .get(&season)
+            .and_then(|multipliers| multipliers.get(good))
+            .copied()
+            .unwrap_or(1.0)
+    }
+}
+
+/// How the nutrition provided by a unit of a consumer good changes as it ages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum SpoilageModel {
+    /// Full nutrition is provided until the unit expires.
+    #[default]
+    CliffEdge,
+    /// Nutrition is scaled by the remaining fraction of the unit's lifetime.
+    Linear,
+}
+
+/// How units of consumer goods with equal remaining lifetimes are ordered for consumption.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConsumeTieBreak {
+    /// In the order in which the goods are declared (e.g. berries before fish).
+    #[default]
+    GoodOrder,
+    /// Units providing the most nutrition first (then in the order the goods are declared).
+    NutritionValue,
+}
+
+/// Minimum quantities of particular categories of nutrition required per day.
+///
+/// Units consumed to meet a category requirement also count towards the daily total, so the
+/// default (no category requirements) treats all consumer goods as interchangeable.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NutritionRequirement {
+    pub categories: Vec<CategoryRequirement>,
+}
+
+/// A minimum quantity of one category of nutrition.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CategoryRequirement {
+    pub category: NutritionCategory,
+    pub quantity: UInt,
+}
+
 #[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(default)]
 pub struct RLConfig {
     pub init_q_value: f32,
+    pub init_strategy: InitStrategy,
     pub sarsa_n: u8,
     pub gamma: f32,
     pub alpha: f32,
     pub epsilon: f32,
+    pub exploration: ExplorationStrategy,
     pub multi_policy: bool,
+    /// If true, `QTable` entries are only inserted when first updated, rather
+    /// than for every state-action pair up front.
+    pub lazy_q_table: bool,
+    /// The coefficient `c` of the count-based exploration bonus `c / sqrt(1 + n)` added to
+    /// the value of a state-action visited n times when selecting actions (zero for none).
+    pub exploration_bonus_c: f32,
     // pub save_model: bool,
     // pub load_model: bool,
     // pub model_checkpoint_file: Option<String>,
@@ -57,11 +275,15 @@ impl Default for RLConfig {
     fn default() -> Self {
         RLConfig {
             init_q_value: 0.0,
+            init_strategy: InitStrategy::Constant,
             sarsa_n: 1,
             gamma: 0.9,
             alpha: 0.1,
             epsilon: 0.1,
+            exploration: ExplorationStrategy::EpsilonGreedy,
             multi_policy: false,
+            lazy_q_table: false,
+            exploration_bonus_c: 0.0,
             // save_model: false,
             // load_model: false,
             // model_checkpoint_file: None,
@@ -69,21 +291,435 @@ impl Default for RLConfig {
     }
 }
 
+/// Strategy for initialising the values in a `QTable`.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug, Default)]
+pub enum InitStrategy {
+    /// Every entry takes the value `init_q_value`.
+    #[default]
+    Constant,
+    /// Every entry takes the given (typically high) value, to encourage exploration.
+    Optimistic(f32),
+    /// Entries for each listed action (keyed by its name) take the given value.
+    /// Entries for unlisted actions take the value `init_q_value`. The map is ordered so
+    /// that the config fingerprint does not depend on insertion order.
+    PerAction(BTreeMap<String, f32>),
+}
+
+impl InitStrategy {
+    /// Returns the initial value for entries with the given action.
+    pub fn init_value<A: std::fmt::Debug>(&self, action: &A, init_q_value: f32) -> f32 {
+        match self {
+            InitStrategy::Constant => init_q_value,
+            InitStrategy::Optimistic(value) => *value,
+            InitStrategy::PerAction(values) => values
+                .get(&format!("{:?}", action))
+                .copied()
+                .unwrap_or(init_q_value),
+        }
+    }
+}
+
+/// Strategy for selecting actions from a `QTable`.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug, Default)]
+pub enum ExplorationStrategy {
+    /// Take the greedy action, except with probability `epsilon` take a uniformly random action.
+    #[default]
+    EpsilonGreedy,
+    /// Take each action with probability proportional to `exp(Q / temperature)`.
+    Softmax { temperature: f32 },
+}
+
+/// Returns the default config, used by the convenience methods that do not take a config
+/// (e.g. `Good::default_productivity`). Agents, simulations and models instead use the config
+/// they hold (see `SharedConfig`).
 pub fn core_config() -> Config {
     Config::default()
 }
 
+/// A config that may be shared between a simulation, its agents and a model, so that all
+/// act under the same (e.g. loaded) settings without copying them at every step. Shared
+/// configs are equal if they are the same config or have equal values.
+#[derive(Debug, Clone, Default)]
+pub struct SharedConfig(pub Arc<Config>);
+
+impl SharedConfig {
+    pub fn new(config: Config) -> Self {
+        SharedConfig(Arc::new(config))
+    }
+}
+
+impl Deref for SharedConfig {
+    type Target = Config;
+
+    fn deref(&self) -> &Config {
+        &self.0
+    }
+}
+
+impl From<Config> for SharedConfig {
+    fn from(config: Config) -> Self {
+        SharedConfig::new(config)
+    }
+}
+
+impl PartialEq for SharedConfig {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0) || self.0 == other.0
+    }
+}
+
+impl Eq for SharedConfig {}
+
+impl Serialize for SharedConfig {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SharedConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Config::deserialize(deserializer).map(SharedConfig::new)
+    }
+}
+
+/// Errors arising when loading a `Config` from file.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file could not be read.
+    Io(std::io::Error),
+    /// The config file could not be parsed as TOML.
+    Parse(toml::de::Error),
+    /// The config values violate one or more invariants.
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "failed to read config file: {}", err),
+            ConfigError::Parse(err) => write!(f, "failed to parse config file: {}", err),
+            ConfigError::Invalid(violations) => {
+                write!(f, "invalid config: {}", violations.join("; "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(err: std::io::Error) -> Self {
+        ConfigError::Io(err)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(err: toml::de::Error) -> Self {
+        ConfigError::Parse(err)
+    }
+}
+
+impl Config {
+    /// Loads a config from the TOML file at the given path.
+    /// Fields missing from the file take their default values.
+    pub fn from_toml_path(path: &Path) -> Result<Config, ConfigError> {
+        let contents = std::fs::read_to_string(path)?;
+        let config: Config = toml::from_str(&contents)?;
+        config.validate().map_err(ConfigError::Invalid)?;
+        Ok(config)
+    }
+
+    /// Returns a fingerprint of the settings that determine how a model is trained: the
+    /// agent config (including the discretisation thresholds) and the RL config. Models
+    /// trained under configs with different fingerprints are not interchangeable.
+    pub fn fingerprint(&self) -> u64 {
+        let settings = serde_json::to_string(&(&self.agent, &self.rl))
+            .expect("agent and RL configs are serializable");
+        // FNV-1a, which (unlike the standard library's hasher) is stable across releases.
+        settings.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+    }
+
+    /// Returns the enabled goods that can never be produced, because an input required to
+    /// produce them (including any fuel) is disabled or is itself unreachable.
+    pub fn unreachable_goods(&self) -> Vec<Good> {
+        let inputs = |good: &Good| {
+            let mut inputs = good.required_inputs();
+            inputs.extend(good.transforms_from_with(&self.transforms));
+            inputs.extend(
+                good.materials_per_step_with(self, &Stock::default())
+                    .into_iter()
+                    .map(|(material, _)| material),
+            );
+            inputs
+        };
+        let mut unreachable: Vec<Good> = Vec::new();
+        // A good may depend on one declared after it, so repeat until nothing changes.
+        loop {
+            let newly_unreachable: Vec<Good> = Good::iter()
+                .filter(|good| self.enabled_goods.contains(good) && !unreachable.contains(good))
+                .filter(|good| {
+                    inputs(good).iter().any(|input| {
+                        !self.enabled_goods.contains(input) || unreachable.contains(input)
+                    })
+                })
+                .collect();
+            if newly_unreachable.is_empty() {
+                break;
+            }
+            unreachable.extend(newly_unreachable);
+        }
+        unreachable.sort();
+        unreachable
+    }
+
+    /// Checks the config values are consistent, returning a description of every violation.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut violations = Vec::new();
+        // With no nutritional requirement, an agent can never die and survival never ends.
+        if self.daily_nutrition == 0 {
+            violations.push("daily_nutrition must be positive".to_string());
+        }
+        for spec in &self.agents {
+            if spec.nutrition == 0 {
+                violations.push(format!(
+                    "nutrition of {:?} agents must be positive",
+                    spec.kind
+                ));
+            }
+        }
+        let agent = &self.agent;
+        if agent.inv_level_low > agent.inv_level_med {
+            violations.push(format!(
+                "inv_level_low ({}) must not exceed inv_level_med ({})",
+                agent.inv_level_low, agent.inv_level_med
+            ));
+        }
+        if agent.inv_level_med > agent.inv_level_high {
+            violations.push(format!(
+                "inv_level_med ({}) must not exceed inv_level_high ({})",
+                agent.inv_level_med, agent.inv_level_high
+            ));
+        }
+        if !(0.0..=1.0).contains(&agent.production_noise) {
+            violations.push(format!(
+                "production_noise ({}) must be in [0, 1]",
+                agent.production_noise
+            ));
+        }
+        let rl = &self.rl;
+        if !(0.0..=1.0).contains(&rl.epsilon) {
+            violations.push(format!("epsilon ({}) must be in [0, 1]", rl.epsilon));
+        }
+        if !(0.0..=1.0).contains(&rl.gamma) {
+            violations.push(format!("gamma ({}) must be in [0, 1]", rl.gamma));
+        }
+        if !(rl.alpha > 0.0 && rl.alpha <= 1.0) {
+            violations.push(format!("alpha ({}) must be in (0, 1]", rl.alpha));
+        }
+        if rl.exploration_bonus_c < 0.0 {
+            violations.push(format!(
+                "exploration_bonus_c ({}) must be non-negative",
+                rl.exploration_bonus_c
+            ));
+        }
+        if let ExplorationStrategy::Softmax { temperature } = rl.exploration
+            && temperature <= 0.0
+        {
+            violations.push(format!(
+                "softmax temperature ({}) must be positive",
+                temperature
+            ));
+        }
+        // The SARSA update requires the last n + 1 steps of history.
+        if let Some(window) = self.history_window
+            && window <= rl.sarsa_n as usize
+        {
+            violations.push(format!(
+                "history_window ({}) must exceed sarsa_n ({})",
+                window, rl.sarsa_n
+            ));
+        }
+        for resource in &self.resources {
+            if resource.capacity <= 0.0 {
+                violations.push(format!(
+                    "capacity of {:?} resource ({}) must be positive",
+                    resource.good, resource.capacity
+                ));
+            }
+            if !(0.0..=1.0).contains(&resource.regeneration_rate) {
+                violations.push(format!(
+                    "regeneration_rate of {:?} resource ({}) must be in [0, 1]",
+                    resource.good, resource.regeneration_rate
+                ));
+            }
+        }
+        if let Some(seasons) = &self.seasons {
+            if seasons.period == 0 {
+                violations.push("season period must be positive".to_string());
+            }
+            for (season, multipliers) in &seasons.multipliers {
+                for (good, multiplier) in multipliers {
+                    if *multiplier < 0.0 {
+                        violations.push(format!(
+                            "{:?} multiplier of {:?} ({}) must not be negative",
+                            season, good, multiplier
+                        ));
+                    }
+                }
+            }
+        }
+        if !self.enabled_goods.iter().any(|good| good.is_consumer()) {
+            violations.push("enabled_goods must include a consumer good".to_string());
+        }
+        if self
+            .fuel
+            .get(&Good::Timber)
+            .is_some_and(|quantity| *quantity > 0)
+        {
+            violations.push("timber cannot be burned as fuel to produce timber".to_string());
+        }
+        for (good, materials) in &self.materials {
+            if materials.contains_key(good) {
+                violations.push(format!("{:?} cannot be built from itself", good));
+            }
+            for material in materials.keys().filter(|material| material.is_consumer()) {
+                violations.push(format!(
+                    "{:?} cannot be a material of {:?}, as it is a consumer good",
+                    material, good
+                ));
+            }
+        }
+        for (good, time) in &self.build_times {
+            if good.default_timesteps_to_complete().is_none() {
+                violations.push(format!(
+                    "build time cannot be set for {:?}, which is produced in a single timestep",
+                    good
+                ));
+            } else if *time == 0 {
+                violations.push(format!("build time of {:?} must be positive", good));
+            }
+        }
+        for (tool, goods) in &self.tool_boosts {
+            for good in goods.keys() {
+                if good.default_boost_from(tool).is_none() {
+                    violations.push(format!(
+                        "{:?} does not boost the productivity of {:?}",
+                        tool, good
+                    ));
+                }
+            }
+        }
+        for good in self.shared_tools.keys() {
+            if good.is_consumer() || good.is_material() {
+                violations.push(format!(
+                    "{:?} cannot be shared, as only capital goods that are not materials can be",
+                    good
+                ));
+            }
+        }
+        for (good, input) in &self.transforms {
+            if good == input {
+                violations.push(format!("{:?} cannot be made by transforming itself", good));
+            }
+            if good.default_timesteps_to_complete().is_some() {
+                violations.push(format!(
+                    "{:?} cannot be made by transformation, as it takes multiple timesteps",
+                    good
+                ));
+            }
+        }
+        let mut endowed = HashSet::new();
+        for partial in &self.partial_endowment {
+            match partial
+                .good
+                .multiple_timesteps_to_complete_with(&self.build_times)
+            {
+                None => violations.push(format!(
+                    "{:?} cannot be endowed partially complete, as it is produced in a single timestep",
+                    partial.good
+                )),
+                Some(build_time) => {
+                    if partial.build_time != build_time {
+                        violations.push(format!(
+                            "build time of partially complete {:?} ({}) must be {}",
+                            partial.good, partial.build_time, build_time
+                        ));
+                    }
+                    if !(1..=partial.build_time).contains(&partial.time_to_completion) {
+                        violations.push(format!(
+                            "time to completion of partially complete {:?} ({}) must be in [1, {}]",
+                            partial.good, partial.time_to_completion, partial.build_time
+                        ));
+                    }
+                }
+            }
+            if !endowed.insert(partial.good) {
+                violations.push(format!(
+                    "only one partially complete {:?} can be endowed",
+                    partial.good
+                ));
+            }
+        }
+        if let Some(limit) = self.agent.max_concurrent_builds
+            && self.partial_endowment.len() as UInt > limit
+        {
+            violations.push(format!(
+                "number of partially complete goods endowed ({}) must not exceed max_concurrent_builds ({})",
+                self.partial_endowment.len(),
+                limit
+            ));
+        }
+        if self
+            .checkpoint
+            .as_ref()
+            .is_some_and(|checkpoint| checkpoint.interval == 0)
+        {
+            violations.push("checkpoint interval must be positive".to_string());
+        }
+        match violations.is_empty() {
+            true => Ok(()),
+            false => Err(violations),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::goods::Productivity;
 
     #[test]
     fn test_config_serialization_toml() {
         let config = Config {
             max_time: 100,
             daily_nutrition: 3,
+            auto_consume: true,
+            hunger_limit: 3,
+            stop_when_all_dead: false,
+            record_events: false,
+            history_window: None,
+            seed: None,
+            agents: vec![AgentSpec::default()],
+            checkpoint: None,
+            nutrition: NutritionRequirement::default(),
+            spoilage: SpoilageModel::default(),
+            consume_tie_break: ConsumeTieBreak::default(),
+            resources: vec![],
+            seasons: None,
+            enabled_goods: Good::iter().collect(),
+            build_times: HashMap::new(),
+            tool_boosts: HashMap::new(),
+            materials: HashMap::new(),
+            transforms: HashMap::new(),
+            fuel: HashMap::new(),
+            partial_abandon_grace: 0,
+            shared_tools: HashMap::new(),
+            partial_endowment: vec![],
             rl: RLConfig::default(),
             agent: AgentConfig::default(),
+            reward: RewardConfig::default(),
         };
         let serialized = toml::to_string(&config).unwrap();
 
@@ -97,4 +733,270 @@ mod tests {
     fn test_read_from_file() {
         std::fs::read_to_string("./crusoe.toml").expect("Failed to read the file");
     }
+
+    #[test]
+    fn test_from_toml_path() {
+        let config = Config::from_toml_path(Path::new("./crusoe.toml")).unwrap();
+        assert_eq!(config.max_time, 100);
+        assert_eq!(config.daily_nutrition, 3);
+        // Fields missing from the file take their default values.
+        assert_eq!(config.agent, AgentConfig::default());
+        assert_eq!(config.rl, RLConfig::default());
+    }
+
+    #[test]
+    fn test_from_toml_path_missing_file() {
+        let result = Config::from_toml_path(Path::new("./does_not_exist.toml"));
+        assert!(matches!(result, Err(ConfigError::Io(_))));
+    }
+
+    #[test]
+    fn test_from_toml_path_malformed_file() {
+        let path = std::env::temp_dir().join("crusoe_test_malformed_config.toml");
+        std::fs::write(&path, "max_time = \"one hundred\"\n").unwrap();
+        let result = Config::from_toml_path(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(ConfigError::Parse(_))));
+    }
+
+    #[test]
+    fn test_from_toml_path_invalid_file() {
+        let path = std::env::temp_dir().join("crusoe_test_invalid_config.toml");
+        std::fs::write(&path, "[rl]\nepsilon = 2.0\n").unwrap();
+        let result = Config::from_toml_path(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(ConfigError::Invalid(v)) if v.len() == 1));
+    }
+
+    #[test]
+    fn test_validate_default() {
+        assert_eq!(Config::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_inv_levels() {
+        let mut config = Config::default();
+        config.agent.inv_level_low = 11;
+        assert_eq!(config.validate().unwrap_err().len(), 1);
+
+        let mut config = Config::default();
+        config.agent.inv_level_high = 6;
+        assert_eq!(config.validate().unwrap_err().len(), 1);
+
+        // Both violations are reported.
+        let mut config = Config::default();
+        config.agent.inv_level_low = 30;
+        config.agent.inv_level_med = 25;
+        assert_eq!(config.validate().unwrap_err().len(), 2);
+    }
+
+    #[test]
+    fn test_validate_epsilon() {
+        let mut config = Config::default();
+        config.rl.epsilon = -0.1;
+        assert_eq!(config.validate().unwrap_err().len(), 1);
+        config.rl.epsilon = 1.0;
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_gamma() {
+        let mut config = Config::default();
+        config.rl.gamma = 1.5;
+        assert_eq!(config.validate().unwrap_err().len(), 1);
+    }
+
+    #[test]
+    fn test_validate_history_window() {
+        let mut config = Config {
+            history_window: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(config.validate().unwrap_err().len(), 1);
+        config.history_window = Some(2);
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_resources() {
+        let config = Config {
+            resources: vec![ResourceConfig {
+                good: Good::Berries,
+                capacity: 0.0,
+                regeneration_rate: 1.5,
+            }],
+            ..Default::default()
+        };
+        assert_eq!(config.validate().unwrap_err().len(), 2);
+    }
+
+    #[test]
+    fn test_validate_enabled_goods() {
+        let mut config = Config {
+            enabled_goods: HashSet::from([Good::Basket]),
+            ..Default::default()
+        };
+        assert_eq!(config.validate().unwrap_err().len(), 1);
+        config.enabled_goods = HashSet::from([Good::Berries]);
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_unreachable_goods() {
+        assert!(Config::default().unreachable_goods().is_empty());
+        let mut config = Config {
+            enabled_goods: HashSet::from([Good::Berries, Good::Boat, Good::Timber]),
+            ..Default::default()
+        };
+        assert_eq!(config.unreachable_goods(), vec![Good::Boat, Good::Timber]);
+        config.enabled_goods.insert(Good::Axe);
+        assert!(config.unreachable_goods().is_empty());
+    }
+
+    #[test]
+    fn test_validate_build_times() {
+        let mut config = Config {
+            build_times: HashMap::from([(Good::Berries, 2), (Good::Boat, 0)]),
+            ..Default::default()
+        };
+        assert_eq!(config.validate().unwrap_err().len(), 2);
+        config.build_times = HashMap::from([(Good::Boat, 5)]);
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_materials() {
+        let mut config = Config {
+            materials: HashMap::from([(
+                Good::Boat,
+                HashMap::from([(Good::Boat, 1), (Good::Berries, 1)]),
+            )]),
+            ..Default::default()
+        };
+        assert_eq!(config.validate().unwrap_err().len(), 2);
+        config.materials = HashMap::from([(
+            Good::Boat,
+            HashMap::from([(Good::Timber, 1), (Good::Spear, 1)]),
+        )]);
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_transforms() {
+        let mut config = Config {
+            transforms: HashMap::from([(Good::Fish, Good::Fish), (Good::Boat, Good::Timber)]),
+            ..Default::default()
+        };
+        assert_eq!(config.validate().unwrap_err().len(), 2);
+        config.transforms = HashMap::from([(Good::Fish, Good::Berries)]);
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_daily_nutrition() {
+        let mut config = Config {
+            daily_nutrition: 0,
+            ..Default::default()
+        };
+        assert_eq!(config.validate().unwrap_err().len(), 1);
+        config.daily_nutrition = 3;
+        config.agents[0].nutrition = 0;
+        assert_eq!(config.validate().unwrap_err().len(), 1);
+        config.agents[0].nutrition = 1;
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_partial_endowment() {
+        let partial = |good, time_to_completion, build_time| PartialGoodsUnit {
+            good,
+            time_to_completion,
+            build_time,
+        };
+        let mut config = Config {
+            partial_endowment: vec![
+                partial(Good::Berries, 1, 2),
+                partial(Good::Boat, 5, 8),
+                partial(Good::Smoker, 0, 3),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(config.validate().unwrap_err().len(), 3);
+        config.partial_endowment = vec![partial(Good::Boat, 5, 10), partial(Good::Boat, 3, 10)];
+        assert_eq!(config.validate().unwrap_err().len(), 1);
+        config.partial_endowment = vec![partial(Good::Boat, 5, 10), partial(Good::Axe, 1, 2)];
+        assert_eq!(config.validate(), Ok(()));
+        config.agent.max_concurrent_builds = Some(1);
+        assert_eq!(config.validate().unwrap_err().len(), 1);
+    }
+
+    #[test]
+    fn test_validate_tool_boosts() {
+        let mut config = Config {
+            tool_boosts: HashMap::from([(
+                Good::Spear,
+                HashMap::from([(Good::Fish, 15), (Good::Berries, 6)]),
+            )]),
+            ..Default::default()
+        };
+        assert_eq!(config.validate().unwrap_err().len(), 1);
+        config.tool_boosts = HashMap::from([(Good::Spear, HashMap::from([(Good::Fish, 15)]))]);
+        assert_eq!(config.validate(), Ok(()));
+        // Boosting the spear leaves it taking precedence over the (unboosted) boat.
+        assert_eq!(
+            Good::Fish.productivity_with_boosts(&[Good::Spear, Good::Boat], &config.tool_boosts),
+            Productivity::Immediate(15)
+        );
+    }
+
+    #[test]
+    fn test_validate_seasons() {
+        let mut config = Config {
+            seasons: Some(SeasonConfig {
+                period: 0,
+                multipliers: HashMap::from([(Season::Winter, HashMap::from([(Good::Fish, -0.5)]))]),
+            }),
+            ..Default::default()
+        };
+        assert_eq!(config.validate().unwrap_err().len(), 2);
+        config.seasons = Some(SeasonConfig {
+            period: 10,
+            multipliers: HashMap::from([(Season::Winter, HashMap::from([(Good::Fish, 0.5)]))]),
+        });
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_alpha() {
+        let mut config = Config::default();
+        config.rl.alpha = 0.0;
+        assert_eq!(config.validate().unwrap_err().len(), 1);
+        config.rl.alpha = 1.1;
+        assert_eq!(config.validate().unwrap_err().len(), 1);
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let with_init = |entries: Vec<(&str, f32)>| {
+            let mut config = Config::default();
+            config.rl.init_strategy = InitStrategy::PerAction(
+                entries
+                    .into_iter()
+                    .map(|(action, value)| (action.to_string(), value))
+                    .collect(),
+            );
+            config
+        };
+        let entries = vec![("ProduceBoat", 5.0), ("ProduceAxe", 3.0), ("Leisure", 1.0)];
+        let config = with_init(entries.clone());
+        // The fingerprint does not depend on the order in which entries are listed.
+        let reversed = with_init(entries.into_iter().rev().collect());
+        assert_eq!(config.fingerprint(), reversed.fingerprint());
+        let deserialized: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(config.fingerprint(), deserialized.fingerprint());
+        assert_ne!(
+            config.fingerprint(),
+            with_init(vec![("ProduceBoat", 5.0)]).fingerprint()
+        );
+    }
 }
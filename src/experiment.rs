@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{UInt, agent::Agent, config::Config, simulation::Simulation};
+
+/// Summary statistics from a single simulation run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExperimentResult {
+    pub config: Config,
+    pub seed: u64,
+    /// Number of timesteps simulated.
+    pub steps: UInt,
+    /// Mean reward per agent per timestep.
+    pub mean_reward: f32,
+    /// Number of times any agent failed to meet its nutritional requirement.
+    pub death_count: UInt,
+}
+
+/// Runs a simulation for every combination of config and seed, returning the results
+/// in config-major order.
+pub fn run_batch(configs: Vec<Config>, seeds: Vec<u64>) -> Vec<ExperimentResult> {
+    let mut results = Vec::new();
+    for config in configs {
+        for seed in &seeds {
+            results.push(run_experiment(config.clone(), *seed));
+        }
+    }
+    results
+}
+
+/// Runs a single simulation, with a newly-initialised model, from the given config and seed.
+/// The model is initialised as given by the RL config, so an experiment over a large state
+/// space may use a lazy Q-table (see `RLConfig::lazy_q_table`).
+pub fn run_experiment(config: Config, seed: u64) -> ExperimentResult {
+    let mut sim = Simulation::new(
+        Config {
+            seed: Some(seed),
+            ..config.clone()
+        },
+        false,
+    );
+    let mut model = sim.new_model();
+    sim.run(&mut model);
+
+    let rewards: Vec<f32> = sim
+        .agents
+        .iter()
+        .flat_map(|agent| Agent::reward_history(agent).iter().map(|r| r.val as f32))
+        .collect();
+    let mean_reward = match rewards.is_empty() {
+        true => 0.0,
+        false => rewards.iter().sum::<f32>() / rewards.len() as f32,
+    };
+    ExperimentResult {
+        config,
+        seed,
+        steps: sim.time,
+        mean_reward,
+        death_count: sim.death_count,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RLConfig;
+
+    #[test]
+    fn test_run_batch() {
+        // A lazy Q-table avoids initialising every state-action of each model.
+        let rl = RLConfig {
+            lazy_q_table: true,
+            ..Default::default()
+        };
+        let configs = vec![
+            Config {
+                max_time: 5,
+                rl: rl.clone(),
+                ..Default::default()
+            },
+            Config {
+                max_time: 10,
+                rl,
+                ..Default::default()
+            },
+        ];
+        let seeds = vec![1, 2];
+        let results = run_batch(configs, seeds);
+        assert_eq!(results.len(), 4);
+        assert_eq!(
+            results
+                .iter()
+                .map(|r| (r.steps, r.seed))
+                .collect::<Vec<_>>(),
+            vec![(5, 1), (5, 2), (10, 1), (10, 2)]
+        );
+    }
+}
@@ -1,5 +1,12 @@
-use crate::{UInt, actions::Action, stock::Stock};
+use crate::{
+    UInt,
+    actions::Action,
+    config::{Config, SpoilageModel, core_config},
+    stock::{Stock, StockError},
+};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use strum_macros::EnumIter;
 
 use crate::stock::RemainingLevel;
@@ -8,6 +15,7 @@ use strum::IntoEnumIterator;
 type Quantity = UInt;
 type Interval = UInt;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Productivity {
     Immediate(Quantity),
     Delayed(Interval),
@@ -23,10 +31,69 @@ impl Productivity {
             Productivity::None => None,
         }
     }
+
+    /// Returns this productivity with an immediate quantity scaled by the given factor and
+    /// rounded to the nearest unit. The time to complete a delayed good is unaffected.
+    pub fn scaled(self, factor: f32) -> Productivity {
+        match self {
+            Productivity::Immediate(quantity) => {
+                Productivity::Immediate((quantity as f32 * factor).round().max(0.0) as UInt)
+            }
+            _ => self,
+        }
+    }
+
+    /// Returns this productivity with an immediate quantity scaled by a random factor drawn
+    /// uniformly from [1 - noise, 1 + noise]. The result is rounded up or down at random, in
+    /// proportion to its fractional part, so the mean quantity is unchanged.
+    pub fn with_noise<R: Rng + ?Sized>(self, noise: f32, rng: &mut R) -> Productivity {
+        match self {
+            Productivity::Immediate(quantity) if noise > 0.0 => {
+                let factor = rng.random_range(1.0 - noise..=1.0 + noise);
+                let scaled = quantity as f32 * factor;
+                let rounded = (scaled + rng.random::<f32>()).floor();
+                Productivity::Immediate(rounded.max(0.0) as UInt)
+            }
+            _ => self,
+        }
+    }
 }
 
-// A good in the abstract (as opposed to particular units of a good).
+/// A category of nutrition provided by consumer goods.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, EnumIter)]
+pub enum NutritionCategory {
+    Fruit,
+    Protein,
+}
+
+/// A classification of goods by their role in the economy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, EnumIter)]
+pub enum GoodCategory {
+    /// A perishable consumer good.
+    Food,
+    /// A consumer good made by preserving a food (none at present).
+    Preserved,
+    /// A capital good used in producing or improving other goods.
+    Tool,
+    /// A capital good used to travel (e.g. to reach better fishing grounds).
+    Vessel,
+    /// A capital good that is used up in producing other goods.
+    RawMaterial,
+}
+
+/// The kind of dependency of one good on another in the production graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum EdgeKind {
+    /// The input good is required to produce the output good.
+    Required,
+    /// The input good enhances production of the output good but is not required.
+    Enhancing,
+}
+
+// A good in the abstract (as opposed to particular units of a good).
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, EnumIter,
+)]
 pub enum Good {
     Berries,
     Fish,
@@ -39,39 +106,182 @@ pub enum Good {
 }
 
 impl Good {
+    /// Returns the category of this good.
+    pub fn category(&self) -> GoodCategory {
+        match self {
+            Good::Berries => GoodCategory::Food,
+            Good::Fish => GoodCategory::Food,
+            Good::Basket => GoodCategory::Tool,
+            Good::Spear => GoodCategory::Tool,
+            Good::Smoker => GoodCategory::Tool,
+            Good::Boat => GoodCategory::Vessel,
+            Good::Timber => GoodCategory::RawMaterial,
+            Good::Axe => GoodCategory::Tool,
+        }
+    }
+
     pub fn is_consumer(&self) -> bool {
+        matches!(
+            self.category(),
+            GoodCategory::Food | GoodCategory::Preserved
+        )
+    }
+
+    /// Returns the nutritional categories of a consumer good (empty for capital goods).
+    pub fn nutrition_categories(&self) -> Vec<NutritionCategory> {
         match self {
-            Good::Berries => true,
-            Good::Fish => true,
-            Good::Basket => false,
-            Good::Spear => false,
-            Good::Smoker => false,
-            Good::Boat => false,
-            Good::Timber => false,
-            Good::Axe => false,
+            Good::Berries => vec![NutritionCategory::Fruit],
+            Good::Fish => vec![NutritionCategory::Protein],
+            _ => vec![],
+        }
+    }
+
+    /// Returns the number of nutritional units provided by one unit of a consumer good
+    /// (zero for capital goods).
+    pub fn nutrition_value(&self) -> UInt {
+        match self {
+            Good::Berries => 1,
+            Good::Fish => 1,
+            _ => 0,
+        }
+    }
+
+    /// Returns the good from which this good is made by transformation, if any.
+    ///
+    /// Producing a transform good (e.g. a preserved form of a perishable good) converts every
+    /// available unit of its input good into one unit of the output good.
+    pub fn transforms_from(&self) -> Option<Good> {
+        self.transforms_from_with(&core_config().transforms)
+    }
+
+    /// Returns the good from which this good is made by transformation, if any, where the
+    /// transforms (keyed by output good) override the defaults.
+    pub fn transforms_from_with(&self, transforms: &HashMap<Good, Good>) -> Option<Good> {
+        transforms
+            .get(self)
+            .copied()
+            .or_else(|| self.default_transforms_from())
+    }
+
+    /// Returns the default good from which this good is made by transformation, if any.
+    pub fn default_transforms_from(&self) -> Option<Good> {
+        match self {
+            Good::Berries => None,
+            Good::Fish => None,
+            Good::Basket => None,
+            Good::Spear => None,
+            Good::Smoker => None,
+            Good::Boat => None,
+            Good::Timber => None,
+            Good::Axe => None,
         }
     }
 
     /// Returns true if this is a capital good that is a material.
     /// Materials are used up when used to produce lower order goods.
     pub fn is_material(&self) -> bool {
-        match self {
-            Good::Timber => true,
-            _ => false,
-        }
+        self.category() == GoodCategory::RawMaterial
     }
 
     /// Gets the default productivity
     /// **FOR GOODS THAT DO NOT REQUIRE MULTIPLE TIMESTEPS TO COMPLETE**.
     pub fn default_productivity(&self, stock: &Stock) -> Productivity {
+        self.default_productivity_with(stock, &[])
+    }
+
+    /// Returns the productivity of this good given the stock and access to the given shared
+    /// goods (see `SharedResources`), which count as tools but not as inputs held in stock.
+    pub fn default_productivity_with(&self, stock: &Stock, shared_tools: &[Good]) -> Productivity {
+        let config = core_config();
+        let build_time = self.multiple_timesteps_to_complete_with(&config.build_times);
+        self.configured_productivity(stock, shared_tools, build_time, &config)
+    }
+
+    /// Returns the productivity of this good as in `default_productivity_with`, given its
+    /// build time (None for goods produced in a single timestep) and the enabled goods, tool
+    /// boosts, materials and fuel of the given config.
+    pub fn configured_productivity(
+        &self,
+        stock: &Stock,
+        shared_tools: &[Good],
+        build_time: Option<UInt>,
+        config: &Config,
+    ) -> Productivity {
+        // Disabled goods cannot be produced.
+        if !config.enabled_goods.contains(self) {
+            return Productivity::None;
+        }
+        // A transform good is produced from every available unit of its input good.
+        if let Some(input) = self.transforms_from_with(&config.transforms) {
+            return match stock.count_units(&input) {
+                0 => Productivity::None,
+                qty => Productivity::Immediate(qty),
+            };
+        }
+        if !self.can_begin_build_with(stock, shared_tools, build_time, config) {
+            return Productivity::None;
+        }
+        // The inputs to a multi-step good were checked against the stock above.
+        if let Some(build_time) = build_time {
+            return Productivity::Delayed(build_time);
+        }
+        let tools: Vec<Good> = Good::iter()
+            .filter(|good| stock.contains(good) || shared_tools.contains(good))
+            .collect();
+        self.productivity_with_boosts(&tools, &config.tool_boosts)
+    }
+
+    /// Returns true if the stock holds sufficient units of each input to produce this good
+    /// (or to continue its production) through to completion.
+    pub fn can_begin_build(&self, stock: &Stock) -> bool {
+        let config = core_config();
+        let build_time = self.multiple_timesteps_to_complete_with(&config.build_times);
+        self.can_begin_build_with(stock, &[], build_time, &config)
+    }
+
+    /// Returns true if the stock, together with access to the given shared goods, holds
+    /// sufficient units of each input to produce this good through to completion, given
+    /// its build time (None for goods produced in a single timestep) and the materials and
+    /// fuel of the given config.
+    pub fn can_begin_build_with(
+        &self,
+        stock: &Stock,
+        shared_tools: &[Good],
+        build_time: Option<UInt>,
+        config: &Config,
+    ) -> bool {
+        // Production requires sufficient units of each input for the remaining timesteps.
+        let timesteps = self.remaining_timesteps(stock, build_time);
+        self.required_input_quantities_for(timesteps, config, stock)
+            .into_iter()
+            .all(|(input, quantity)| {
+                // A shared good stands in for a unit of a required tool, but not of a material.
+                let shared = (shared_tools.contains(&input) && !input.is_material()) as UInt;
+                stock.count_units(&input) + shared >= quantity
+            })
+    }
+
+    /// Returns the productivity of this good given access to the given goods (capital goods
+    /// and materials), without reference to a `Stock`.
+    pub fn productivity_with(&self, tools: &[Good]) -> Productivity {
+        self.productivity_with_boosts(tools, &core_config().tool_boosts)
+    }
+
+    /// Returns the productivity of this good given access to the given goods, where the
+    /// boost from each tool is as configured in the given overrides of the defaults.
+    pub fn productivity_with_boosts(
+        &self,
+        tools: &[Good],
+        boosts: &HashMap<Good, HashMap<Good, UInt>>,
+    ) -> Productivity {
         match self.multiple_timesteps_to_complete() {
             Some(time_to_complete) => {
                 // If a good takes multiple timesteps to complete and requires inputs
                 // at every timestep, the productivity is zero unless they already
                 // have enough input materials.
                 for required_input in self.required_inputs() {
-                    // IMP TODO: check sufficient quantity of inputs.
-                    if !stock.contains(&required_input) {
+                    // Quantities are checked against the stock in `default_productivity`.
+                    if !tools.contains(&required_input) {
                         return Productivity::None;
                     }
                 }
@@ -79,39 +289,78 @@ impl Good {
             }
             None => {} // Do nothing. Contiue to following match statement.
         }
+        // Of the available tools, the first in declaration order that boosts this good is
+        // used (so a spear takes precedence over a boat in fishing).
+        let boosted = Good::iter()
+            .filter(|tool| tools.contains(tool))
+            .find_map(|tool| self.boost_from_with(&tool, boosts));
+        if let Some(productivity) = boosted {
+            return productivity;
+        }
         match self {
-            Good::Berries => {
-                // Productivity of berries is increased by access to a basket.
-                if stock.contains(&Good::Basket) {
-                    return Productivity::Immediate(8);
-                }
-                Productivity::Immediate(4)
-            }
-            Good::Basket => return Productivity::Immediate(1),
-            Good::Fish => {
-                // Productivity of fish is increased by access to a spear or a boat.
-                if stock.contains(&Good::Spear) {
-                    return Productivity::Immediate(10);
-                }
-                if stock.contains(&Good::Boat) {
-                    return Productivity::Immediate(20);
-                }
-                Productivity::Immediate(2)
-            }
+            Good::Berries => Productivity::Immediate(4),
+            Good::Basket => Productivity::Immediate(1),
+            Good::Fish => Productivity::Immediate(2),
             Good::Spear => Productivity::Immediate(1),
             Good::Smoker => panic!("Smoker takes multiple timesteps to complete"),
             Good::Boat => panic!("Boat takes multiple timesteps to complete"),
-            Good::Timber => {
-                // Productivity of timber is dependent on access to an axe.
-                if stock.contains(&Good::Axe) {
-                    return Productivity::Immediate(2);
-                }
-                return Productivity::None;
-            }
+            // Productivity of timber is dependent on access to an axe.
+            Good::Timber => Productivity::None,
             Good::Axe => panic!("Axe takes multiple timesteps to complete"),
+            // Productivity of a transform good depends on the stock of its input.
+        }
+    }
+
+    /// Returns the productivity of this good when produced using the given tool, or None
+    /// if the tool does not boost its production.
+    pub fn boost_from(&self, tool: &Good) -> Option<Productivity> {
+        self.boost_from_with(tool, &core_config().tool_boosts)
+    }
+
+    /// Returns the productivity of this good when produced using the given tool, where the
+    /// boosts (keyed by tool and then by good) override the defaults.
+    pub fn boost_from_with(
+        &self,
+        tool: &Good,
+        boosts: &HashMap<Good, HashMap<Good, UInt>>,
+    ) -> Option<Productivity> {
+        let default = self.default_boost_from(tool)?;
+        let quantity = boosts
+            .get(tool)
+            .and_then(|boosts| boosts.get(self))
+            .copied()
+            .unwrap_or(default);
+        Some(Productivity::Immediate(quantity))
+    }
+
+    /// Returns the default units of this good produced per timestep using the given tool,
+    /// or None if the tool does not boost its production.
+    pub fn default_boost_from(&self, tool: &Good) -> Option<UInt> {
+        match (self, tool) {
+            // Productivity of berries is increased by access to a basket.
+            (Good::Berries, Good::Basket) => Some(8),
+            // Productivity of fish is increased by access to a spear or a boat.
+            (Good::Fish, Good::Spear) => Some(10),
+            (Good::Fish, Good::Boat) => Some(20),
+            (Good::Timber, Good::Axe) => Some(2),
+            _ => None,
         }
     }
 
+    /// Returns the single capital good that most increases the productivity of this good,
+    /// or None if no capital good improves on the productivity without any capital goods.
+    pub fn best_enabling_good(&self) -> Option<Good> {
+        let baseline = self.productivity_with(&[]).per_unit_time().unwrap_or(0.0);
+        Good::iter()
+            .filter(|good| self.is_produced_using(good))
+            .filter_map(|good| {
+                let productivity = self.productivity_with(&[good]).per_unit_time()?;
+                (productivity > baseline).then_some((good, productivity))
+            })
+            .max_by(|(_, x), (_, y)| x.partial_cmp(y).unwrap())
+            .map(|(good, _)| good)
+    }
+
     /// Returns true if this good is produced using the given (higher order) good.
     pub fn is_produced_using(&self, good: &Good) -> bool {
         match self {
@@ -144,6 +393,40 @@ impl Good {
         self.is_produced_using(good) || self.is_improved_using(good)
     }
 
+    /// Returns the edges of the production graph as (input good, output good) pairs,
+    /// each with the kind of dependency of the output on the input.
+    pub fn production_edges() -> Vec<(Good, Good, EdgeKind)> {
+        Good::iter()
+            .flat_map(|output| {
+                Good::iter()
+                    .filter(move |input| output.is_downsteam_of(input))
+                    .map(move |input| {
+                        let kind = if output.required_inputs().contains(&input) {
+                            EdgeKind::Required
+                        } else {
+                            EdgeKind::Enhancing
+                        };
+                        (input, output, kind)
+                    })
+            })
+            .collect()
+    }
+
+    /// Returns the production order of this good: 0 for consumer goods, and otherwise one
+    /// more than the highest order of the goods downstream of it (e.g. 1 for a Spear, used
+    /// to produce Fish, and 3 for an Axe, used to produce Timber).
+    pub fn order(&self) -> u32 {
+        if self.is_consumer() {
+            return 0;
+        }
+        Good::iter()
+            .filter(|good| good.is_downsteam_of(self))
+            .map(|good| good.order())
+            .max()
+            .unwrap_or(0)
+            + 1
+    }
+
     pub fn lifetime_improvement_increment(&self, improved_good: &Good) -> u32 {
         match self {
             // Smoker increases lifetime by 20 time units.
@@ -172,12 +455,192 @@ impl Good {
         }
     }
 
-    // TODO: This should be different for each agent,
-    // i.e. agents could have different rates of productivity
-    // for capital goods, as they do for consumer goods.
+    /// Returns the quantity of each required input needed to produce one unit of this good.
+    /// Materials are needed for every timestep of production (see `total_material_cost`),
+    /// while any other required input is needed as a single unit.
+    pub fn required_input_quantities(&self) -> Vec<(Good, UInt)> {
+        self.required_input_quantities_with(&core_config().build_times)
+    }
+
+    /// Returns the quantity of each required input needed to produce one unit of this good,
+    /// given build times overriding the defaults. The threshold of materials needed to start
+    /// production therefore always matches the build time.
+    pub fn required_input_quantities_with(
+        &self,
+        build_times: &HashMap<Good, UInt>,
+    ) -> Vec<(Good, UInt)> {
+        let timesteps = self.multiple_timesteps_to_complete_with(build_times);
+        self.required_input_quantities_for(
+            timesteps.unwrap_or(1),
+            &core_config(),
+            &Stock::default(),
+        )
+    }
+
+    /// Returns the quantity of each required input needed for the given number of
+    /// timesteps of production of this good from the stock, under the given config.
+    fn required_input_quantities_for(
+        &self,
+        timesteps: UInt,
+        config: &Config,
+        stock: &Stock,
+    ) -> Vec<(Good, UInt)> {
+        let materials = self.materials_per_step_with(config, stock);
+        self.inputs(config, stock)
+            .into_iter()
+            .map(|input| {
+                let quantity = materials
+                    .iter()
+                    .find(|(material, _)| *material == input)
+                    .map_or(1, |(_, quantity)| quantity * timesteps);
+                (input, quantity)
+            })
+            .collect()
+    }
+
+    /// Returns the first input required to produce this good that is missing from the stock,
+    /// either because it is absent or (for materials) because too few units are available
+    /// to complete production.
+    pub fn missing_input(&self, stock: &Stock) -> Option<Good> {
+        let config = core_config();
+        let build_time = self.multiple_timesteps_to_complete_with(&config.build_times);
+        self.missing_input_with(stock, build_time, &config)
+    }
+
+    /// Returns the first input required to produce this good that is missing from the stock,
+    /// given its build time (None for goods produced in a single timestep) and the materials
+    /// and fuel of the given config.
+    pub fn missing_input_with(
+        &self,
+        stock: &Stock,
+        build_time: Option<UInt>,
+        config: &Config,
+    ) -> Option<Good> {
+        let timesteps = self.remaining_timesteps(stock, build_time);
+        self.required_input_quantities_for(timesteps, config, stock)
+            .into_iter()
+            .find(|(input, quantity)| stock.count_units(input) < *quantity)
+            .map(|(input, _)| input)
+    }
+
+    /// Returns the number of timesteps of production of this good remaining, given the stock
+    /// (which may hold a partially complete unit) and the build time.
+    fn remaining_timesteps(&self, stock: &Stock, build_time: Option<UInt>) -> UInt {
+        match stock.get_partial(*self) {
+            Some(partial) => partial.time_to_completion,
+            None => build_time.unwrap_or(1),
+        }
+    }
+
+    /// Returns the required inputs of this good, followed by any other materials used up in
+    /// its production from the stock (i.e. fuel).
+    fn inputs(&self, config: &Config, stock: &Stock) -> Vec<Good> {
+        let mut inputs = self.required_inputs();
+        for (material, _) in self.materials_per_step_with(config, stock) {
+            if !inputs.contains(&material) {
+                inputs.push(material);
+            }
+        }
+        inputs
+    }
+
+    /// Returns the units of each material used up by one timestep of production of this good,
+    /// including any timber burned as fuel under the configured fuel requirements.
+    ///
+    /// The total material cost of a good is this quantity for every timestep of its production
+    /// (see `total_material_cost`), so a Smoker costs 3 Timber and a Boat costs 10 Timber.
+    pub fn materials_per_step(&self) -> Vec<(Good, UInt)> {
+        self.materials_per_step_with(&core_config(), &Stock::default())
+    }
+
+    /// Returns the units of each material used up by one timestep of production of this good
+    /// from the stock, given the materials and the units of timber burned as fuel per timestep
+    /// of producing each good in the given config. Fuel for a good that a tool improves (as a
+    /// Smoker improves fish) is burned only when the stock holds such a tool.
+    pub fn materials_per_step_with(&self, config: &Config, stock: &Stock) -> Vec<(Good, UInt)> {
+        let mut materials = self.building_materials_per_step_with(&config.materials);
+        let improving_tools: Vec<Good> = Good::iter()
+            .filter(|tool| self.is_improved_using(tool))
+            .collect();
+        let burns_fuel =
+            improving_tools.is_empty() || improving_tools.iter().any(|tool| stock.contains(tool));
+        if let Some(&quantity) = config
+            .fuel
+            .get(self)
+            .filter(|quantity| burns_fuel && **quantity > 0)
+        {
+            match materials
+                .iter_mut()
+                .find(|(material, _)| *material == Good::Timber)
+            {
+                Some((_, total)) => *total += quantity,
+                None => materials.push((Good::Timber, quantity)),
+            }
+        }
+        materials
+    }
+
+    /// Returns the units of each material built into this good at each timestep of production,
+    /// where the materials (keyed by good and then by material) override the defaults.
+    fn building_materials_per_step_with(
+        &self,
+        materials: &HashMap<Good, HashMap<Good, UInt>>,
+    ) -> Vec<(Good, UInt)> {
+        match materials.get(self) {
+            // Listed in declaration order, which the map does not preserve.
+            Some(materials) => Good::iter()
+                .filter_map(|material| materials.get(&material).map(|qty| (material, *qty)))
+                .collect(),
+            None => self.default_building_materials_per_step(),
+        }
+    }
+
+    /// Returns the default units of each material built into this good at each timestep of
+    /// production.
+    fn default_building_materials_per_step(&self) -> Vec<(Good, UInt)> {
+        match self {
+            Good::Berries => Vec::new(),
+            Good::Fish => Vec::new(),
+            Good::Basket => Vec::new(),
+            Good::Spear => Vec::new(),
+            Good::Smoker => vec![(Good::Timber, 1)],
+            Good::Boat => vec![(Good::Timber, 1)],
+            Good::Timber => Vec::new(),
+            Good::Axe => Vec::new(),
+        }
+    }
+
+    /// Returns the total units of each material used up in producing one unit of this good.
+    pub fn total_material_cost(&self) -> Vec<(Good, UInt)> {
+        let timesteps = self.multiple_timesteps_to_complete().unwrap_or(1);
+        self.materials_per_step()
+            .into_iter()
+            .map(|(material, quantity)| (material, quantity * timesteps))
+            .collect()
+    }
+
     /// Returns the number of timesteps taken to complete production of this good,
     /// or None if one or more units of the good can be produced in one time unit.
+    ///
+    /// Defaults may be overridden by the configured build times. Agents may also have
+    /// their own build times (see `Agent::build_time`).
     pub fn multiple_timesteps_to_complete(&self) -> Option<UInt> {
+        self.multiple_timesteps_to_complete_with(&core_config().build_times)
+    }
+
+    /// Returns the number of timesteps taken to complete production of this good, given
+    /// build times overriding the defaults. Goods produced in one time unit are unaffected.
+    pub fn multiple_timesteps_to_complete_with(
+        &self,
+        build_times: &HashMap<Good, UInt>,
+    ) -> Option<UInt> {
+        let default = self.default_timesteps_to_complete()?;
+        Some(build_times.get(self).copied().unwrap_or(default))
+    }
+
+    /// Returns the default number of timesteps taken to complete production of this good,
+    /// or None if one or more units of the good can be produced in one time unit.
+    pub fn default_timesteps_to_complete(&self) -> Option<UInt> {
         match self {
             Good::Berries => None,
             Good::Fish => None,
@@ -220,7 +683,7 @@ pub struct GoodsUnit {
 
 // For units of goods, each has a lifetime remaining value before it is destroyed.
 // For capital goods, (e.g. spear, timber), each has a number of uses remaining before it is destroyed.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct GoodsUnitLevel {
     pub good: Good,
     pub remaining_lifetime: RemainingLevel,
@@ -234,17 +697,61 @@ impl GoodsUnitLevel {
         }
     }
 
+    /// Iterates over all goods unit levels that can occur in a discretised stock.
+    /// Capital goods are not bucketed by remaining lifetime (see `RemainingLevel::from_goods_unit`).
     pub fn iter() -> impl Iterator<Item = GoodsUnitLevel> {
-        Good::iter().flat_map(|good| {
-            RemainingLevel::iter().map(move |remaining_lifetime| GoodsUnitLevel {
-                good,
-                remaining_lifetime,
-            })
+        Self::iter_with(&core_config().enabled_goods)
+    }
+
+    /// Iterates over all goods unit levels for the given enabled goods.
+    pub fn iter_with(
+        enabled_goods: &HashSet<Good>,
+    ) -> impl Iterator<Item = GoodsUnitLevel> + use<> {
+        let goods: Vec<Good> = Good::iter()
+            .filter(|good| enabled_goods.contains(good))
+            .collect();
+        goods.into_iter().flat_map(|good| {
+            RemainingLevel::iter()
+                .filter(move |remaining_lifetime| {
+                    good.is_consumer() || *remaining_lifetime == RemainingLevel::Low
+                })
+                .map(move |remaining_lifetime| GoodsUnitLevel {
+                    good,
+                    remaining_lifetime,
+                })
         })
     }
 }
 
 impl GoodsUnit {
+    /// Returns the number of nutritional units provided by this unit, under the given model of
+    /// spoilage.
+    pub fn nutrition_value(&self, spoilage: &SpoilageModel) -> f32 {
+        let value = self.good.nutrition_value() as f32;
+        match spoilage {
+            SpoilageModel::CliffEdge => value,
+            SpoilageModel::Linear => {
+                let lifetime = GoodsUnit::new(&self.good).remaining_lifetime as f32;
+                // Improved goods may outlast a newly-produced unit, but provide no extra value.
+                value * f32::min(1.0, self.remaining_lifetime as f32 / lifetime)
+            }
+        }
+    }
+
+    /// Returns a newly-produced unit of the given good, with the lifetime of a perishable
+    /// (consumer or material) good shifted uniformly at random by up to `jitter` days.
+    /// The lifetime is at least one day.
+    pub fn new_with_jitter<R: Rng + ?Sized>(good: &Good, jitter: UInt, rng: &mut R) -> Self {
+        let mut goods_unit = GoodsUnit::new(good);
+        if jitter > 0 && (good.is_consumer() || good.is_material()) {
+            let jitter = jitter as i64;
+            let lifetime =
+                goods_unit.remaining_lifetime as i64 + rng.random_range(-jitter..=jitter);
+            goods_unit.remaining_lifetime = lifetime.max(1) as UInt;
+        }
+        goods_unit
+    }
+
     /// Returns a newly-produced unit of the given good.
     pub fn new(good: &Good) -> Self {
         match good {
@@ -287,9 +794,11 @@ impl GoodsUnit {
     pub fn step_forward(&self, action: Action) -> Option<Self> {
         // Note: handles materials differently. They are capital goods but can only be used once.
         // The remaining_lifetime of a material is its time before expiry (like a consumer good).
+        // Units of a material used in production are removed from the stock at the time of
+        // production (see `Stock::consume_material_inputs`), so any remaining units simply age.
 
-        match self.good.is_consumer() {
-            // If this good exists in the stock and is a consumer good, degrade it.
+        match self.good.is_consumer() || self.good.is_material() {
+            // If this good exists in the stock and is a consumer good or material, degrade it.
             true => {
                 if self.remaining_lifetime > 1 {
                     return Some(GoodsUnit {
@@ -303,51 +812,21 @@ impl GoodsUnit {
             // If this is a capital good and the action makes use of it, degrade its
             // remaining lifetime. Otherwise return it unchanged.
             false => match action {
-                Action::ProduceGood(produced_good) => {
+                Action::ProduceGood(produced_good) | Action::ProduceGoodFor(produced_good, _) => {
                     if produced_good.is_produced_using(&self.good) {
-                        match &self.good.is_material() {
-                            true => {
-                                // If the good is a material, and is used in production, it cannot
-                                // be used again, so return None.
-                                return None;
-                            }
-                            false => {
-                                if self.remaining_lifetime > 1 {
-                                    return Some(GoodsUnit {
-                                        good: self.good,
-                                        remaining_lifetime: self.remaining_lifetime - 1,
-                                    });
-                                }
-                                // If the remaining_lifetime is 0 (after the step), return None.
-                                return None;
-                            }
-                        }
-                    }
-                    // If the good is a material but is *not* used in production, reduce its
-                    // remaining lifetime (as if it were a consumer good).
-                    if self.good.is_material() {
-                        return Some(GoodsUnit {
-                            good: self.good,
-                            remaining_lifetime: self.remaining_lifetime - 1,
-                        });
-                    }
-                    // If the capital good is not used in production (as is not a material),
-                    // it is unchanged.
-                    Some(self.clone())
-                }
-                Action::Leisure => {
-                    match self.good.is_material() {
-                        true => {
-                            // If the good is a material but is *not* used in production, reduce its
-                            // remaining lifetime (as if it were a consumer good).
+                        if self.remaining_lifetime > 1 {
                             return Some(GoodsUnit {
                                 good: self.good,
                                 remaining_lifetime: self.remaining_lifetime - 1,
                             });
                         }
-                        false => Some(self.clone()),
+                        // If the remaining_lifetime is 0 (after the step), return None.
+                        return None;
                     }
+                    // If the capital good is not used in production, it is unchanged.
+                    Some(*self)
                 }
+                Action::Leisure | Action::Consume => Some(*self),
             },
         }
     }
@@ -357,17 +836,25 @@ impl GoodsUnit {
 pub struct PartialGoodsUnit {
     pub good: Good,
     pub time_to_completion: UInt, // Number of days required to complete production.
+    pub build_time: UInt,         // Number of days to complete production from the start.
 }
 
 impl PartialGoodsUnit {
     /// Returns a partially complete unit of a good.
-    pub fn new(good: &Good) -> Option<Self> {
-        match good.multiple_timesteps_to_complete() {
-            Some(time) => Some(PartialGoodsUnit {
+    pub fn new(good: &Good) -> Result<Self, StockError> {
+        Self::new_with_build_time(good, good.multiple_timesteps_to_complete())
+    }
+
+    /// Returns a partially complete unit of a good with the given build time, which
+    /// is None for goods produced in a single timestep.
+    pub fn new_with_build_time(good: &Good, build_time: Option<UInt>) -> Result<Self, StockError> {
+        match build_time {
+            Some(time) => Ok(PartialGoodsUnit {
                 good: good.clone(),
                 time_to_completion: time,
+                build_time: time,
             }),
-            None => None,
+            None => Err(StockError::SingleStepGood(*good)),
         }
     }
 
@@ -375,13 +862,25 @@ impl PartialGoodsUnit {
         self.time_to_completion = self.time_to_completion - 1
     }
 
+    /// Returns true if production of this unit is complete.
+    pub fn is_complete(&self) -> bool {
+        self.time_to_completion == 0
+    }
+
     // Step forward this partially complete goods unit and penalise any
-    // discontinuity in the production process.
+    // discontinuity in the production process. Returns None if the unit
+    // is abandoned, i.e. all progress on it has been lost.
     pub fn step_forward(&self, action: Action) -> Option<PartialGoodsUnit> {
+        self.step_forward_with(action, core_config().partial_abandon_grace)
+    }
+
+    /// Steps forward this partially complete goods unit, abandoning it if its time to
+    /// completion has already reached the build time plus the given number of grace days.
+    pub fn step_forward_with(&self, action: Action, grace: UInt) -> Option<PartialGoodsUnit> {
         // If the action is to continue production, return the partial good
         // unchanged (as production was incremented when the agent acted).
         match action {
-            Action::ProduceGood(good) => {
+            Action::ProduceGood(good) | Action::ProduceGoodFor(good, _) => {
                 if good == self.good {
                     return Some(*self);
                 }
@@ -391,17 +890,12 @@ impl PartialGoodsUnit {
         // If the action is *not* to continue production, extend
         // the remaining time to completion by 1 time unit.
         let time_to_completion = self.time_to_completion + 1;
-        let max_time_to_completion = self
-            .good
-            .multiple_timesteps_to_complete()
-            .expect("PartialGoodsUnit must take multiple timesteps to complete");
-
-        if self.time_to_completion == max_time_to_completion {
+        if self.time_to_completion >= self.build_time + grace {
             return None;
         }
         Some(PartialGoodsUnit {
-            good: self.good,
             time_to_completion: time_to_completion,
+            ..*self
         })
     }
 }
@@ -411,6 +905,275 @@ mod tests {
     use super::*;
     use crate::goods::{Good, GoodsUnit};
 
+    #[test]
+    fn test_category() {
+        let expected = [
+            (Good::Berries, GoodCategory::Food),
+            (Good::Fish, GoodCategory::Food),
+            (Good::Basket, GoodCategory::Tool),
+            (Good::Spear, GoodCategory::Tool),
+            (Good::Smoker, GoodCategory::Tool),
+            (Good::Boat, GoodCategory::Vessel),
+            (Good::Timber, GoodCategory::RawMaterial),
+            (Good::Axe, GoodCategory::Tool),
+        ];
+        assert_eq!(expected.len(), Good::iter().count());
+        for (good, category) in expected {
+            assert_eq!(good.category(), category);
+        }
+        // Consumer goods and materials are consistent with their categories.
+        let consumers: Vec<Good> = Good::iter().filter(|good| good.is_consumer()).collect();
+        assert_eq!(consumers, vec![Good::Berries, Good::Fish]);
+        let materials: Vec<Good> = Good::iter().filter(|good| good.is_material()).collect();
+        assert_eq!(materials, vec![Good::Timber]);
+    }
+
+    #[test]
+    fn test_nutrition_categories() {
+        assert_eq!(
+            Good::Fish.nutrition_categories(),
+            vec![NutritionCategory::Protein]
+        );
+        // Every consumer good provides some category of nutrition.
+        for good in Good::iter() {
+            assert_eq!(good.is_consumer(), !good.nutrition_categories().is_empty());
+            assert_eq!(good.is_consumer(), good.nutrition_value() > 0);
+        }
+    }
+
+    #[test]
+    fn test_partial_new() {
+        assert!(PartialGoodsUnit::new(&Good::Boat).is_ok());
+        assert_eq!(
+            PartialGoodsUnit::new(&Good::Berries),
+            Err(StockError::SingleStepGood(Good::Berries))
+        );
+    }
+
+    #[test]
+    fn test_new_with_jitter() {
+        use rand::{SeedableRng, rngs::StdRng};
+        let mut rng = StdRng::seed_from_u64(42);
+        let lifetime = GoodsUnit::new(&Good::Berries).remaining_lifetime;
+        for _ in 0..20 {
+            let goods_unit = GoodsUnit::new_with_jitter(&Good::Berries, 2, &mut rng);
+            assert!(goods_unit.remaining_lifetime.abs_diff(lifetime) <= 2);
+        }
+        // Capital goods are not jittered.
+        assert_eq!(
+            GoodsUnit::new_with_jitter(&Good::Spear, 2, &mut rng),
+            GoodsUnit::new(&Good::Spear)
+        );
+        // Lifetimes are at least one day.
+        assert!(GoodsUnit::new_with_jitter(&Good::Fish, 5, &mut rng).remaining_lifetime >= 1);
+    }
+
+    #[test]
+    fn test_missing_input() {
+        let mut stock = Stock::default();
+        assert_eq!(Good::Smoker.missing_input(&stock), Some(Good::Timber));
+        assert_eq!(Good::Timber.missing_input(&stock), Some(Good::Axe));
+        assert_eq!(Good::Berries.missing_input(&stock), None);
+        // Timber for a single timestep is not enough to complete the smoker.
+        stock.add(GoodsUnit::new(&Good::Timber), 1).unwrap();
+        assert_eq!(Good::Smoker.missing_input(&stock), Some(Good::Timber));
+        let build_time = Good::Smoker.multiple_timesteps_to_complete().unwrap();
+        stock
+            .add(GoodsUnit::new(&Good::Timber), build_time - 1)
+            .unwrap();
+        assert_eq!(Good::Smoker.missing_input(&stock), None);
+
+        // Once production is under way, only the remaining timesteps need inputs.
+        let mut stock = Stock::default();
+        let mut partial = PartialGoodsUnit::new(&Good::Smoker).unwrap();
+        for _ in 1..build_time {
+            partial.increment_production();
+        }
+        stock.add_partial(partial).unwrap();
+        assert_eq!(Good::Smoker.missing_input(&stock), Some(Good::Timber));
+        stock.add(GoodsUnit::new(&Good::Timber), 1).unwrap();
+        assert_eq!(Good::Smoker.missing_input(&stock), None);
+    }
+
+    #[test]
+    fn test_required_input_quantities() {
+        assert_eq!(
+            Good::Smoker.required_input_quantities(),
+            vec![(Good::Timber, 3)]
+        );
+        assert_eq!(
+            Good::Timber.required_input_quantities(),
+            vec![(Good::Axe, 1)]
+        );
+        assert!(Good::Berries.required_input_quantities().is_empty());
+
+        // The timber needed for a boat follows its build time.
+        assert_eq!(
+            Good::Boat.required_input_quantities(),
+            vec![(Good::Timber, 10)]
+        );
+        let build_times = HashMap::from([(Good::Boat, 6)]);
+        assert_eq!(
+            Good::Boat.required_input_quantities_with(&build_times),
+            vec![(Good::Timber, 6)]
+        );
+        assert_eq!(
+            Good::Smoker.required_input_quantities_with(&build_times),
+            vec![(Good::Timber, 3)]
+        );
+
+        // A smoker cannot be started with too little timber to complete it.
+        let mut stock = Stock::default();
+        stock.add(GoodsUnit::new(&Good::Timber), 1).unwrap();
+        assert_eq!(
+            Good::Smoker.default_productivity(&stock),
+            Productivity::None
+        );
+        stock.add(GoodsUnit::new(&Good::Timber), 2).unwrap();
+        assert_eq!(
+            Good::Smoker.default_productivity(&stock),
+            Productivity::Delayed(3)
+        );
+
+        // Once in progress, only the timber for the remaining timesteps is required.
+        let mut partial = PartialGoodsUnit::new(&Good::Smoker).unwrap();
+        partial.increment_production();
+        partial.increment_production();
+        let mut stock = Stock::default();
+        stock.add(GoodsUnit::new(&Good::Timber), 1).unwrap();
+        stock.add_partial(partial).unwrap();
+        assert_eq!(
+            Good::Smoker.default_productivity(&stock),
+            Productivity::Delayed(3)
+        );
+    }
+
+    #[test]
+    fn test_fuel() {
+        // This tree has no smoked goods, so fuel is illustrated with fish and the smoker.
+        let config = Config {
+            fuel: HashMap::from([(Good::Fish, 2), (Good::Smoker, 1)]),
+            ..Config::default()
+        };
+        let smoker = Stock::from_goods(&[(Good::Smoker, 1)]);
+        assert_eq!(
+            Good::Fish.materials_per_step_with(&config, &smoker),
+            vec![(Good::Timber, 2)]
+        );
+        // Fish are only smoked, burning fuel, with a Smoker.
+        assert!(
+            Good::Fish
+                .materials_per_step_with(&config, &Stock::default())
+                .is_empty()
+        );
+        // Fuel is added to the timber used as a building material.
+        assert_eq!(
+            Good::Smoker.materials_per_step_with(&config, &Stock::default()),
+            vec![(Good::Timber, 2)]
+        );
+        assert!(
+            Good::Berries
+                .materials_per_step_with(&config, &smoker)
+                .is_empty()
+        );
+        // Without configured fuel, production burns no timber.
+        assert!(Good::Fish.materials_per_step().is_empty());
+        assert!(
+            !Good::Fish
+                .inputs(&Config::default(), &smoker)
+                .contains(&Good::Timber)
+        );
+        assert!(Good::Fish.inputs(&config, &smoker).contains(&Good::Timber));
+    }
+
+    #[test]
+    fn test_order() {
+        assert_eq!(Good::Berries.order(), 0);
+        assert_eq!(Good::Fish.order(), 0);
+        assert_eq!(Good::Spear.order(), 1);
+        assert_eq!(Good::Basket.order(), 1);
+        assert_eq!(Good::Smoker.order(), 1);
+        assert_eq!(Good::Boat.order(), 1);
+        assert_eq!(Good::Timber.order(), 2);
+        // An Axe is used to produce Timber, so is one order higher.
+        assert_eq!(Good::Axe.order(), Good::Timber.order() + 1);
+    }
+
+    #[test]
+    fn test_productivity_with() {
+        assert_eq!(
+            Good::Fish.productivity_with(&[]),
+            Productivity::Immediate(2)
+        );
+        assert_eq!(
+            Good::Fish.productivity_with(&[Good::Spear]),
+            Productivity::Immediate(10)
+        );
+        // A spear takes precedence over a boat when both are available.
+        assert_eq!(
+            Good::Fish.productivity_with(&[Good::Spear, Good::Boat]),
+            Productivity::Immediate(10)
+        );
+        assert_eq!(
+            Good::Fish.productivity_with(&[Good::Boat, Good::Spear]),
+            Productivity::Immediate(10)
+        );
+        assert_eq!(
+            Good::Fish.productivity_with(&[Good::Boat]),
+            Productivity::Immediate(20)
+        );
+        assert_eq!(Good::Timber.productivity_with(&[]), Productivity::None);
+        assert_eq!(Good::Boat.productivity_with(&[]), Productivity::None);
+        assert_eq!(
+            Good::Boat.productivity_with(&[Good::Timber]),
+            Productivity::Delayed(10)
+        );
+    }
+
+    #[test]
+    fn test_boost_from() {
+        assert_eq!(
+            Good::Fish.boost_from(&Good::Spear),
+            Some(Productivity::Immediate(10))
+        );
+        assert_eq!(Good::Fish.boost_from(&Good::Smoker), None);
+        assert_eq!(Good::Berries.boost_from(&Good::Spear), None);
+
+        // Changing the boost from a spear changes the productivity of fish with a spear.
+        let boosts = HashMap::from([(Good::Spear, HashMap::from([(Good::Fish, 15)]))]);
+        assert_eq!(
+            Good::Fish.boost_from_with(&Good::Spear, &boosts),
+            Some(Productivity::Immediate(15))
+        );
+        assert_eq!(
+            Good::Fish.productivity_with_boosts(&[Good::Spear], &boosts),
+            Productivity::Immediate(15)
+        );
+        // The spear still takes precedence over the boat.
+        assert_eq!(
+            Good::Fish.productivity_with_boosts(&[Good::Spear, Good::Boat], &boosts),
+            Productivity::Immediate(15)
+        );
+        let boosts = HashMap::from([(Good::Spear, HashMap::from([(Good::Fish, 30)]))]);
+        assert_eq!(
+            Good::Fish.productivity_with_boosts(&[Good::Spear, Good::Boat], &boosts),
+            Productivity::Immediate(30)
+        );
+        assert_eq!(
+            Good::Fish.productivity_with_boosts(&[], &boosts),
+            Productivity::Immediate(2)
+        );
+    }
+
+    #[test]
+    fn test_best_enabling_good() {
+        assert_eq!(Good::Fish.best_enabling_good(), Some(Good::Boat));
+        assert_eq!(Good::Berries.best_enabling_good(), Some(Good::Basket));
+        assert_eq!(Good::Timber.best_enabling_good(), Some(Good::Axe));
+        assert_eq!(Good::Boat.best_enabling_good(), Some(Good::Timber));
+        assert_eq!(Good::Spear.best_enabling_good(), None);
+    }
+
     #[test]
     fn test_step_forward() {
         // Test with a consumer good.
@@ -446,9 +1209,11 @@ mod tests {
         let good = good.step_forward(action).unwrap();
         assert_eq!(good.remaining_lifetime, 97);
 
+        // Units of a material used in production are removed from the stock at the time of
+        // production, so the remaining units simply age.
         let action = Action::ProduceGood(Good::Boat);
-        let good = good.step_forward(action);
-        assert!(good.is_none());
+        let good = good.step_forward(action).unwrap();
+        assert_eq!(good.remaining_lifetime, 96);
 
         // Test with a non-material capital good.
         let good = GoodsUnit {
@@ -469,4 +1234,95 @@ mod tests {
         let good = good.step_forward(action).unwrap();
         assert_eq!(good.remaining_lifetime, 4);
     }
+
+    #[test]
+    fn test_production_edges() {
+        let edges: HashSet<_> = Good::production_edges().into_iter().collect();
+        let expected = HashSet::from([
+            (Good::Basket, Good::Berries, EdgeKind::Enhancing),
+            (Good::Spear, Good::Fish, EdgeKind::Enhancing),
+            (Good::Boat, Good::Fish, EdgeKind::Enhancing),
+            (Good::Smoker, Good::Fish, EdgeKind::Enhancing),
+            (Good::Timber, Good::Smoker, EdgeKind::Required),
+            (Good::Timber, Good::Boat, EdgeKind::Required),
+            (Good::Axe, Good::Timber, EdgeKind::Required),
+        ]);
+        assert_eq!(edges, expected);
+    }
+
+    #[test]
+    fn test_total_material_cost() {
+        assert_eq!(Good::Smoker.total_material_cost(), vec![(Good::Timber, 3)]);
+        assert_eq!(Good::Boat.total_material_cost(), vec![(Good::Timber, 10)]);
+        assert!(Good::Berries.total_material_cost().is_empty());
+    }
+
+    #[test]
+    fn test_productivity_with_noise() {
+        use rand::{SeedableRng, rngs::StdRng};
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let productivity = Productivity::Immediate(8);
+        assert_eq!(productivity.with_noise(0.0, &mut rng), productivity);
+        assert_eq!(
+            Productivity::Delayed(3).with_noise(0.5, &mut rng),
+            Productivity::Delayed(3)
+        );
+
+        // Noisy yields vary, but their mean matches the noiseless quantity.
+        let yields: Vec<UInt> = (0..10000)
+            .map(|_| match productivity.with_noise(0.5, &mut rng) {
+                Productivity::Immediate(qty) => qty,
+                _ => unreachable!(),
+            })
+            .collect();
+        assert!(yields.iter().any(|qty| *qty < 8));
+        assert!(yields.iter().any(|qty| *qty > 8));
+        assert!(yields.iter().all(|qty| (4..=12).contains(qty)));
+        let mean = yields.iter().sum::<UInt>() as f32 / yields.len() as f32;
+        assert!((mean - 8.0).abs() < 0.1, "mean yield {}", mean);
+    }
+
+    #[test]
+    fn test_partial_step_forward() {
+        // One step into the production of an axe.
+        let mut partial = PartialGoodsUnit::new(&Good::Axe).unwrap();
+        partial.increment_production();
+        assert_eq!(partial.time_to_completion, 1);
+
+        // Continuing production leaves the unit unchanged.
+        let continued = partial.step_forward(Action::ProduceGood(Good::Axe));
+        assert_eq!(continued, Some(partial));
+
+        // Any other action loses progress, abandoning the unit once no progress remains.
+        let idle = partial.step_forward(Action::Leisure).unwrap();
+        assert_eq!(idle.time_to_completion, 2);
+        assert_eq!(idle.step_forward(Action::Leisure), None);
+    }
+
+    #[test]
+    fn test_partial_abandon_grace() {
+        let mut partial = PartialGoodsUnit::new(&Good::Axe).unwrap();
+        partial.increment_production();
+
+        // Without grace, the axe is abandoned on the idle day after all progress is lost.
+        let idle = partial.step_forward_with(Action::Leisure, 0).unwrap();
+        assert_eq!(idle.time_to_completion, 2);
+        assert_eq!(idle.step_forward_with(Action::Leisure, 0), None);
+
+        // With a day's grace, the axe survives a further idle day and can still be completed.
+        let mut resumed = idle.step_forward_with(Action::Leisure, 1).unwrap();
+        assert_eq!(resumed.time_to_completion, 3);
+        for _ in 0..3 {
+            resumed.increment_production();
+            resumed = resumed
+                .step_forward_with(Action::ProduceGood(Good::Axe), 1)
+                .unwrap();
+        }
+        assert!(resumed.is_complete());
+
+        // A third idle day exhausts the grace.
+        let idle = idle.step_forward_with(Action::Leisure, 1).unwrap();
+        assert_eq!(idle.step_forward_with(Action::Leisure, 1), None);
+    }
 }
@@ -1,27 +1,107 @@
-use std::collections::HashMap;
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    goods::GoodsUnitLevel,
-    stock::{InvLevel, Stock},
+    config::{Config, core_config},
+    goods::{Good, GoodsUnitLevel},
+    stock::{InvLevel, Stock, StockDiscrete},
 };
 
 pub trait DiscrRep<S, L> {
     fn representation(&self) -> Vec<(S, L)>;
+    /// Returns the representation under the given config (e.g. its discretisation levels and
+    /// enabled goods). By default, the representation does not depend on the config.
+    fn representation_with(&self, _config: &Config) -> Vec<(S, L)> {
+        self.representation()
+    }
+}
+
+/// An item in the discretised state representation of a stock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum StateItem {
+    /// Completed units of a good at a given lifetime level.
+    Goods(GoodsUnitLevel),
+    /// Progress on the partially complete goods in the stock.
+    ///
+    /// Partial goods are aggregated into a single item, since each additional item multiplies
+    /// the size of the `QTable` keyspace by a further factor of `InvLevel::iter().count()`.
+    Partial,
 }
 
-impl DiscrRep<GoodsUnitLevel, InvLevel> for Stock {
-    fn representation(&self) -> Vec<(GoodsUnitLevel, InvLevel)> {
-        let hm: HashMap<GoodsUnitLevel, InvLevel> = self.discretise().stock.into_iter().collect();
-        GoodsUnitLevel::iter()
-            .map(|good_unit_level| {
-                (
-                    good_unit_level,
-                    hm.get(&good_unit_level)
+impl StateItem {
+    /// Iterates over all items in the discretised state representation.
+    pub fn iter() -> impl Iterator<Item = StateItem> {
+        Self::iter_with(&core_config().enabled_goods)
+    }
+
+    /// Iterates over all items in the discretised state representation, for the given
+    /// enabled goods.
+    pub fn iter_with(enabled_goods: &HashSet<Good>) -> impl Iterator<Item = StateItem> + use<> {
+        GoodsUnitLevel::iter_with(enabled_goods)
+            .map(StateItem::Goods)
+            .chain(std::iter::once(StateItem::Partial))
+    }
+}
+
+impl DiscrRep<StateItem, InvLevel> for StockDiscrete {
+    fn representation(&self) -> Vec<(StateItem, InvLevel)> {
+        self.representation_with(&core_config())
+    }
+
+    fn representation_with(&self, config: &Config) -> Vec<(StateItem, InvLevel)> {
+        StateItem::iter_with(&config.enabled_goods)
+            .map(|state_item| {
+                let level = match state_item {
+                    StateItem::Goods(good_unit_level) => self
+                        .stock
+                        .get(&good_unit_level)
                         .cloned()
                         // .unwrap_or(InvLevel::Critical),
                         .unwrap_or(InvLevel::Low),
-                )
+                    StateItem::Partial => self.partial,
+                };
+                (state_item, level)
             })
             .collect()
     }
 }
+
+impl DiscrRep<StateItem, InvLevel> for Stock {
+    fn representation(&self) -> Vec<(StateItem, InvLevel)> {
+        self.representation_with(&core_config())
+    }
+
+    fn representation_with(&self, config: &Config) -> Vec<(StateItem, InvLevel)> {
+        self.discretise_with(&config.agent)
+            .representation_with(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::goods::{Good, GoodsUnit, PartialGoodsUnit};
+
+    #[test]
+    fn test_representation_partial() {
+        let mut stock = Stock::default();
+        stock.add(GoodsUnit::new(&Good::Berries), 5).unwrap();
+        let mut with_boat = stock.clone();
+        with_boat
+            .add_partial(PartialGoodsUnit::new(&Good::Boat).unwrap())
+            .unwrap();
+
+        assert_ne!(stock.representation(), with_boat.representation());
+        assert!(
+            stock
+                .representation()
+                .contains(&(StateItem::Partial, InvLevel::Low))
+        );
+        assert!(
+            with_boat
+                .representation()
+                .contains(&(StateItem::Partial, InvLevel::Medium))
+        );
+    }
+}
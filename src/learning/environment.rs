@@ -0,0 +1,186 @@
+use rand::rngs::StdRng;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+use super::{
+    agent_state::DiscrRep,
+    history::{History, SAR},
+    reward::Reward,
+    tabular_rl::SARSAModel,
+};
+use crate::{Int, UInt};
+
+/// An environment in which a single agent acts, so that the tabular RL machinery can be
+/// exercised independently of the economy of goods (e.g. to test the learner in isolation).
+pub trait Environment {
+    /// The state observed by the agent, discretised into items at levels.
+    type State: DiscrRep<Self::Item, Self::Level> + Clone;
+    type Item: Eq + Hash + Clone + Debug + Serialize + DeserializeOwned;
+    type Level: Eq + Hash + Clone + Debug + Serialize + DeserializeOwned;
+    type Action: Eq + Hash + Clone + Debug + Serialize + IntoEnumIterator + DeserializeOwned;
+
+    /// Returns the current state of the environment.
+    fn state(&self) -> Self::State;
+    /// Returns the actions available to the agent.
+    fn actions(&self) -> Vec<Self::Action>;
+    /// Performs the action, advancing the environment by one time step.
+    fn step(&mut self, action: &Self::Action);
+    /// Returns the reward for the most recent time step.
+    fn reward(&self) -> Reward;
+}
+
+/// Trains the model on the environment for the given number of time steps, as the agent with
+/// id 0, sampling each action from the model under its own hyperparameters.
+pub fn train<E: Environment>(
+    env: &mut E,
+    model: &mut SARSAModel<E::State, E::Item, E::Level, E::Action>,
+    steps: UInt,
+    rng: &mut StdRng,
+) {
+    let mut agent_hist = BTreeMap::from([(0, History::new())]);
+    for t in 1..=steps {
+        let state = env.state();
+        let action = model.sample_action_by_id(0, &state.representation(), rng);
+        env.step(&action);
+        agent_hist
+            .get_mut(&0)
+            .expect("history was initialised for agent 0")
+            .push(SAR::new(state, action, env.reward()));
+        model.step(t as i32, &agent_hist);
+    }
+}
+
+/// A toy environment: a corridor of cells, along which the agent moves one cell at a time.
+/// Reaching the far end is rewarded and returns the agent to the start, while every other
+/// step is penalised.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Corridor {
+    pub length: UInt,
+    pub position: UInt,
+    reward: Reward,
+}
+
+/// The reward for reaching the far end of a `Corridor`.
+const CORRIDOR_GOAL_REWARD: Int = 10;
+/// The reward for any other step along a `Corridor`.
+const CORRIDOR_STEP_REWARD: Int = -1;
+
+/// The single item in the state of a `Corridor`, whose level is the agent's position.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, EnumIter,
+)]
+pub enum CorridorItem {
+    Position,
+}
+
+/// The position of the agent in a `Corridor`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CorridorState(pub UInt);
+
+impl DiscrRep<CorridorItem, UInt> for CorridorState {
+    fn representation(&self) -> Vec<(CorridorItem, UInt)> {
+        vec![(CorridorItem::Position, self.0)]
+    }
+}
+
+/// A move along a `Corridor`.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, EnumIter,
+)]
+pub enum Move {
+    Left,
+    Right,
+}
+
+impl Corridor {
+    /// Returns a corridor of the given number of cells, with the agent at the start.
+    pub fn new(length: UInt) -> Self {
+        Corridor {
+            length,
+            position: 0,
+            reward: Reward::new(0),
+        }
+    }
+
+    /// Returns every position in the corridor.
+    pub fn positions(&self) -> Vec<UInt> {
+        (0..self.length).collect()
+    }
+}
+
+impl Environment for Corridor {
+    type State = CorridorState;
+    type Item = CorridorItem;
+    type Level = UInt;
+    type Action = Move;
+
+    fn state(&self) -> CorridorState {
+        CorridorState(self.position)
+    }
+
+    fn actions(&self) -> Vec<Move> {
+        Move::iter().collect()
+    }
+
+    fn step(&mut self, action: &Move) {
+        self.position = match action {
+            Move::Left => self.position.saturating_sub(1),
+            Move::Right => self.position + 1,
+        };
+        self.reward = match self.position + 1 >= self.length {
+            true => {
+                self.position = 0;
+                Reward::new(CORRIDOR_GOAL_REWARD)
+            }
+            false => Reward::new(CORRIDOR_STEP_REWARD),
+        };
+    }
+
+    fn reward(&self) -> Reward {
+        self.reward
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    /// Returns the number of steps the greedy policy takes to reach the end of the corridor,
+    /// or None if it does not do so within twice the length of the corridor.
+    fn greedy_steps_to_goal(
+        model: &SARSAModel<CorridorState, CorridorItem, UInt, Move>,
+        length: UInt,
+    ) -> Option<UInt> {
+        let mut env = Corridor::new(length);
+        (1..=2 * length).find(|_| {
+            let action = model.greedy_action_by_id(0, &env.state().representation());
+            env.step(&action);
+            env.reward().val == CORRIDOR_GOAL_REWARD
+        })
+    }
+
+    #[test]
+    fn test_train_corridor() {
+        let length = 5;
+        let mut env = Corridor::new(length);
+        let mut model = SARSAModel::new(
+            vec![0],
+            vec![CorridorItem::Position],
+            env.positions(),
+            env.actions(),
+            false,
+        );
+        // With every value equal, the untrained greedy policy only ever moves left.
+        assert_eq!(greedy_steps_to_goal(&model, length), None);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        train(&mut env, &mut model, 2000, &mut rng);
+        assert_eq!(greedy_steps_to_goal(&model, length), Some(length - 1));
+    }
+}
@@ -1,4 +1,5 @@
 use super::{agent_state::DiscrRep, q_table::QKey, reward::Reward};
+use crate::config::Config;
 use serde::{Deserialize, Serialize};
 use std::marker::PhantomData;
 
@@ -55,6 +56,11 @@ where
     pub fn len(&self) -> usize {
         self.trajectory.len()
     }
+    /// Discards all but the most recent `window` entries of the trajectory.
+    pub fn truncate(&mut self, window: usize) {
+        let excess = self.trajectory.len().saturating_sub(window);
+        self.trajectory.drain(..excess);
+    }
 }
 
 impl<T, S, L, A> SAR<T, S, L, A>
@@ -75,6 +81,12 @@ where
     pub fn representation(&self) -> QKey<S, L, A> {
         QKey(self.state.representation(), self.action.clone())
     }
+
+    /// Returns the key of this state-action under the given config (see
+    /// `DiscrRep::representation_with`).
+    pub fn representation_with(&self, config: &Config) -> QKey<S, L, A> {
+        QKey(self.state.representation_with(config), self.action.clone())
+    }
 }
 
 #[cfg(test)]
@@ -84,7 +96,7 @@ mod tests {
     // AgenteState is now Stock
     // AgentStateItems is now Good
     use crate::actions::ActionFlattened as Action;
-    use crate::goods::GoodsUnitLevel as AgentStateItems;
+    use crate::learning::agent_state::StateItem as AgentStateItems;
     use crate::stock::{InvLevel, Stock as AgentState};
 
     fn get_test_history() -> History<AgentState, AgentStateItems, InvLevel, Action> {
@@ -110,6 +122,20 @@ mod tests {
         assert_ne!(history.trajectory.last().unwrap(), &sar2);
     }
 
+    #[test]
+    fn test_history_truncate() {
+        let mut history = get_test_history();
+        let sar = SAR::new(AgentState::default(), Action::Leisure, Reward { val: 1 });
+        history.push(sar.clone());
+        history.push(sar.clone());
+        history.truncate(2);
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.trajectory, vec![sar.clone(), sar]);
+        // Truncating to a window longer than the trajectory has no effect.
+        history.truncate(5);
+        assert_eq!(history.len(), 2);
+    }
+
     #[test]
     fn test_last_state_action() {
         assert_eq!(
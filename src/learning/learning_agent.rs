@@ -1,14 +1,14 @@
-use rand::SeedableRng;
-use rand::rngs::StdRng;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 use crate::actions::Action;
-use crate::agent::Agent;
+use crate::agent::{Agent, AgentCore};
 use crate::goods::{Good, GoodsUnit, PartialGoodsUnit, Productivity};
 use crate::learning::agent_state::DiscrRep;
+use crate::learning::policy::{Policy, SharedPolicy};
 use crate::learning::reward::Reward;
-use crate::stock::Stock;
-use crate::{Model, NEGATIVE_REWARD, POSITIVE_REWARD, UInt};
+use crate::stock::{Stock, StockError};
+use crate::{Model, UInt};
 
 // LearningAgent is currently just a clone of CrusoeAgent. The idea would
 // be to have each agent type in its own module (or sub-directory)
@@ -17,34 +17,58 @@ use crate::{Model, NEGATIVE_REWARD, POSITIVE_REWARD, UInt};
 pub struct LearningAgent {
     pub id: u64,
     pub stock: Stock,
-    pub is_alive: bool,
     pub action_history: Vec<Action>,
     stock_history: Vec<Stock>,
     pub reward_history: Vec<Reward>,
+    #[serde(flatten)]
+    pub core: AgentCore,
+    /// A policy used to choose actions in place of the model, if set.
+    #[serde(skip)]
+    policy: Option<SharedPolicy>,
 }
 
 impl LearningAgent {
+    /// Returns an agent that requires one nutritional unit per time step.
     pub fn new(id: u64) -> Self {
+        Self::new_with_nutrition(id, 1)
+    }
+
+    /// Returns an agent that requires the given number of nutritional units per time step.
+    pub fn new_with_nutrition(id: u64, daily_nutrition: UInt) -> Self {
         LearningAgent {
             id,
             stock: Stock::default(),
-            is_alive: true,
             action_history: vec![],
             stock_history: vec![],
             reward_history: vec![],
+            core: AgentCore::new(daily_nutrition),
+            policy: None,
         }
     }
+
+    /// Sets a policy used to choose actions in place of the model.
+    pub fn set_policy(&mut self, policy: impl Policy + 'static) {
+        self.policy = Some(SharedPolicy(Arc::new(policy)));
+    }
 }
 
 impl Agent for LearningAgent {
     fn get_id(&self) -> u64 {
-        todo!()
+        self.id
     }
 
     fn get_name(&self) -> &str {
         "LearningAgent"
     }
 
+    fn core(&self) -> &AgentCore {
+        &self.core
+    }
+
+    fn core_mut(&mut self) -> &mut AgentCore {
+        &mut self.core
+    }
+
     fn stock(&self) -> &Stock {
         &self.stock
     }
@@ -56,17 +80,18 @@ impl Agent for LearningAgent {
     /// e.g. (5, false) indicates  5 units can be produced in one day,
     /// while (5, true) indicates that a single unit takes 5 days to produce.
     fn productivity(&self, good: &Good) -> Productivity {
-        // TODO: make configurable.
         // TODO: can modify default productivity for different agents (for specialisation).
-        good.default_productivity(&self.stock)
+        let productivity = good
+            .configured_productivity(&self.stock, &[], self.build_time(good), &self.core.config)
+            .scaled(self.season_multiplier(good));
+        self.depleted(good, productivity)
     }
 
     // TODO: consider moving teh action_history update into act method, so
     // self can be immutable here.
     fn choose_action(&mut self) -> Action {
-        // let action = Action::random_weighted(&mut StdRng::from_os_rng(), 0.5);
-        let action = Action::random(&mut StdRng::from_os_rng());
-        // let action = Action::random(&mut StdRng::seed_from_u64(self.id));
+        // let action = Action::random_weighted(&mut self.rng, 0.5);
+        let action = Action::random_with(&mut self.core.rng, &self.core.config);
         self.action_history.push(action);
         action
     }
@@ -74,103 +99,17 @@ impl Agent for LearningAgent {
     // TODO: consider moving teh action_history update into act method, so
     // self can be immutable here.
     fn choose_action_with_model(&mut self, model: &Model) -> Action {
-        let action =
-            model.sample_action_by_id(0, &self.stock.representation(), &mut StdRng::from_os_rng());
-        self.action_history.push(action.into());
-        action.into()
-    }
-
-    // TODO: initially 1 unit of nutrition required per time unit
-    // TODO: in future, make this e.g. 3 units which could be 1 berries + 2 fish.
-
-    /// Consume the requisite units of food per time unit.
-    /// Return false if insufficient stock was available.
-    fn consume(&mut self, nutritional_units: UInt) -> bool {
-        let consumables = self.stock.next_consumables();
-        if consumables.is_empty() {
-            // println!("DEBUG: consume - no consumables available");
-            return false;
-        }
-
-        let mut outstanding_nutritional_units = nutritional_units;
-        let mut stock_change: Vec<_> = vec![];
-        for (good, qty) in consumables {
-            if *qty > outstanding_nutritional_units {
-                // return self.consume(nutritional_units - qty);
-                stock_change.push((*good, outstanding_nutritional_units));
-                outstanding_nutritional_units = 0;
-                break;
-            } else {
-                stock_change.push((*good, *qty));
-                outstanding_nutritional_units -= *qty;
+        let action = match &self.policy {
+            Some(SharedPolicy(policy)) => {
+                policy.choose_action(&self.stock.discretise_with(&self.core.config.agent))
             }
-        }
-        // Update stock
-        for (good, qty) in stock_change {
-            self.stock.remove(&good, qty);
-        }
-        // Returns false if the agent dies from lack of nutrients
-        if outstanding_nutritional_units > 0 {
-            return false;
-        }
-        true
-    }
-
-    fn is_alive(&self) -> bool {
-        self.is_alive
-    }
-
-    fn act(&mut self, action: Action) {
-        match action {
-            Action::ProduceGood(good) => {
-                let productivity = self.productivity(&good);
-                match productivity {
-                    Productivity::Immediate(qty) => self.stock.add(GoodsUnit::new(&good), qty),
-                    Productivity::Delayed(_) => {
-                        if let Some(mut partial_good) = self.stock.get_partial(good) {
-                            // If a partial good already exists, do the next step of production.
-                            partial_good.increment_production();
-                        } else {
-                            // Otherwise create a new partial good.
-                            self.stock.add_partial(PartialGoodsUnit::new(&good).expect(
-                                "Delayed productivity implies multiple timesteps to produce.",
-                            ))
-                        }
-                    }
-                    Productivity::None => {} // Wasted action.
-                }
-            }
-            Action::Leisure => (),
-        }
-    }
-
-    fn step_forward(&mut self, action: Option<Action>) {
-        // Select action if not given.
-        let action = match action {
-            Some(a) => a,
-            None => self.choose_action(),
-        };
-        // Perform action, which updates the agent's stock
-        self.act(action);
-        // Consume stock, which updates whether the agent is alive
-        // TODO: make required nutritional_units per time unit configurable.
-        // self.is_alive = self.consume(1);
-        let is_alive = self.consume(1);
-        // Degrade the agent's stock.
-        self.stock_history.push(self.stock.clone());
-        self.stock = self.stock.step_forward(action);
-        // Update reward history
-        match (action, is_alive) {
-            (Action::ProduceGood(_), true) => {
-                self.reward_history.push(Reward::new(0));
-            }
-            (Action::Leisure, true) => {
-                self.reward_history.push(Reward::new(POSITIVE_REWARD));
-            }
-            (_, false) => {
-                self.reward_history.push(Reward::new(NEGATIVE_REWARD));
+            None => {
+                let state = self.stock.representation_with(&self.core.config);
+                model.sample_action_by_id(self.id as u32, &state, &mut self.core.rng)
             }
         };
+        self.action_history.push(action.into());
+        action.into()
     }
 
     fn action_history(&self) -> &[Action] {
@@ -191,16 +130,26 @@ impl Agent for LearningAgent {
     fn reward_history_mut(&mut self) -> &mut Vec<Reward> {
         &mut self.reward_history
     }
-    fn set_liveness(&mut self, value: bool) {
-        self.is_alive = value;
+    /// Unlike other agents, a learning agent keeps its stock on death, which steps forward
+    /// as it would otherwise.
+    fn on_death(&mut self, action: Action) {
+        let config = self.core.config.clone();
+        let build_time = action.good().and_then(|good| self.build_time(&good));
+        let stock = &self.stock;
+        self.stock = stock.step_forward_with_usage(
+            action,
+            |good| stock.is_used_with(good, action, build_time, &config),
+            config.partial_abandon_grace,
+        );
     }
 
-    fn acquire(&mut self, goods_unit: GoodsUnit, quantity: UInt) {
-        self.stock.add(goods_unit, quantity);
+    fn acquire(&mut self, goods_unit: GoodsUnit, quantity: UInt) -> Result<(), StockError> {
+        self.stock.add(goods_unit, quantity)
     }
 
-    fn acquire_partial(&mut self, partial_goods_unit: PartialGoodsUnit) {
-        self.stock.add_partial(partial_goods_unit);
+    fn acquire_partial(&mut self, partial_goods_unit: PartialGoodsUnit) -> Result<(), StockError> {
+        let limit = self.core.config.agent.max_concurrent_builds;
+        self.stock.add_partial_with_limit(partial_goods_unit, limit)
     }
 
     fn get_partial(&self, good: Good) -> Option<PartialGoodsUnit> {
@@ -215,3 +164,31 @@ impl Agent for LearningAgent {
         self.stock = stock;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::ActionFlattened;
+    use crate::learning::agent_state::StateItem;
+    use crate::learning::policy::ConstantPolicy;
+    use crate::test_model;
+
+    #[test]
+    fn test_policy() {
+        // The model is not consulted when a policy is set, so a minimal one suffices.
+        let model = test_model(vec![StateItem::Partial]);
+        let mut agent = LearningAgent::new(0);
+        agent.set_policy(ConstantPolicy(ActionFlattened::ProduceBerries));
+        for _ in 0..20 {
+            let action = agent.choose_action_with_model(&model);
+            agent.step_forward(Some(action));
+        }
+        assert_eq!(agent.action_history().len(), 20);
+        assert!(
+            agent
+                .action_history()
+                .iter()
+                .all(|action| *action == Action::ProduceGood(Good::Berries))
+        );
+    }
+}
@@ -1,9 +1,10 @@
 // pub mod action;
 pub mod agent_state;
+pub mod environment;
 pub mod history;
+pub mod learning_agent;
 pub mod policy;
 pub mod q_table;
 pub mod reward;
 pub mod serde_utils;
 pub mod tabular_rl;
-pub mod learning_agent;
\ No newline at end of file
@@ -1,7 +1,62 @@
+use std::fmt;
+use std::sync::Arc;
+
 use crate::stock::StockDiscrete;
 
 use crate::actions::ActionFlattened as Action;
 
 pub trait Policy {
-    fn chose_action(&self, agent_state: &StockDiscrete) -> Action;
+    fn choose_action(&self, agent_state: &StockDiscrete) -> Action;
+}
+
+/// A policy that always chooses the same action, regardless of the agent's state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConstantPolicy(pub Action);
+
+impl Policy for ConstantPolicy {
+    fn choose_action(&self, _agent_state: &StockDiscrete) -> Action {
+        self.0
+    }
+}
+
+/// A policy that may be shared between agents. Shared policies are equal only if they are
+/// the same policy.
+#[derive(Clone)]
+pub struct SharedPolicy(pub Arc<dyn Policy>);
+
+impl fmt::Debug for SharedPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SharedPolicy")
+    }
+}
+
+impl PartialEq for SharedPolicy {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for SharedPolicy {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stock::Stock;
+
+    /// A trivial implementation, so that a change to the name of the trait method fails to compile.
+    struct LeisurePolicy;
+
+    impl Policy for LeisurePolicy {
+        fn choose_action(&self, _agent_state: &StockDiscrete) -> Action {
+            Action::Leisure
+        }
+    }
+
+    #[test]
+    fn test_choose_action() {
+        let agent_state = Stock::default().discretise();
+        assert_eq!(LeisurePolicy.choose_action(&agent_state), Action::Leisure);
+        let policy = ConstantPolicy(Action::ProduceFish);
+        assert_eq!(policy.choose_action(&agent_state), Action::ProduceFish);
+    }
 }
@@ -1,4 +1,5 @@
-use crate::config::core_config;
+use crate::UInt;
+use crate::config::{ExplorationStrategy, InitStrategy, RLConfig, core_config};
 use crate::learning::serde_utils;
 use itertools::Itertools;
 use rand::{Rng, rngs::StdRng};
@@ -24,9 +25,19 @@ where
     // To serialize with a struct as key, custom serialization with a `serde_utils` module can be [used](https://stackoverflow.com/questions/51276896/how-do-i-use-serde-to-serialize-a-hashmap-with-structs-as-keys-to-json)
     #[serde(with = "serde_utils")]
     pub tab: HashMap<QKey<S, L, A>, f32>,
+    #[serde(default)]
+    init_strategy: InitStrategy,
+    #[serde(default)]
+    init_q_value: f32,
+    /// The actions available in every state (all actions, if empty).
+    #[serde(default = "Vec::new", bound(deserialize = ""))]
+    actions: Vec<A>,
+    /// The number of updates of each state-action, for count-based exploration.
+    #[serde(default = "HashMap::new", with = "serde_utils")]
+    visits: HashMap<QKey<S, L, A>, UInt>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct QKey<S, L, A>(pub Vec<(S, L)>, pub A);
 
 impl<S, L, A> QKey<S, L, A> {
@@ -48,6 +59,36 @@ where
         + DeserializeOwned,
 {
     pub fn new(state_items: Vec<S>, state_levels: Vec<L>, actions: Vec<A>) -> Self {
+        Self::new_with_rl(state_items, state_levels, actions, &core_config().rl)
+    }
+
+    /// Returns a table initialised as given by the RL config, which is lazy if configured.
+    pub fn new_with_rl(
+        state_items: Vec<S>,
+        state_levels: Vec<L>,
+        actions: Vec<A>,
+        rl: &RLConfig,
+    ) -> Self {
+        if rl.lazy_q_table {
+            return Self::new_lazy(actions, rl.init_strategy.clone(), rl.init_q_value);
+        }
+        Self::new_with_init(
+            state_items,
+            state_levels,
+            actions,
+            &rl.init_strategy,
+            rl.init_q_value,
+        )
+    }
+
+    /// Returns a table with initial values given by the init strategy.
+    pub fn new_with_init(
+        state_items: Vec<S>,
+        state_levels: Vec<L>,
+        actions: Vec<A>,
+        init_strategy: &InitStrategy,
+        init_q_value: f32,
+    ) -> Self {
         let mut q_tbl = HashMap::new();
         let mut combs_for_all_state_items = Vec::new();
         for s in state_items {
@@ -73,15 +114,84 @@ where
         //     .into_iter()
         //     .collect_vec();
 
-        let q = combs.into_iter().cartesian_product(actions).collect_vec();
+        let q = combs
+            .into_iter()
+            .cartesian_product(actions.clone())
+            .collect_vec();
 
         for el in q {
             // println!("{:?}", el);
+            let init_value = init_strategy.init_value(&el.1, init_q_value);
             let q_key = QKey(el.0, el.1);
-            q_tbl.insert(q_key, core_config().rl.init_q_value);
+            q_tbl.insert(q_key, init_value);
         }
 
-        QTable { tab: q_tbl }
+        QTable {
+            tab: q_tbl,
+            init_strategy: init_strategy.clone(),
+            init_q_value,
+            actions,
+            visits: HashMap::new(),
+        }
+    }
+
+    /// Returns an empty table whose entries are inserted, with their initial values, as
+    /// state-actions are updated (see `value_mut`). Sampling only reads initial values, as
+    /// actions are sampled from a model shared by the agents.
+    pub fn new_lazy(actions: Vec<A>, init_strategy: InitStrategy, init_q_value: f32) -> Self {
+        QTable {
+            tab: HashMap::new(),
+            init_strategy,
+            init_q_value,
+            actions,
+            visits: HashMap::new(),
+        }
+    }
+
+    /// Returns the actions available in every state.
+    pub fn actions(&self) -> Vec<A> {
+        match self.actions.is_empty() {
+            true => A::iter().collect(),
+            false => self.actions.clone(),
+        }
+    }
+
+    /// Returns the value for the given state-action. Missing entries (e.g. every entry not
+    /// yet updated in a lazy table, or a state outside the enumerated levels) take their
+    /// initial value without being inserted; entries are inserted when first updated.
+    pub fn value(&self, q_key: &QKey<S, L, A>) -> f32 {
+        match self.tab.get(q_key) {
+            Some(q) => *q,
+            None => self.init_strategy.init_value(&q_key.1, self.init_q_value),
+        }
+    }
+
+    /// Returns a mutable reference to the value for the given state-action, first inserting
+    /// its initial value if the entry is missing.
+    pub fn value_mut(&mut self, q_key: &QKey<S, L, A>) -> &mut f32 {
+        let init_value = self.init_strategy.init_value(&q_key.1, self.init_q_value);
+        self.tab.entry(q_key.clone()).or_insert(init_value)
+    }
+
+    /// Returns the number of times the given state-action has been visited.
+    pub fn visit_count(&self, q_key: &QKey<S, L, A>) -> UInt {
+        self.visits.get(q_key).copied().unwrap_or(0)
+    }
+
+    /// Records a visit to the given state-action.
+    pub fn record_visit(&mut self, q_key: &QKey<S, L, A>) {
+        *self.visits.entry(q_key.clone()).or_insert(0) += 1;
+    }
+
+    /// Returns the value for the given state-action plus the count-based exploration bonus
+    /// `c / sqrt(1 + n)`, where n is its visit count. The count is offset by one so that the
+    /// bonus for an unvisited state-action is finite.
+    pub fn value_with_bonus(&self, q_key: &QKey<S, L, A>, c: f32) -> f32 {
+        let q = self.value(q_key);
+        match c == 0.0 {
+            true => q,
+            false => q + c / (1.0 + self.visit_count(q_key) as f32).sqrt(),
+        }
     }
 
     pub fn get_tab_mut(&mut self) -> &mut HashMap<QKey<S, L, A>, f32> {
@@ -91,44 +201,196 @@ where
         &self.tab
     }
 
-    pub fn sample_action(&self, state: &Vec<(S, L)>, rng: &mut StdRng) -> (A, f32) {
-        let mut optimal_a: A = self.pick_rnd(rng);
-        let mut q_optimal = self
-            .get_tab()
-            .get(&QKey(state.to_owned(), optimal_a.clone()))
-            .unwrap();
+    /// Samples an action according to the exploration strategy, epsilon and exploration bonus
+    /// of the given RL config. Sampling does not insert entries into a lazy table; entries are
+    /// inserted when the value is first updated.
+    pub fn sample_action(&self, state: &Vec<(S, L)>, rng: &mut StdRng, rl: &RLConfig) -> (A, f32) {
+        self.sample_action_with_bonus(
+            state,
+            rng,
+            &rl.exploration,
+            rl.epsilon,
+            rl.exploration_bonus_c,
+        )
+    }
 
-        for a in A::iter() {
-            let q_a = self
-                .get_tab()
-                .get(&QKey(state.to_owned(), optimal_a.clone()))
-                .unwrap();
-            // println!("{:?}, {:?}", a, q_a);
-            if q_a > q_optimal {
-                optimal_a = a;
-                q_optimal = self
-                    .get_tab()
-                    .get(&QKey(state.to_owned(), optimal_a.clone()))
-                    .unwrap();
+    /// Samples an action according to the given exploration strategy.
+    pub fn sample_action_with(
+        &self,
+        state: &Vec<(S, L)>,
+        rng: &mut StdRng,
+        exploration: &ExplorationStrategy,
+        epsilon: f32,
+    ) -> (A, f32) {
+        self.sample_action_with_bonus(state, rng, exploration, epsilon, 0.0)
+    }
+
+    /// Samples an action according to the given exploration strategy, comparing actions by
+    /// their values plus the count-based exploration bonus with coefficient `c` (see
+    /// `value_with_bonus`). The value returned with the action excludes the bonus.
+    pub fn sample_action_with_bonus(
+        &self,
+        state: &Vec<(S, L)>,
+        rng: &mut StdRng,
+        exploration: &ExplorationStrategy,
+        epsilon: f32,
+        c: f32,
+    ) -> (A, f32) {
+        match exploration {
+            ExplorationStrategy::EpsilonGreedy => {
+                self.sample_epsilon_greedy(state, rng, epsilon, c)
+            }
+            ExplorationStrategy::Softmax { temperature } => {
+                self.sample_softmax(state, rng, *temperature, c)
             }
         }
+    }
+
+    fn sample_epsilon_greedy(
+        &self,
+        state: &Vec<(S, L)>,
+        rng: &mut StdRng,
+        epsilon: f32,
+        c: f32,
+    ) -> (A, f32) {
+        let (mut optimal_a, q_optimal) = self
+            .greedy_action_with_bonus(state, c)
+            .expect("there is at least one action");
         let r: f32 = rng.random();
-        if r < core_config().rl.epsilon {
+        if r < epsilon {
             optimal_a = self.pick_rnd(rng);
         }
-        (optimal_a, *q_optimal)
+        (optimal_a, q_optimal)
+    }
+
+    fn sample_softmax(
+        &self,
+        state: &Vec<(S, L)>,
+        rng: &mut StdRng,
+        temperature: f32,
+        c: f32,
+    ) -> (A, f32) {
+        let values: Vec<(A, f32)> = self
+            .actions()
+            .into_iter()
+            .map(|a| {
+                let q_a = self.value(&QKey(state.to_owned(), a.clone()));
+                (a, q_a)
+            })
+            .collect();
+        let bonused: Vec<f32> = values
+            .iter()
+            .map(|(a, _)| self.value_with_bonus(&QKey(state.to_owned(), a.clone()), c))
+            .collect();
+        // Subtract the maximum value before exponentiating, for numerical stability.
+        let q_max = bonused.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let weights: Vec<f32> = bonused
+            .iter()
+            .map(|q| ((q - q_max) / temperature).exp())
+            .collect();
+        let mut r: f32 = rng.random::<f32>() * weights.iter().sum::<f32>();
+        for ((a, q_a), weight) in values.iter().zip(&weights) {
+            if r < *weight {
+                return (a.clone(), *q_a);
+            }
+            r -= weight;
+        }
+        // Guard against rounding error in the cumulative sum.
+        values
+            .last()
+            .cloned()
+            .expect("there is at least one action")
     }
     fn pick_rnd(&self, rng: &mut StdRng) -> A {
-        let actions: Vec<A> = A::iter().collect();
+        let actions: Vec<A> = self.actions();
         let idx = (rng.random::<f64>() * actions.len() as f64).floor() as usize;
         actions[idx].clone()
     }
+
+    /// Returns the action with the highest value in the given state, and its value.
+    ///
+    /// Ties between equally-valued actions are broken deterministically in favour of the
+    /// action that comes first in the enum declaration order of `A` (for `ActionFlattened`,
+    /// production actions precede `Leisure`, which precedes `Consume`).
+    pub fn greedy_action(&self, state: &Vec<(S, L)>) -> Option<(A, f32)> {
+        self.greedy_action_with_bonus(state, 0.0)
+    }
+
+    /// Returns the action with the highest value plus exploration bonus with coefficient `c`
+    /// (see `value_with_bonus`) in the given state, and its value excluding the bonus. Ties
+    /// are broken as in `greedy_action`.
+    pub fn greedy_action_with_bonus(&self, state: &Vec<(S, L)>, c: f32) -> Option<(A, f32)> {
+        let mut greedy: Option<(A, f32, f32)> = None;
+        for a in self.actions() {
+            let q_key = QKey(state.to_owned(), a.clone());
+            let (q_a, b_a) = (self.value(&q_key), self.value_with_bonus(&q_key, c));
+            let is_better = greedy.as_ref().is_none_or(|(greedy_a, _, b)| {
+                b_a > *b || (b_a == *b && enum_index(&a) < enum_index(greedy_a))
+            });
+            if is_better {
+                greedy = Some((a, q_a, b_a));
+            }
+        }
+        greedy.map(|(a, q_a, _)| (a, q_a))
+    }
+}
+
+impl<S, L, A> QTable<S, L, A>
+where
+    S: std::cmp::Eq + std::hash::Hash + Ord + Clone + Debug + Serialize + DeserializeOwned,
+    L: std::cmp::Eq + std::hash::Hash + Ord + Clone + Debug + Serialize + DeserializeOwned,
+    A: std::cmp::Eq
+        + std::hash::Hash
+        + Ord
+        + Clone
+        + Debug
+        + Serialize
+        + IntoEnumIterator
+        + DeserializeOwned,
+{
+    /// Returns every state in the table, sorted.
+    pub fn all_states(&self) -> Vec<Vec<(S, L)>> {
+        self.get_tab()
+            .keys()
+            .map(|QKey(state, _)| state.clone())
+            .sorted()
+            .dedup()
+            .collect()
+    }
+
+    /// Returns every entry in the table, sorted by key.
+    pub fn dump(&self) -> Vec<(QKey<S, L, A>, f32)> {
+        self.get_tab()
+            .iter()
+            .map(|(q_key, q)| (q_key.clone(), *q))
+            .sorted_by(|(a, _), (b, _)| a.cmp(b))
+            .collect()
+    }
+
+    /// Formats the greedy policy as one line per state.
+    pub fn format_greedy_policy(&self) -> String {
+        self.all_states()
+            .iter()
+            .filter_map(|state| {
+                self.greedy_action(state)
+                    .map(|(a, q)| format!("{:?} => {:?} ({})", state, a, q))
+            })
+            .join("\n")
+    }
+}
+
+/// Returns the position of the given variant in the declaration order of its enum.
+fn enum_index<A: PartialEq + IntoEnumIterator>(action: &A) -> usize {
+    A::iter()
+        .position(|a| a == *action)
+        .expect("every variant is iterated")
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::stock::InvLevel;
+    use std::collections::BTreeMap;
 
     #[test]
     fn test_multi_product() {
@@ -140,4 +402,284 @@ mod tests {
         // Should be: 3 ** 3 with each position taking all possible variants of the enum
         assert_eq!(combs.len(), 27)
     }
+
+    #[test]
+    fn test_dump() {
+        use crate::actions::ActionFlattened as Action;
+        use crate::goods::Good;
+
+        let q_table: QTable<Good, InvLevel, Action> = QTable::new(
+            vec![Good::Berries, Good::Fish],
+            InvLevel::iter().collect(),
+            Action::iter().collect(),
+        );
+        let num_states = InvLevel::iter().count().pow(2);
+        let num_actions = Action::iter().count();
+        assert_eq!(q_table.all_states().len(), num_states);
+
+        let dump = q_table.dump();
+        assert_eq!(dump.len(), num_states * num_actions);
+        // Entries are sorted by key.
+        assert!(dump.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(q_table.format_greedy_policy().lines().count(), num_states);
+    }
+
+    #[test]
+    fn test_per_action_init() {
+        use crate::actions::ActionFlattened as Action;
+        use crate::goods::Good;
+
+        let init_strategy =
+            InitStrategy::PerAction(BTreeMap::from([("ProduceBoat".to_string(), 5.0)]));
+        let q_table: QTable<Good, InvLevel, Action> = QTable::new_with_init(
+            vec![Good::Berries, Good::Fish],
+            InvLevel::iter().collect(),
+            Action::iter().collect(),
+            &init_strategy,
+            0.0,
+        );
+        for (QKey(_, action), q) in q_table.get_tab() {
+            match action {
+                Action::ProduceBoat => assert_eq!(*q, 5.0),
+                _ => assert_eq!(*q, 0.0),
+            }
+        }
+    }
+
+    #[test]
+    fn test_lazy_table() {
+        use crate::actions::ActionFlattened as Action;
+        use crate::goods::Good;
+        use rand::SeedableRng;
+
+        let mut q_table: QTable<Good, InvLevel, Action> =
+            QTable::new_lazy(Action::iter().collect(), InitStrategy::Constant, 1.5);
+        assert!(q_table.get_tab().is_empty());
+
+        // Sampling reads initial values without inserting entries.
+        let state = vec![(Good::Berries, InvLevel::Low), (Good::Fish, InvLevel::High)];
+        let mut rng = StdRng::seed_from_u64(42);
+        let (action, q) = q_table.sample_action(&state, &mut rng, &RLConfig::default());
+        assert_eq!(q, 1.5);
+        assert!(q_table.get_tab().is_empty());
+
+        // Updating a visited state-action inserts a single entry, with its initial value.
+        let q_key = QKey(state.clone(), action);
+        *q_table.value_mut(&q_key) += 1.0;
+        assert_eq!(q_table.get_tab().len(), 1);
+        assert_eq!(q_table.value(&q_key), 2.5);
+        assert_eq!(q_table.all_states(), vec![state]);
+    }
+
+    #[test]
+    fn test_softmax() {
+        use crate::actions::ActionFlattened as Action;
+        use crate::goods::Good;
+        use rand::SeedableRng;
+
+        let mut q_table: QTable<Good, InvLevel, Action> = QTable::new_with_init(
+            vec![Good::Berries],
+            InvLevel::iter().collect(),
+            Action::iter().collect(),
+            &InitStrategy::Constant,
+            0.0,
+        );
+        let state = vec![(Good::Berries, InvLevel::Low)];
+        q_table
+            .get_tab_mut()
+            .insert(QKey(state.clone(), Action::ProduceBerries), 1.0);
+
+        let num_samples = 10000;
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut sample_counts = |temperature: f32| {
+            let exploration = ExplorationStrategy::Softmax { temperature };
+            let mut counts: HashMap<Action, usize> = HashMap::new();
+            for _ in 0..num_samples {
+                let (a, _) = q_table.sample_action_with(&state, &mut rng, &exploration, 0.0);
+                *counts.entry(a).or_default() += 1;
+            }
+            counts
+        };
+
+        // At low temperature, the greedy action is (almost) always selected.
+        let counts = sample_counts(0.01);
+        assert_eq!(counts[&Action::ProduceBerries], num_samples);
+
+        // At high temperature, selection approaches uniform.
+        let counts = sample_counts(1000.0);
+        let uniform = num_samples as f32 / Action::iter().count() as f32;
+        for a in Action::iter() {
+            let count = *counts.get(&a).unwrap_or(&0) as f32;
+            assert!((count - uniform).abs() < 0.2 * uniform);
+        }
+    }
+
+    #[test]
+    fn test_greedy_tie_break() {
+        use crate::actions::ActionFlattened as Action;
+        use crate::goods::Good;
+        use rand::SeedableRng;
+
+        // The actions are listed in reverse of their declaration order.
+        let actions = vec![Action::Consume, Action::Leisure, Action::ProduceFish];
+        let mut q_table: QTable<Good, InvLevel, Action> = QTable::new_with_init(
+            vec![Good::Berries],
+            InvLevel::iter().collect(),
+            actions,
+            &InitStrategy::Constant,
+            0.0,
+        );
+        let state = vec![(Good::Berries, InvLevel::Low)];
+        for a in [Action::Leisure, Action::ProduceFish] {
+            q_table.get_tab_mut().insert(QKey(state.clone(), a), 1.0);
+        }
+
+        assert_eq!(
+            q_table.greedy_action(&state),
+            Some((Action::ProduceFish, 1.0))
+        );
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let sampled = q_table.sample_action_with(
+                &state,
+                &mut rng,
+                &ExplorationStrategy::EpsilonGreedy,
+                0.0,
+            );
+            assert_eq!(sampled, (Action::ProduceFish, 1.0));
+        }
+    }
+
+    #[test]
+    fn test_enabled_goods() {
+        use crate::actions::{Action as AgentAction, ActionFlattened as Action};
+        use crate::config::Config;
+        use crate::goods::Good;
+        use crate::learning::agent_state::StateItem;
+        use rand::SeedableRng;
+        use std::collections::HashSet;
+
+        let enabled_goods = HashSet::from([Good::Berries]);
+        let config = Config {
+            enabled_goods: enabled_goods.clone(),
+            ..Config::default()
+        };
+        // Consuming is an action only when consumption is not automatic.
+        assert_eq!(
+            Action::enabled_with(&Config {
+                auto_consume: false,
+                ..config.clone()
+            }),
+            vec![Action::ProduceBerries, Action::Leisure, Action::Consume]
+        );
+        let actions = Action::enabled_with(&config);
+        assert_eq!(actions, vec![Action::ProduceBerries, Action::Leisure]);
+        let q_table: QTable<StateItem, InvLevel, Action> = QTable::new_with_init(
+            StateItem::iter_with(&enabled_goods).collect(),
+            InvLevel::iter().collect(),
+            actions.clone(),
+            &InitStrategy::Constant,
+            0.0,
+        );
+        // Disabled goods appear in neither the actions nor the states.
+        assert!(
+            q_table
+                .get_tab()
+                .keys()
+                .all(|QKey(_, a)| actions.contains(a))
+        );
+        for state in q_table.all_states() {
+            assert!(state.iter().all(|(item, _)| match item {
+                StateItem::Goods(level) => level.good == Good::Berries,
+                StateItem::Partial => true,
+            }));
+        }
+
+        // Sampled (including fully random) actions only involve enabled goods.
+        let state = q_table.all_states()[0].clone();
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..100 {
+            let (a, _) = q_table.sample_action_with(
+                &state,
+                &mut rng,
+                &ExplorationStrategy::EpsilonGreedy,
+                1.0,
+            );
+            assert!(actions.contains(&a));
+            let random = AgentAction::random_with(&mut rng, &config);
+            assert!(random.is_enabled_with(&config));
+        }
+    }
+
+    #[test]
+    fn test_exploration_bonus() {
+        use crate::actions::ActionFlattened as Action;
+        use crate::goods::Good;
+        use rand::SeedableRng;
+
+        let actions = vec![Action::ProduceFish, Action::Leisure];
+        let mut q_table: QTable<Good, InvLevel, Action> = QTable::new_with_init(
+            vec![Good::Berries],
+            InvLevel::iter().collect(),
+            actions,
+            &InitStrategy::Constant,
+            1.0,
+        );
+        let state = vec![(Good::Berries, InvLevel::Low)];
+        let visited = QKey(state.clone(), Action::ProduceFish);
+        q_table.record_visit(&visited);
+        assert_eq!(q_table.visit_count(&visited), 1);
+
+        // Without a bonus, the tie is broken in favour of the visited action.
+        let sample = |q_table: &QTable<Good, InvLevel, Action>, c: f32| {
+            let mut rng = StdRng::seed_from_u64(0);
+            q_table.sample_action_with_bonus(
+                &state,
+                &mut rng,
+                &ExplorationStrategy::EpsilonGreedy,
+                0.0,
+                c,
+            )
+        };
+        assert_eq!(sample(&q_table, 0.0), (Action::ProduceFish, 1.0));
+        // With a positive bonus, the unvisited action is preferred, and its value excludes
+        // the bonus.
+        assert_eq!(sample(&q_table, 0.5), (Action::Leisure, 1.0));
+        assert_eq!(
+            q_table.value_with_bonus(&visited, 0.5),
+            1.0 + 0.5 / 2.0_f32.sqrt()
+        );
+    }
+
+    #[test]
+    fn test_missing_key() {
+        use crate::actions::ActionFlattened as Action;
+        use crate::goods::Good;
+        use rand::SeedableRng;
+
+        let mut q_table: QTable<Good, InvLevel, Action> = QTable::new_with_init(
+            vec![Good::Berries],
+            InvLevel::iter().collect(),
+            Action::iter().collect(),
+            &InitStrategy::Constant,
+            0.5,
+        );
+        // A state outside the enumerated items takes the initial value without being inserted.
+        let state = vec![(Good::Fish, InvLevel::High)];
+        let q_key = QKey(state.clone(), Action::Leisure);
+        assert!(!q_table.get_tab().contains_key(&q_key));
+        assert_eq!(q_table.value(&q_key), 0.5);
+        let mut rng = StdRng::seed_from_u64(0);
+        let (_, q) = q_table.sample_action(&state, &mut rng, &RLConfig::default());
+        assert_eq!(q, 0.5);
+        assert_eq!(
+            q_table.greedy_action(&state),
+            Some((Action::ProduceBerries, 0.5))
+        );
+        assert!(!q_table.get_tab().contains_key(&q_key));
+
+        // An update inserts the entry.
+        q_table.get_tab_mut().insert(q_key.clone(), 2.0);
+        assert_eq!(q_table.value(&q_key), 2.0);
+    }
 }
@@ -1,7 +1,9 @@
-use crate::config::core_config;
+use crate::Model;
+use crate::actions::ActionFlattened;
+use crate::config::SharedConfig;
 
 use super::{
-    agent_state::DiscrRep,
+    agent_state::{DiscrRep, StateItem},
     history::History,
     q_table::{QKey, QTable},
     serde_utils,
@@ -12,13 +14,52 @@ use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::collections::HashMap;
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
 use std::fs::File;
 use std::io::Write;
 use std::marker::PhantomData;
+use std::path::Path;
 use strum::IntoEnumIterator;
 
+/// Errors arising when reading a trained model.
 #[derive(Debug)]
+pub enum ModelError {
+    /// The model file could not be read.
+    Io(std::io::Error),
+    /// The model file could not be parsed as JSON.
+    Json(serde_json::Error),
+    /// The state or action space of the model does not match that of the config.
+    SpaceMismatch,
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelError::Io(err) => write!(f, "failed to read model: {}", err),
+            ModelError::Json(err) => write!(f, "failed to parse model: {}", err),
+            ModelError::SpaceMismatch => write!(
+                f,
+                "state or action space of model does not match that of the config"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ModelError {}
+
+impl From<std::io::Error> for ModelError {
+    fn from(err: std::io::Error) -> Self {
+        ModelError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for ModelError {
+    fn from(err: serde_json::Error) -> Self {
+        ModelError::Json(err)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct SARSAModel<T, S, L, A>
 where
     T: DiscrRep<S, L> + Clone,
@@ -33,11 +74,25 @@ where
         + DeserializeOwned,
 {
     /// Q tables indexed by agent ID.
+    #[serde(with = "serde_utils")]
     pub q_tbls: HashMap<u32, QTable<S, L, A>>,
     /// Only learn single table if value is false, while one per agent if true.
     multi_policy: bool,
     agent_state_type: PhantomData<T>,
     pub checkpoint_itr: Option<i32>,
+    /// The fingerprint of the config under which the model is trained (see
+    /// `Config::fingerprint`), if known.
+    #[serde(default)]
+    pub config_fingerprint: Option<u64>,
+    /// The config under which states are represented (see `DiscrRep::representation_with`),
+    /// whose RL config gives the hyperparameters with which the model samples actions and is
+    /// updated. It is not serialized, so must be set again after resuming (see
+    /// `Simulation::resume`).
+    #[serde(skip)]
+    pub config: SharedConfig,
+    /// The temporal-difference error of the most recent update, indexed by agent ID.
+    #[serde(skip)]
+    last_td_errors: HashMap<u32, f32>,
 }
 
 impl<T, S, L, A> SARSAModel<T, S, L, A>
@@ -60,12 +115,38 @@ where
         state_levels: Vec<L>,
         actions: Vec<A>,
         multi_policy: bool,
+    ) -> Self {
+        let config = SharedConfig::default();
+        Self::new_with_config(
+            agent_ids,
+            state_items,
+            state_levels,
+            actions,
+            multi_policy,
+            config,
+        )
+    }
+
+    /// Returns a model trained under the given config, whose RL config determines the
+    /// initialisation of the Q-tables and the hyperparameters.
+    pub fn new_with_config(
+        agent_ids: Vec<u32>,
+        state_items: Vec<S>,
+        state_levels: Vec<L>,
+        actions: Vec<A>,
+        multi_policy: bool,
+        config: SharedConfig,
     ) -> Self {
         let mut q_tbls = HashMap::new();
         for id in agent_ids {
             q_tbls.insert(
                 id,
-                QTable::new(state_items.clone(), state_levels.clone(), actions.clone()),
+                QTable::new_with_rl(
+                    state_items.clone(),
+                    state_levels.clone(),
+                    actions.clone(),
+                    &config.rl,
+                ),
             );
         }
         SARSAModel {
@@ -73,6 +154,9 @@ where
             multi_policy,
             agent_state_type: PhantomData,
             checkpoint_itr: None,
+            config_fingerprint: None,
+            config,
+            last_td_errors: HashMap::new(),
         }
     }
 
@@ -81,17 +165,39 @@ where
     }
 
     pub fn step(&mut self, t: i32, agent_hist: &BTreeMap<u32, History<T, S, L, A>>) {
-        let tau_: i32 = t - core_config().rl.sarsa_n as i32 - 1;
+        let rl = &self.config.rl;
+        let (n, gamma, alpha) = (rl.sarsa_n as usize, rl.gamma, rl.alpha);
+        self.step_with(t, agent_hist, n, gamma, alpha)
+    }
+
+    /// Performs an n-step SARSA update for every agent, with discount factor `gamma` and
+    /// learning rate `alpha`. The target for the state-action n steps before the end of the
+    /// trajectory is the discounted sum of the following n rewards, plus `gamma^n` times the
+    /// value of the final state-action.
+    pub fn step_with(
+        &mut self,
+        t: i32,
+        agent_hist: &BTreeMap<u32, History<T, S, L, A>>,
+        n: usize,
+        gamma: f32,
+        alpha: f32,
+    ) {
+        let tau_: i32 = t - n as i32 - 1;
 
         // do update
         if tau_ >= 0 {
             // update all agents in turn
             for (id, hist) in agent_hist.iter() {
-                let tab = self.get_table_by_id_mut(*id);
+                let config = self.config.clone();
+                let q_tbl = self.get_qtable_by_id_mut(*id);
                 let traj = &hist.trajectory;
 
-                let tau = tau_ as usize;
-                let n = core_config().rl.sarsa_n as usize;
+                // Index tau relative to the end of the trajectory, which may be truncated
+                // to a window of the most recent steps.
+                if traj.len() < n + 1 {
+                    continue;
+                }
+                let tau = traj.len() - n - 1;
                 let mut g: f32 = 0.0;
 
                 // sum n rewards (discounted back)
@@ -99,26 +205,36 @@ where
                     // assuming index (s0,a0,r1),(s1,a1,r2)...
                     // book assumes (s0,a0),(s1,a1,r1)...
                     let r_i = traj[i - 1].reward.val;
-                    g += core_config().rl.gamma.powf((i - tau - 1) as f32) * r_i as f32;
+                    g += gamma.powf((i - tau - 1) as f32) * r_i as f32;
                 }
 
                 // bootstrap using q(n+1)
-                let q_btstrap = tab
-                    .get(&traj[tau + n].representation())
-                    .expect("all possible state-actions will be in the QTable");
-                g += core_config().rl.gamma.powf(n as f32) * q_btstrap;
-
-                // update q for (s_tau,a_tau)
-                let mut q_tau = *tab
-                    .get(&traj[tau].representation())
-                    .expect("all possible state-actions will be in the QTable");
-                q_tau += core_config().rl.alpha * (g - q_tau);
-                let old_q = tab.insert(traj[tau].representation(), q_tau);
-                // println!("{:?} -> {:?}", old_q, q_tau)
+                let q_btstrap = q_tbl.value(&traj[tau + n].representation_with(&config));
+                g += gamma.powf(n as f32) * q_btstrap;
+
+                // update q for (s_tau,a_tau), inserting the entry if it is missing
+                let q_key = traj[tau].representation_with(&config);
+                let q_tau = q_tbl.value_mut(&q_key);
+                let td_error = g - *q_tau;
+                *q_tau += alpha * td_error;
+                q_tbl.record_visit(&q_key);
+                self.last_td_errors.insert(*id, td_error);
             }
         }
     }
 
+    /// Returns the temporal-difference error of the most recent update for the given agent,
+    /// or None if no update has yet been made.
+    pub fn last_td_error(&self, agent_id: u32) -> Option<f32> {
+        self.last_td_errors.get(&agent_id).copied()
+    }
+
+    pub fn get_qtable_by_id_mut(&mut self, id: u32) -> &mut QTable<S, L, A> {
+        self.q_tbls
+            .get_mut(&self.policy_id(id))
+            .expect("qtable was initialised for all agent id's")
+    }
+
     pub fn get_table_by_id_mut(&mut self, id: u32) -> &mut HashMap<QKey<S, L, A>, f32> {
         self.q_tbls
             .get_mut(&self.policy_id(id))
@@ -133,12 +249,62 @@ where
             .get_tab()
     }
 
+    /// Returns the greedy action in every state of the given agent's Q-table, sorted by state.
+    /// Ties are broken as in `QTable::greedy_action`.
+    pub fn greedy_policy_table(&self, agent_id: u32) -> Vec<(Vec<(S, L)>, A)>
+    where
+        S: Ord,
+        L: Ord,
+        A: Ord,
+    {
+        let q_tbl = self
+            .q_tbls
+            .get(&self.policy_id(agent_id))
+            .expect("qtable was initialised for all agent id's");
+        q_tbl
+            .all_states()
+            .into_iter()
+            .filter_map(|state| q_tbl.greedy_action(&state).map(|(a, _)| (state, a)))
+            .collect()
+    }
+
+    /// Returns the greedy action in the given state under the given agent's Q-table.
+    pub fn greedy_action_by_id(&self, id: u32, state: &Vec<(S, L)>) -> A
+    where
+        A: Ord,
+    {
+        self.q_tbls
+            .get(&self.policy_id(id))
+            .expect("qtable was initialised for all agent id's")
+            .greedy_action(state)
+            .expect("there is at least one action")
+            .0
+    }
+
+    /// Returns the value of each action in the given state under the given agent's Q-table,
+    /// e.g. to log how the agent's expectations evolve during training.
+    pub fn action_values<R: DiscrRep<S, L>>(&self, agent_id: u32, state: &R) -> Vec<(A, f32)> {
+        let q_tbl = self
+            .q_tbls
+            .get(&self.policy_id(agent_id))
+            .expect("qtable was initialised for all agent id's");
+        let rep = state.representation_with(&self.config);
+        q_tbl
+            .actions()
+            .into_iter()
+            .map(|a| {
+                let q = q_tbl.value(&QKey(rep.clone(), a.clone()));
+                (a, q)
+            })
+            .collect()
+    }
+
     pub fn sample_action_by_id(&self, id: u32, state: &Vec<(S, L)>, rng: &mut StdRng) -> A {
         let (a, q_optimal) = self
             .q_tbls
             .get(&self.policy_id(id))
             .expect("qtable was initialised for all agent id's")
-            .sample_action(state, rng);
+            .sample_action(state, rng, &self.config.rl);
         if id == 0 {
             // println!("{}", q_optimal)
         }
@@ -199,6 +365,37 @@ where
     // }
 }
 
+impl Model {
+    /// Reads a previously trained model from the JSON file at the given path (e.g. the model
+    /// of a checkpoint), as a warm start for training under the given config, whose
+    /// hyperparameters may differ from those the model was trained with. Fails if the state
+    /// or action space of the trained model does not match that of the config.
+    ///
+    /// The Q-values are kept, but the config fingerprint is cleared, as the model is no
+    /// longer trained under a single config.
+    pub fn from_pretrained(path: &Path, config: SharedConfig) -> Result<Self, ModelError> {
+        let contents = std::fs::read_to_string(path)?;
+        let model: Self = serde_json::from_str(&contents)?;
+        let state_items: Vec<StateItem> = StateItem::iter_with(&config.enabled_goods).collect();
+        let actions = ActionFlattened::enabled_with(&config);
+        let matches = model.q_tbls.values().all(|q_tbl| {
+            q_tbl.actions() == actions
+                && q_tbl
+                    .get_tab()
+                    .keys()
+                    .all(|QKey(state, _)| state.iter().map(|(item, _)| item).eq(state_items.iter()))
+        });
+        if !matches {
+            return Err(ModelError::SpaceMismatch);
+        }
+        Ok(SARSAModel {
+            config,
+            config_fingerprint: None,
+            ..model
+        })
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SARSACheckpoint<S, L, A>
 where
@@ -235,3 +432,199 @@ where
         serde_json::from_str::<SARSACheckpoint<S, L, A>>(&serial).unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Model;
+    use crate::actions::ActionFlattened as Action;
+    use crate::config::{Config, RLConfig};
+    use crate::goods::{Good, GoodsUnit};
+    use crate::learning::agent_state::StateItem;
+    use crate::learning::history::SAR;
+    use crate::learning::reward::Reward;
+    use crate::stock::{InvLevel, Stock};
+    use crate::{lazy_test_model, test_model};
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_td_error() {
+        let mut model = lazy_test_model(RLConfig::default());
+        let s0 = Stock::default();
+        let mut s1 = Stock::default();
+        s1.add(GoodsUnit::new(&Good::Berries), 10).unwrap();
+        let mut hist = History::new();
+        hist.push(SAR::new(s0, Action::ProduceBerries, Reward::new(1)));
+        hist.push(SAR::new(s1, Action::Leisure, Reward::new(0)));
+        let q_next = 2.0;
+        model
+            .get_table_by_id_mut(0)
+            .insert(hist.trajectory[1].representation(), q_next);
+        let agent_hist = BTreeMap::from([(0, hist)]);
+
+        assert_eq!(model.last_td_error(0), None);
+        let (gamma, alpha) = (0.5, 0.1);
+        model.step_with(2, &agent_hist, 1, gamma, alpha);
+
+        // The target is the reward plus the discounted value of the next state-action,
+        // and Q(s0, a0) starts at zero.
+        let td_error = 1.0 + gamma * q_next;
+        assert_eq!(model.last_td_error(0), Some(td_error));
+        let q_updated = model.get_table_by_id(0)[&agent_hist[&0].trajectory[0].representation()];
+        assert_eq!(q_updated, alpha * td_error);
+    }
+
+    #[test]
+    fn test_lazy_step() {
+        use crate::config::{Config, RLConfig, SharedConfig};
+
+        let rl = RLConfig {
+            lazy_q_table: true,
+            sarsa_n: 1,
+            init_q_value: 0.5,
+            ..RLConfig::default()
+        };
+        let config = SharedConfig::new(Config {
+            rl: rl.clone(),
+            ..Config::default()
+        });
+        let mut model = Model::new_with_config(
+            vec![0],
+            StateItem::iter().collect(),
+            InvLevel::iter().collect(),
+            Action::enabled(),
+            false,
+            config,
+        );
+        assert!(model.get_table_by_id(0).is_empty());
+
+        let mut s1 = Stock::default();
+        s1.add(GoodsUnit::new(&Good::Berries), 10).unwrap();
+        let mut hist = History::new();
+        hist.push(SAR::new(
+            Stock::default(),
+            Action::ProduceBerries,
+            Reward::new(1),
+        ));
+        hist.push(SAR::new(s1.clone(), Action::Leisure, Reward::new(0)));
+        let mut agent_hist = BTreeMap::from([(0, hist)]);
+        model.step(2, &agent_hist);
+
+        // Only the updated state-action is inserted, starting from its initial value.
+        let table = model.get_table_by_id(0);
+        assert_eq!(table.len(), 1);
+        let target = 1.0 + rl.gamma * rl.init_q_value;
+        let expected = rl.init_q_value + rl.alpha * (target - rl.init_q_value);
+        let q = table[&agent_hist[&0].trajectory[0].representation()];
+        assert!((q - expected).abs() < 1e-6);
+
+        // The table grows by one entry with each further state-action updated.
+        let hist = agent_hist.get_mut(&0).unwrap();
+        hist.push(SAR::new(s1, Action::ProduceBerries, Reward::new(1)));
+        model.step(3, &agent_hist);
+        assert_eq!(model.get_table_by_id(0).len(), 2);
+    }
+
+    #[test]
+    fn test_from_pretrained() {
+        let config = SharedConfig::new(Config {
+            rl: RLConfig {
+                lazy_q_table: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let mut model = Model::new_with_config(
+            vec![0],
+            StateItem::iter_with(&config.enabled_goods).collect(),
+            InvLevel::iter().collect(),
+            Action::enabled_with(&config),
+            false,
+            config.clone(),
+        );
+        let q_key = QKey(
+            Stock::default().representation_with(&config),
+            Action::Leisure,
+        );
+        model.get_table_by_id_mut(0).insert(q_key.clone(), 1.5);
+        model.config_fingerprint = Some(1);
+        let path = std::env::temp_dir().join("crusoe_test_pretrained_model.json");
+        std::fs::write(&path, serde_json::to_string(&model).unwrap()).unwrap();
+
+        let rl = RLConfig {
+            epsilon: 0.5,
+            alpha: 0.3,
+            ..config.rl.clone()
+        };
+        let warm_config = SharedConfig::new(Config {
+            rl: rl.clone(),
+            ..(*config).clone()
+        });
+        let warm_started = Model::from_pretrained(&path, warm_config);
+        // The state space of a run with fewer goods enabled does not match.
+        let mismatched = Model::from_pretrained(
+            &path,
+            SharedConfig::new(Config {
+                enabled_goods: HashSet::from([Good::Berries]),
+                ..(*config).clone()
+            }),
+        );
+        std::fs::remove_file(&path).unwrap();
+        let warm_started = warm_started.unwrap();
+        assert_eq!(warm_started.get_table_by_id(0), model.get_table_by_id(0));
+        assert_eq!(warm_started.get_table_by_id(0)[&q_key], 1.5);
+        assert_eq!(warm_started.config.rl, rl);
+        assert_eq!(warm_started.config_fingerprint, None);
+        assert!(matches!(mismatched, Err(ModelError::SpaceMismatch)));
+
+        // A missing file is reported as such.
+        assert!(matches!(
+            Model::from_pretrained(&path, config),
+            Err(ModelError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_greedy_policy_table() {
+        let mut model = test_model(vec![StateItem::Partial]);
+        let state = |level| vec![(StateItem::Partial, level)];
+        let table = model.get_table_by_id_mut(0);
+        table.insert(QKey(state(InvLevel::Low), Action::Leisure), 1.0);
+        table.insert(QKey(state(InvLevel::Medium), Action::ProduceFish), 2.0);
+        table.insert(QKey(state(InvLevel::Medium), Action::Consume), 1.0);
+
+        // With every value equal, the first action is greedy.
+        assert_eq!(
+            model.greedy_policy_table(0),
+            vec![
+                (state(InvLevel::Low), Action::Leisure),
+                (state(InvLevel::Medium), Action::ProduceFish),
+                (state(InvLevel::High), Action::ProduceBerries),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_action_values() {
+        let mut model = lazy_test_model(RLConfig::default());
+        let empty = Stock::default().discretise();
+        let table = model.get_table_by_id_mut(0);
+        table.insert(QKey(empty.representation(), Action::Leisure), 1.5);
+        table.insert(QKey(empty.representation(), Action::ProduceFish), -2.0);
+
+        let values = model.action_values(0, &empty);
+        assert_eq!(values.len(), Action::enabled().len());
+        for (action, q) in values {
+            match action {
+                Action::Leisure => assert_eq!(q, 1.5),
+                Action::ProduceFish => assert_eq!(q, -2.0),
+                _ => assert_eq!(q, 0.0),
+            }
+        }
+        // The discrete stock has the same representation as the stock itself.
+        assert_eq!(
+            model.action_values(0, &Stock::default()),
+            model.action_values(0, &empty)
+        );
+    }
+}
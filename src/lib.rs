@@ -1,23 +1,65 @@
 use crate::{
     actions::ActionFlattened as Action,
-    goods::GoodsUnitLevel,
-    learning::tabular_rl::SARSAModel,
+    learning::{agent_state::StateItem, history::History, tabular_rl::SARSAModel},
     stock::{InvLevel, Stock},
 };
 
 pub mod actions;
 pub mod agent;
 pub mod config;
+pub mod experiment;
 pub mod goods;
 pub mod learning;
+pub mod resource;
+pub mod season;
 pub mod simulation;
 pub mod stock;
+pub mod trade;
 pub mod valuation;
 
 pub type Int = i32;
 pub type UInt = u32;
 
-pub type Model = SARSAModel<Stock, GoodsUnitLevel, InvLevel, Action>;
+pub type Model = SARSAModel<Stock, StateItem, InvLevel, Action>;
+pub type AgentHistory = History<Stock, StateItem, InvLevel, Action>;
+
+/// Returns a single-policy model for agent 0 over the given state items, with every
+/// inventory level and every action enabled under the default config, for use in tests.
+#[cfg(test)]
+pub(crate) fn test_model(state_items: Vec<StateItem>) -> Model {
+    use strum::IntoEnumIterator;
+    Model::new(
+        vec![0],
+        state_items,
+        InvLevel::iter().collect(),
+        Action::enabled(),
+        false,
+    )
+}
+
+/// Returns a single-policy model for agent 0 over every state item, as in `test_model`, but
+/// whose Q-table is lazy (see `RLConfig::lazy_q_table`) and otherwise initialised as given,
+/// so that tests running a simulation need not allocate the dense table.
+#[cfg(test)]
+pub(crate) fn lazy_test_model(rl: config::RLConfig) -> Model {
+    use config::{Config, RLConfig, SharedConfig};
+    use strum::IntoEnumIterator;
+    let config = Config {
+        rl: RLConfig {
+            lazy_q_table: true,
+            ..rl
+        },
+        ..Config::default()
+    };
+    Model::new_with_config(
+        vec![0],
+        StateItem::iter().collect(),
+        InvLevel::iter().collect(),
+        Action::enabled(),
+        false,
+        SharedConfig::new(config),
+    )
+}
 
 const POSITIVE_REWARD: Int = 1;
 const NEGATIVE_REWARD: Int = -100000;
@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{UInt, config::ResourceConfig, goods::Good};
+
+/// The renewable resources from which goods are harvested, keyed by the harvested good.
+pub type Resources = BTreeMap<Good, ResourceState>;
+
+/// The state of a renewable resource (e.g. a berry patch), which is depleted by harvesting
+/// and regenerates over time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResourceState {
+    pub level: f32,
+    pub capacity: f32,
+    pub regeneration_rate: f32,
+}
+
+// Resource levels are always finite, so equality is reflexive.
+impl Eq for ResourceState {}
+
+impl ResourceState {
+    /// Returns a resource at full capacity.
+    pub fn new(config: &ResourceConfig) -> Self {
+        ResourceState {
+            level: config.capacity,
+            capacity: config.capacity,
+            regeneration_rate: config.regeneration_rate,
+        }
+    }
+
+    /// Returns the resources described in the config, each at full capacity.
+    pub fn from_configs(configs: &[ResourceConfig]) -> Resources {
+        configs
+            .iter()
+            .map(|config| (config.good, ResourceState::new(config)))
+            .collect()
+    }
+
+    /// Returns the yield of a harvest from the resource, given the quantity that would be
+    /// produced from a resource at full capacity. The yield is reduced in proportion to the
+    /// depletion of the resource, and cannot exceed its current level.
+    pub fn yield_of(&self, quantity: UInt) -> UInt {
+        let fraction = self.level / self.capacity;
+        f32::min((quantity as f32 * fraction).floor(), self.level.floor()) as UInt
+    }
+
+    /// Harvests from the resource, given the quantity that would be produced from a resource at
+    /// full capacity (see `yield_of`). Returns the quantity harvested.
+    pub fn harvest(&mut self, quantity: UInt) -> UInt {
+        self.take(self.yield_of(quantity))
+    }
+
+    /// Removes the given quantity from the resource, up to its current level. Returns the
+    /// quantity removed.
+    pub fn take(&mut self, quantity: UInt) -> UInt {
+        let taken = f32::min(quantity as f32, self.level.floor());
+        self.level -= taken;
+        taken as UInt
+    }
+
+    /// Regenerates the resource for one time step, closing a fixed fraction of the gap between
+    /// its current level and its capacity.
+    pub fn regenerate(&mut self) {
+        self.level += self.regeneration_rate * (self.capacity - self.level);
+    }
+}
+
+/// Capital goods shared by every agent in a simulation (e.g. a single boat), rather than owned
+/// individually. Each unit can be used by only one agent per time step, so agents are served
+/// in turn until every unit is in use.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SharedResources {
+    /// The number of units of each shared good.
+    pub tools: BTreeMap<Good, UInt>,
+    /// The number of units of each shared good in use during the current time step.
+    in_use: BTreeMap<Good, UInt>,
+}
+
+impl SharedResources {
+    /// Returns shared resources with the given number of units of each good, none in use.
+    pub fn new(tools: BTreeMap<Good, UInt>) -> Self {
+        SharedResources {
+            tools,
+            in_use: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the number of units of a shared good not yet in use in the current time step.
+    pub fn available(&self, good: &Good) -> UInt {
+        let total = self.tools.get(good).copied().unwrap_or(0);
+        total.saturating_sub(self.in_use.get(good).copied().unwrap_or(0))
+    }
+
+    /// Returns the shared goods of which at least one unit is available.
+    pub fn available_tools(&self) -> Vec<Good> {
+        self.tools
+            .keys()
+            .filter(|good| self.available(good) > 0)
+            .copied()
+            .collect()
+    }
+
+    /// Claims a unit of a shared good for the rest of the current time step. Returns false
+    /// if no unit is available.
+    pub fn claim(&mut self, good: &Good) -> bool {
+        if self.available(good) == 0 {
+            return false;
+        }
+        *self.in_use.entry(*good).or_default() += 1;
+        true
+    }
+
+    /// Returns every unit in use to the pool, at the end of a time step.
+    pub fn release_all(&mut self) {
+        self.in_use.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_resources() {
+        let mut shared = SharedResources::new(BTreeMap::from([(Good::Boat, 1)]));
+        assert_eq!(shared.available_tools(), vec![Good::Boat]);
+        assert!(shared.claim(&Good::Boat));
+        assert!(!shared.claim(&Good::Boat));
+        assert!(!shared.claim(&Good::Spear));
+        assert!(shared.available_tools().is_empty());
+        shared.release_all();
+        assert_eq!(shared.available(&Good::Boat), 1);
+    }
+
+    #[test]
+    fn test_sustained_harvest() {
+        let mut resource = ResourceState::new(&ResourceConfig {
+            good: Good::Berries,
+            capacity: 40.0,
+            regeneration_rate: 0.05,
+        });
+        let yields: Vec<UInt> = (0..10)
+            .map(|_| {
+                let harvested = resource.harvest(4);
+                resource.regenerate();
+                harvested
+            })
+            .collect();
+        assert_eq!(yields[0], 4);
+        // Sustained harvesting reduces the daily yield.
+        assert!(yields.windows(2).all(|w| w[1] <= w[0]));
+        assert!(yields[9] < yields[0]);
+        assert!(resource.level < resource.capacity);
+    }
+
+    #[test]
+    fn test_regenerate() {
+        let mut resource = ResourceState::new(&ResourceConfig {
+            good: Good::Fish,
+            capacity: 10.0,
+            regeneration_rate: 0.5,
+        });
+        assert_eq!(resource.harvest(10), 10);
+        assert_eq!(resource.level, 0.0);
+        resource.level = 4.0;
+        resource.regenerate();
+        assert_eq!(resource.level, 7.0);
+    }
+}
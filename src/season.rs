@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+use crate::UInt;
+
+/// A season of the year, which cycles with the simulation time (see `SeasonConfig`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, EnumIter)]
+pub enum Season {
+    Spring,
+    Summer,
+    Autumn,
+    Winter,
+}
+
+impl Season {
+    /// Returns the season at the given time, where each season lasts `period` timesteps and
+    /// the first begins at time zero.
+    pub fn at(time: UInt, period: UInt) -> Season {
+        let index = (time / period.max(1)) as usize % Season::iter().count();
+        Season::iter()
+            .nth(index)
+            .expect("index is less than the number of seasons")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_season_at() {
+        assert_eq!(Season::at(0, 10), Season::Spring);
+        assert_eq!(Season::at(9, 10), Season::Spring);
+        assert_eq!(Season::at(10, 10), Season::Summer);
+        assert_eq!(Season::at(35, 10), Season::Winter);
+        // The seasons cycle.
+        assert_eq!(Season::at(40, 10), Season::Spring);
+    }
+}
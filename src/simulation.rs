@@ -1,23 +1,222 @@
-use crate::actions::ActionFlattened as Action;
-use crate::agent::{Agent, AgentType, CrusoeAgent};
-use crate::config::Config;
-use crate::goods::GoodsUnitLevel;
-use crate::learning::history::{History, SAR};
-use crate::learning::learning_agent::LearningAgent;
+use crate::actions::Action;
+use crate::actions::ActionFlattened;
+use crate::agent::{Agent, AgentType};
+use crate::config::{Config, SharedConfig};
+use crate::goods::Good;
+use crate::learning::agent_state::{DiscrRep, StateItem};
+use crate::learning::history::History;
+use crate::learning::reward::Reward;
+use crate::resource::{ResourceState, Resources, SharedResources};
+use crate::season::Season;
 use crate::stock::{InvLevel, Stock};
-use crate::{Model, UInt};
+use crate::{AgentHistory, Model, UInt};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
 use std::vec::Vec;
+use strum::IntoEnumIterator;
 
 // TODO: add RL algorithm
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Simulation {
     pub time: UInt,
     pub agents: Vec<AgentType>,
-    pub config: Config,
-    pub agent_hist: BTreeMap<u32, History<Stock, GoodsUnitLevel, InvLevel, Action>>,
+    /// The config under which the simulation runs, shared with every agent.
+    pub config: SharedConfig,
+    pub agent_hist: BTreeMap<u32, AgentHistory>,
     pub verbose: bool,
+    /// Number of times any agent failed to meet its nutritional requirement.
+    pub death_count: UInt,
+    /// The state of each renewable resource.
+    pub resources: Resources,
+    /// The capital goods shared by all agents.
+    #[serde(default)]
+    pub shared: SharedResources,
+    /// The time at which each agent first failed to meet its nutritional requirement.
+    pub death_times: BTreeMap<u64, UInt>,
+    /// A custom reward function which, if set, replaces the reward each agent would otherwise
+    /// receive for its action. It is not serialized, so must be set again after resuming.
+    #[serde(skip)]
+    pub reward_fn: Option<RewardFn>,
+    /// The events of the simulation, if configured to record them.
+    #[serde(default)]
+    pub events: Vec<SimEvent>,
+}
+
+/// An event in the simulation, at the given time and involving the agent with the given id.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SimEvent {
+    /// Units of a good were produced (on completion, for a multi-step good).
+    Produced {
+        time: UInt,
+        agent_id: u64,
+        good: Good,
+        quantity: UInt,
+    },
+    /// Units of a perishable good expired in the agent's stock.
+    Spoiled {
+        time: UInt,
+        agent_id: u64,
+        good: Good,
+        quantity: UInt,
+    },
+    /// The agent failed to meet its nutritional requirement.
+    Died { time: UInt, agent_id: u64 },
+}
+
+impl SimEvent {
+    /// Returns the events of a step at the given time in which the agent, having produced
+    /// the given totals of each good before the step, survived or died.
+    fn of_step(
+        time: UInt,
+        agent: &AgentType,
+        production_totals: &HashMap<Good, UInt>,
+        is_alive: bool,
+    ) -> Vec<SimEvent> {
+        let agent_id = agent.get_id();
+        let mut events: Vec<SimEvent> = Good::iter()
+            .filter_map(|good| {
+                let before = production_totals.get(&good).copied().unwrap_or(0);
+                let after = agent.production_totals().get(&good).copied().unwrap_or(0);
+                (after > before).then_some(SimEvent::Produced {
+                    time,
+                    agent_id,
+                    good,
+                    quantity: after - before,
+                })
+            })
+            .collect();
+        match (is_alive, agent.stock_history().last()) {
+            // Perishable units are lost only by expiring when the stock is stepped forward.
+            (true, Some(stock)) => events.extend(
+                Good::iter()
+                    .filter(|good| good.is_consumer() || good.is_material())
+                    .filter_map(|good| {
+                        let lost = stock
+                            .count_units(&good)
+                            .saturating_sub(agent.stock().count_units(&good));
+                        (lost > 0).then_some(SimEvent::Spoiled {
+                            time,
+                            agent_id,
+                            good,
+                            quantity: lost,
+                        })
+                    }),
+            ),
+            (true, None) => (),
+            (false, _) => events.push(SimEvent::Died { time, agent_id }),
+        }
+        events
+    }
+}
+
+/// A function of the stock in which an action is taken, the action, and whether the agent
+/// survived the time step, returning the reward.
+pub type RewardClosure = dyn Fn(&Stock, Action, bool) -> Reward;
+
+/// A boxed reward function, overriding the agent's own reward.
+pub struct RewardFn(pub Box<RewardClosure>);
+
+impl fmt::Debug for RewardFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RewardFn")
+    }
+}
+
+/// File name of the simulation in a checkpoint directory.
+const SIMULATION_CHECKPOINT_FILE: &str = "simulation.json";
+/// File name of the model in a checkpoint directory.
+const MODEL_CHECKPOINT_FILE: &str = "model.json";
+
+/// Errors arising when writing or reading a checkpoint.
+#[derive(Debug)]
+pub enum CheckpointError {
+    /// A checkpoint file could not be written or read.
+    Io(std::io::Error),
+    /// A checkpoint file could not be serialized or parsed as JSON.
+    Json(serde_json::Error),
+    /// The model was trained under a config with a different fingerprint from the simulation's.
+    ConfigMismatch { simulation: u64, model: u64 },
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(err) => write!(f, "failed to access checkpoint: {}", err),
+            CheckpointError::Json(err) => write!(f, "failed to (de)serialize checkpoint: {}", err),
+            CheckpointError::ConfigMismatch { simulation, model } => write!(
+                f,
+                "model was trained under a different config (fingerprint {:x}, expected {:x})",
+                model, simulation
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl From<std::io::Error> for CheckpointError {
+    fn from(err: std::io::Error) -> Self {
+        CheckpointError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for CheckpointError {
+    fn from(err: serde_json::Error) -> Self {
+        CheckpointError::Json(err)
+    }
+}
+
+/// A compact summary of the outcome of a simulation.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SimulationSummary {
+    pub total_steps: UInt,
+    pub death_count: UInt,
+    pub agents: Vec<AgentSummary>,
+}
+
+/// Number of most recent steps over which the trailing average reward is taken.
+pub const TRAILING_REWARD_WINDOW: usize = 10000;
+
+/// The result of a finished simulation, keyed by agent id.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RunOutcome {
+    pub total_steps: UInt,
+    pub death_count: UInt,
+    /// Sum of each agent's rewards over its retained history.
+    pub total_rewards: BTreeMap<u64, i64>,
+    /// Mean of each agent's rewards over the last `TRAILING_REWARD_WINDOW` steps.
+    pub trailing_avg_rewards: BTreeMap<u64, f32>,
+    pub final_stocks: BTreeMap<u64, Stock>,
+}
+
+/// The performance of a model's greedy policy over several episodes, pooled over every
+/// agent in every episode.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PolicyEvaluation {
+    pub episodes: UInt,
+    pub mean_survival_time: f32,
+    pub median_survival_time: f32,
+    pub mean_total_reward: f32,
+}
+
+/// A compact summary of the outcome for a single agent.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AgentSummary {
+    pub id: u64,
+    /// Number of steps before the agent first failed to meet its nutritional requirement,
+    /// or the total number of steps if it never did.
+    pub survival_time: UInt,
+    /// Value of the agent's final stock, at the prices given to `Simulation::summary_with_prices`.
+    pub final_stock_value: f32,
+    /// Sum of the agent's rewards over its retained history.
+    pub total_reward: i64,
+    /// Number of units of each good the agent has ever produced.
+    pub production_totals: BTreeMap<Good, UInt>,
 }
 
 impl Default for Simulation {
@@ -25,20 +224,34 @@ impl Default for Simulation {
         Simulation {
             time: 0,
             agents: Vec::new(),
-            config: Config {
+            config: SharedConfig::new(Config {
                 max_time: 100,
                 ..Default::default()
-            },
+            }),
             agent_hist: BTreeMap::new(),
             verbose: true,
+            death_count: 0,
+            resources: Resources::new(),
+            shared: SharedResources::default(),
+            death_times: BTreeMap::new(),
+            reward_fn: None,
+            events: vec![],
         }
     }
 }
 
 impl Simulation {
+    /// Returns a simulation of the population of agents described in the config.
     pub fn new(config: Config, verbose: bool) -> Self {
-        // TODO: add n_agents to config
-        // let num_agents = 10;
+        let agents = AgentType::from_specs(&config.agents);
+        Self::with_agents(config, verbose, agents)
+    }
+
+    /// Returns a simulation of the given agents, each of which acts under the config and is
+    /// given the partially complete goods of the configured endowment.
+    pub fn with_agents(config: Config, verbose: bool, mut agents: Vec<AgentType>) -> Self {
+        let config = SharedConfig::new(config);
+        // TODO: add RL model to config
         // let multi_policy = false;
         // let model = SARSAModel::new(
         //     (0..num_agents).map(|n| n.into()).collect(),
@@ -47,19 +260,48 @@ impl Simulation {
         //     Action::iter().collect::<Vec<Action>>(),
         //     multi_policy,
         // );
-        let mut agent_hist = BTreeMap::new();
-        agent_hist.insert(0, History::new());
+        let agent_hist = agents
+            .iter()
+            .map(|agent| (agent.get_id() as u32, History::new()))
+            .collect();
+        if let Some(seed) = config.seed {
+            for agent in agents.iter_mut() {
+                let agent_seed = seed.wrapping_add(agent.get_id());
+                *agent.rng() = StdRng::seed_from_u64(agent_seed);
+            }
+        }
+        for agent in agents.iter_mut() {
+            agent.set_config(config.clone());
+            for partial in &config.partial_endowment {
+                agent
+                    .acquire_partial(*partial)
+                    .expect("partial endowment should be valid");
+            }
+        }
+        let resources = ResourceState::from_configs(&config.resources);
+        let shared = SharedResources::new(config.shared_tools.clone().into_iter().collect());
         Simulation {
             time: 0,
-            // agents: vec![AgentType::Crusoe(CrusoeAgent::new(0))], // Initialize with one Crusoe agent
-            agents: vec![AgentType::Rl(LearningAgent::new(0))], // Initialize with one RL agent
+            agents,
             config,
             agent_hist,
             verbose,
+            death_count: 0,
+            resources,
+            shared,
+            death_times: BTreeMap::new(),
+            reward_fn: None,
+            events: vec![],
         }
     }
 
     pub fn step_forward(&mut self, model: &Model) {
+        self.step_forward_with(|agent| agent.choose_action_with_model(model))
+    }
+
+    /// Steps forward every living agent, each taking the action chosen by the given function.
+    fn step_forward_with(&mut self, mut choose_action: impl FnMut(&mut AgentType) -> Action) {
+        let season = self.season();
         // Step forward each agent.
         // Per day:
         // - Start the day
@@ -72,23 +314,58 @@ impl Simulation {
         // - Degrade the agent's stock
         // - End the day
         // println!("{:#?}", self);
+        // Agents act in a fixed order, so those earlier in `agents` have the first claim on
+        // any shared goods (see `SharedResources`).
         for agent in self.agents.iter_mut() {
             // Check agent is alive
             if !agent.is_alive() {
                 continue; // Skip dead agents
             }
-            let action = agent.choose_action_with_model(model);
-            agent.step_forward(Some(action));
-            self.agent_hist
-                // TODO: update to use more than just agent with ID 0
-                .entry(0)
-                .or_insert_with(History::new)
-                .push(SAR::new(
-                    agent.stock().clone(),
-                    *agent.action_history().last().unwrap(),
-                    *agent.reward_history().last().unwrap(),
-                ))
+            agent.set_season(season);
+            agent.set_resources(self.resources.clone());
+            let action = choose_action(agent);
+            // The stock in which the action is taken, for any custom reward function.
+            let stock = self.reward_fn.as_ref().map(|_| agent.stock().clone());
+            let production_totals = self
+                .config
+                .record_events
+                .then(|| agent.production_totals().clone());
+            let hist = self
+                .agent_hist
+                .entry(agent.get_id() as u32)
+                .or_insert_with(History::new);
+            let is_alive =
+                agent.step_and_record(Some(action), hist, &mut self.resources, &mut self.shared);
+            if let (Some(RewardFn(reward_fn)), Some(stock)) = (&self.reward_fn, stock) {
+                let reward = reward_fn(&stock, action, is_alive);
+                if let Some(last) = agent.reward_history_mut().last_mut() {
+                    *last = reward;
+                }
+                if let Some(last) = hist.trajectory.last_mut() {
+                    last.reward = reward;
+                }
+            }
+            if let Some(production_totals) = production_totals {
+                self.events.extend(SimEvent::of_step(
+                    self.time,
+                    agent,
+                    &production_totals,
+                    is_alive,
+                ));
+            }
+            if !is_alive {
+                self.death_count += 1;
+                self.death_times.entry(agent.get_id()).or_insert(self.time);
+            }
+            if let Some(window) = self.config.history_window {
+                agent.truncate_history(window);
+                hist.truncate(window);
+            }
+        }
+        for resource in self.resources.values_mut() {
+            resource.regenerate();
         }
+        self.shared.release_all();
         self.after_step();
     }
 
@@ -101,22 +378,241 @@ impl Simulation {
         // Execute that trade by updating the stocks of the two agents involved.
     }
 
-    // Run simulation
+    // Run simulation, updating the model given the agent history at each step.
     pub fn run(&mut self, model: &mut Model) {
+        self.run_with_output(model, &mut io::stdout())
+            .expect("failed to write to stdout");
+    }
+
+    /// Runs the simulation as in `run`, writing any verbose output to the given writer.
+    /// Nothing is written unless the simulation is verbose.
+    pub fn run_with_output(&mut self, model: &mut Model, out: &mut impl Write) -> io::Result<()> {
         while self.time < self.config.max_time {
             self.step_forward(model);
             if self.verbose {
-                println!("Time: {}, Agents: {}", self.time, self.agents.len());
-                println!("Actions:  {0:#?}", self.agents[0]);
+                writeln!(out, "Time: {}, Agents: {}", self.time, self.agents.len())?;
+                writeln!(out, "Actions:  {0:#?}", self.agents[0])?;
             }
             self.time += 1;
+            model.step(self.time as i32, &self.agent_hist);
+            if self.config.stop_when_all_dead && self.all_dead() {
+                break;
+            }
         }
+        Ok(())
+    }
+
+    /// Returns a newly-initialised model of the agents of the simulation, under its config.
+    pub fn new_model(&self) -> Model {
+        let mut model = Model::new_with_config(
+            self.agents
+                .iter()
+                .map(|agent| agent.get_id() as u32)
+                .collect(),
+            StateItem::iter_with(&self.config.enabled_goods).collect(),
+            InvLevel::iter().collect(),
+            ActionFlattened::enabled_with(&self.config),
+            self.config.rl.multi_policy,
+            self.config.clone(),
+        );
+        model.config_fingerprint = Some(self.config.fingerprint());
+        model
+    }
+
+    /// Writes the simulation (including the current time) and the model to the given
+    /// directory, creating it if necessary and replacing any existing checkpoint.
+    pub fn checkpoint(&self, model: &Model, dir: &Path) -> Result<(), CheckpointError> {
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(
+            dir.join(SIMULATION_CHECKPOINT_FILE),
+            serde_json::to_string(self)?,
+        )?;
+        std::fs::write(
+            dir.join(MODEL_CHECKPOINT_FILE),
+            serde_json::to_string(model)?,
+        )?;
+        Ok(())
+    }
+
+    /// Reads a simulation and model from a checkpoint written by `checkpoint`. Fails if the
+    /// model records a config fingerprint that does not match the simulation's config.
+    ///
+    /// Random number generators are not checkpointed: each agent's generator is reseeded
+    /// from the config seed (offset by the current time) or, if there is no seed, from OS entropy.
+    pub fn resume(dir: &Path) -> Result<(Simulation, Model), CheckpointError> {
+        let contents = std::fs::read_to_string(dir.join(SIMULATION_CHECKPOINT_FILE))?;
+        let mut sim: Simulation = serde_json::from_str(&contents)?;
+        let contents = std::fs::read_to_string(dir.join(MODEL_CHECKPOINT_FILE))?;
+        let mut model: Model = serde_json::from_str(&contents)?;
+        if let Some(fingerprint) = model.config_fingerprint
+            && fingerprint != sim.config.fingerprint()
+        {
+            return Err(CheckpointError::ConfigMismatch {
+                simulation: sim.config.fingerprint(),
+                model: fingerprint,
+            });
+        }
+        if let Some(seed) = sim.config.seed {
+            for agent in sim.agents.iter_mut() {
+                let agent_seed = seed
+                    .wrapping_add(agent.get_id())
+                    .wrapping_add(sim.time as u64);
+                *agent.rng() = StdRng::seed_from_u64(agent_seed);
+            }
+        }
+        // Configs are serialized with the simulation only, so are shared again on resuming.
+        for agent in sim.agents.iter_mut() {
+            agent.set_config(sim.config.clone());
+        }
+        model.config = sim.config.clone();
+        Ok((sim, model))
+    }
+
+    /// Evaluates the greedy policy of the model over the given number of fresh episodes,
+    /// each starting from the population described in the config and lasting at most
+    /// `max_steps` steps or until every agent is dead. The model is not updated.
+    pub fn evaluate_policy(
+        &self,
+        model: &Model,
+        episodes: UInt,
+        max_steps: UInt,
+    ) -> PolicyEvaluation {
+        let mut survival_times = Vec::new();
+        let mut total_rewards = Vec::new();
+        for episode in 0..episodes {
+            let config = Config {
+                max_time: max_steps,
+                stop_when_all_dead: true,
+                // Seeded episodes differ from one another but are reproducible.
+                seed: self
+                    .config
+                    .seed
+                    .map(|seed| seed.wrapping_add(episode as u64)),
+                ..(*self.config).clone()
+            };
+            let mut sim = Simulation::new(config, false);
+            while sim.time < max_steps && !sim.all_dead() {
+                sim.step_forward_with(|agent| {
+                    let state = agent.stock().representation_with(agent.config());
+                    let id = agent.get_id() as u32;
+                    let action: Action = model.greedy_action_by_id(id, &state).into();
+                    agent.action_history_mut().push(action);
+                    action
+                });
+                sim.time += 1;
+            }
+            for agent in sim.summary().agents {
+                survival_times.push(agent.survival_time);
+                total_rewards.push(agent.total_reward);
+            }
+        }
+        let count = survival_times.len().max(1) as f32;
+        survival_times.sort();
+        let median_survival_time = match survival_times.len() {
+            0 => 0.0,
+            len if len % 2 == 0 => {
+                (survival_times[len / 2 - 1] + survival_times[len / 2]) as f32 / 2.0
+            }
+            len => survival_times[len / 2] as f32,
+        };
+        PolicyEvaluation {
+            episodes,
+            mean_survival_time: survival_times.iter().sum::<UInt>() as f32 / count,
+            median_survival_time,
+            mean_total_reward: total_rewards.iter().sum::<i64>() as f32 / count,
+        }
+    }
+
+    /// Returns the current season, or None if seasons are not configured.
+    pub fn season(&self) -> Option<Season> {
+        self.config
+            .seasons
+            .as_ref()
+            .map(|seasons| seasons.season_at(self.time))
+    }
+
+    /// Returns true if no agent in the simulation is alive.
+    pub fn all_dead(&self) -> bool {
+        self.agents.iter().all(|agent| !agent.is_alive())
+    }
+
+    /// Returns a summary of the outcome of the simulation, valuing every unit of final
+    /// stock equally.
+    pub fn summary(&self) -> SimulationSummary {
+        let prices: HashMap<Good, f32> = Good::iter().map(|good| (good, 1.0)).collect();
+        self.summary_with_prices(&prices)
+    }
+
+    /// Returns a summary of the outcome of the simulation, valuing final stock at the
+    /// given prices.
+    pub fn summary_with_prices(&self, prices: &HashMap<Good, f32>) -> SimulationSummary {
+        let agents = self
+            .agents
+            .iter()
+            .map(|agent| AgentSummary {
+                id: agent.get_id(),
+                survival_time: *self.death_times.get(&agent.get_id()).unwrap_or(&self.time),
+                final_stock_value: agent.stock().value(prices),
+                total_reward: Agent::reward_history(agent)
+                    .iter()
+                    .map(|reward| reward.val as i64)
+                    .sum(),
+                production_totals: agent
+                    .production_totals()
+                    .iter()
+                    .map(|(good, qty)| (*good, *qty))
+                    .collect(),
+            })
+            .collect();
+        SimulationSummary {
+            total_steps: self.time,
+            death_count: self.death_count,
+            agents,
+        }
+    }
+
+    /// Consumes the simulation, returning the outcome of the run.
+    pub fn finish(self) -> RunOutcome {
+        let mut outcome = RunOutcome {
+            total_steps: self.time,
+            death_count: self.death_count,
+            total_rewards: BTreeMap::new(),
+            trailing_avg_rewards: BTreeMap::new(),
+            final_stocks: BTreeMap::new(),
+        };
+        for agent in self.agents {
+            let id = agent.get_id();
+            let total_reward = Agent::reward_history(&agent)
+                .iter()
+                .map(|reward| reward.val as i64)
+                .sum();
+            outcome.total_rewards.insert(id, total_reward);
+            outcome
+                .trailing_avg_rewards
+                .insert(id, agent.trailing_avg_reward(TRAILING_REWARD_WINDOW));
+            outcome.final_stocks.insert(id, agent.stock().clone());
+        }
+        outcome
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::actions::ActionFlattened as Action;
+    use crate::config::{InitStrategy, RLConfig};
+    use crate::learning::agent_state::StateItem;
+    use crate::learning::learning_agent::LearningAgent;
+    use crate::learning::policy::ConstantPolicy;
+    use crate::{lazy_test_model, test_model};
+
+    /// Returns a learning agent with the given id and daily nutrition that takes the given
+    /// action on every step.
+    fn constant_agent(id: u64, daily_nutrition: UInt, action: Action) -> LearningAgent {
+        let mut agent = LearningAgent::new_with_nutrition(id, daily_nutrition);
+        agent.set_policy(ConstantPolicy(action));
+        agent
+    }
 
     #[test]
     fn test_simulation_initialization() {
@@ -135,4 +631,559 @@ mod tests {
 
         println!(">>>>> {:?}", sim);
     }
+
+    #[test]
+    fn test_agent_specs() {
+        use crate::config::{AgentKind, AgentSpec};
+
+        let config = Config {
+            agents: vec![AgentSpec {
+                kind: AgentKind::Rational,
+                count: 2,
+                nutrition: 3,
+            }],
+            ..Default::default()
+        };
+        let sim = Simulation::new(config, false);
+        assert_eq!(sim.agents.len(), 2);
+        for (id, agent) in sim.agents.iter().enumerate() {
+            assert!(matches!(agent, AgentType::Rational(_)));
+            assert_eq!(agent.get_id(), id as u64);
+            assert_eq!(agent.daily_nutrition(), 3);
+        }
+
+        // Specs are read from TOML.
+        let config: Config = toml::from_str(
+            "[[agents]]\nkind = \"crusoe\"\ncount = 1\nnutrition = 2\n\n\
+             [[agents]]\nkind = \"rl\"\ncount = 2\n",
+        )
+        .unwrap();
+        let sim = Simulation::new(config, false);
+        assert_eq!(sim.agents.len(), 3);
+        assert!(matches!(sim.agents[0], AgentType::Crusoe(_)));
+        assert!(matches!(sim.agents[2], AgentType::Rl(_)));
+        assert_eq!(sim.agents[2].daily_nutrition(), 1);
+    }
+
+    #[test]
+    fn test_history_window() {
+        let window = 5;
+        let mut sim = Simulation::new(
+            Config {
+                max_time: 20,
+                history_window: Some(window),
+                ..Default::default()
+            },
+            false,
+        );
+        let mut model = lazy_test_model(RLConfig::default());
+        while sim.time < sim.config.max_time {
+            sim.step_forward(&model);
+            sim.time += 1;
+            model.step(sim.time as i32, &sim.agent_hist);
+        }
+        let agent = &sim.agents[0];
+        assert_eq!(agent.action_history().len(), window);
+        assert_eq!(agent.stock_history().len(), window);
+        assert_eq!(agent.reward_history().len(), window);
+        assert_eq!(sim.agent_hist[&0].len(), window);
+    }
+
+    #[test]
+    fn test_stop_when_all_dead() {
+        use crate::goods::GoodsUnit;
+
+        // An agent that only takes leisure lives on its stock of berries, then dies.
+        let mut agent = constant_agent(0, 1, Action::Leisure);
+        agent.acquire(GoodsUnit::new(&Good::Berries), 6).unwrap();
+        let survival_days = agent.stock().survival_days(agent.daily_nutrition());
+        assert!(survival_days > 0);
+        let mut sim = Simulation::with_agents(
+            Config {
+                max_time: 10000,
+                stop_when_all_dead: true,
+                ..Default::default()
+            },
+            false,
+            vec![AgentType::Rl(agent)],
+        );
+        let mut model = lazy_test_model(RLConfig::default());
+        sim.run(&mut model);
+
+        // The simulation stops at the end of the step on which the agent dies.
+        assert!(sim.all_dead());
+        assert_eq!(sim.time, survival_days + 1);
+    }
+
+    #[test]
+    fn test_quiet_by_default() {
+        let config = Config {
+            max_time: 5,
+            ..Default::default()
+        };
+        let mut model = lazy_test_model(RLConfig::default());
+        let mut out = Vec::new();
+        let mut sim = Simulation::new(config.clone(), false);
+        sim.run_with_output(&mut model, &mut out).unwrap();
+        assert_eq!(sim.time, 5);
+        assert!(out.is_empty());
+
+        // Diagnostics are only written by a verbose simulation.
+        let mut sim = Simulation::new(config, true);
+        sim.run_with_output(&mut model, &mut out).unwrap();
+        assert!(!out.is_empty());
+    }
+
+    #[test]
+    fn test_shared_tools() {
+        let agents = (0..2)
+            .map(|id| AgentType::Rl(constant_agent(id, 1, Action::ProduceFish)))
+            .collect();
+        let mut sim = Simulation::with_agents(
+            Config {
+                max_time: 3,
+                shared_tools: HashMap::from([(Good::Boat, 1)]),
+                ..Default::default()
+            },
+            false,
+            agents,
+        );
+        let model = lazy_test_model(RLConfig::default());
+        let fish_totals = |sim: &Simulation| -> Vec<UInt> {
+            sim.agents
+                .iter()
+                .map(|agent| agent.production_totals()[&Good::Fish])
+                .collect()
+        };
+        sim.step_forward(&model);
+        // Only the first agent in turn fishes with the boat.
+        assert_eq!(fish_totals(&sim), vec![20, 2]);
+        // The boat is returned to the pool at the end of every step.
+        assert_eq!(sim.shared.available(&Good::Boat), 1);
+        sim.step_forward(&model);
+        assert_eq!(fish_totals(&sim), vec![40, 4]);
+
+        // An agent claims only the shared tool it fishes with (a spear takes precedence over
+        // a boat), leaving the boat to the next.
+        sim.shared = SharedResources::new(BTreeMap::from([(Good::Boat, 1), (Good::Spear, 1)]));
+        sim.step_forward(&model);
+        assert_eq!(fish_totals(&sim), vec![50, 24]);
+    }
+
+    #[test]
+    fn test_evaluate_policy() {
+        let sim = Simulation::new(
+            Config {
+                seed: Some(0),
+                ..Default::default()
+            },
+            false,
+        );
+        // Prefer the given action in every state.
+        let prefer = |preferred: Action| {
+            lazy_test_model(RLConfig {
+                init_strategy: InitStrategy::PerAction(BTreeMap::from([(
+                    format!("{:?}", preferred),
+                    1.0,
+                )])),
+                ..Default::default()
+            })
+        };
+
+        // Gathering berries every day sustains the agent for the whole episode.
+        let model = prefer(Action::ProduceBerries);
+        let evaluation = sim.evaluate_policy(&model, 3, 20);
+        assert_eq!(evaluation.episodes, 3);
+        assert_eq!(evaluation.mean_survival_time, 20.0);
+        assert_eq!(evaluation.median_survival_time, 20.0);
+
+        // Idling from an empty stock starves the agent immediately.
+        let model = prefer(Action::Leisure);
+        let evaluation = sim.evaluate_policy(&model, 3, 20);
+        assert_eq!(evaluation.mean_survival_time, 0.0);
+        assert!(evaluation.mean_total_reward < 0.0);
+    }
+
+    #[test]
+    fn test_reward_fn() {
+        use crate::goods::GoodsUnit;
+
+        let agents = (0..2)
+            .map(|id| {
+                let mut agent = constant_agent(id, 1, Action::ProduceBerries);
+                if id == 1 {
+                    agent.acquire(GoodsUnit::new(&Good::Boat), 1).unwrap();
+                }
+                AgentType::Rl(agent)
+            })
+            .collect();
+        let mut sim = Simulation::with_agents(Config::default(), false, agents);
+        // Reward holding a boat, whatever the action.
+        sim.reward_fn = Some(RewardFn(Box::new(|stock, _, _| {
+            Reward::new(stock.contains(&Good::Boat) as i32)
+        })));
+        let model = lazy_test_model(RLConfig::default());
+        for _ in 0..3 {
+            sim.step_forward(&model);
+            sim.time += 1;
+        }
+        let rewards = |id: usize| -> Vec<i32> {
+            Agent::reward_history(&sim.agents[id])
+                .iter()
+                .map(|reward| reward.val)
+                .collect()
+        };
+        assert_eq!(rewards(0), vec![0, 0, 0]);
+        assert_eq!(rewards(1), vec![1, 1, 1]);
+        // The recorded history of each agent also holds its custom rewards.
+        let recorded = |id: u32| -> Vec<i32> {
+            sim.agent_hist[&id]
+                .trajectory
+                .iter()
+                .map(|sar| sar.reward.val)
+                .collect()
+        };
+        assert_eq!(recorded(0), vec![0, 0, 0]);
+        assert_eq!(recorded(1), vec![1, 1, 1]);
+    }
+
+    #[test]
+    fn test_events() {
+        use crate::goods::GoodsUnit;
+
+        let mut sim = Simulation::new(
+            Config {
+                record_events: true,
+                ..Default::default()
+            },
+            false,
+        );
+        sim.agents[0]
+            .acquire(GoodsUnit::new(&Good::Berries), 20)
+            .unwrap();
+        let lifetime = GoodsUnit::new(&Good::Berries).remaining_lifetime;
+        for _ in 0..=lifetime {
+            sim.step_forward_with(|_| Action::Leisure.into());
+            sim.time += 1;
+        }
+        // Berries not eaten by the end of their lifetime spoil, after which the agent starves.
+        assert_eq!(
+            sim.events,
+            vec![
+                SimEvent::Spoiled {
+                    time: lifetime - 1,
+                    agent_id: 0,
+                    good: Good::Berries,
+                    quantity: 20 - lifetime,
+                },
+                SimEvent::Died {
+                    time: lifetime,
+                    agent_id: 0
+                },
+            ]
+        );
+
+        // Production is recorded, and nothing is recorded unless configured.
+        sim.step_forward_with(|_| Action::ProduceBerries.into());
+        assert!(matches!(
+            sim.events.last(),
+            Some(SimEvent::Produced {
+                good: Good::Berries,
+                ..
+            })
+        ));
+        sim.config = SharedConfig::new(Config {
+            record_events: false,
+            ..(*sim.config).clone()
+        });
+        let num_events = sim.events.len();
+        sim.step_forward_with(|_| Action::ProduceBerries.into());
+        assert_eq!(sim.events.len(), num_events);
+    }
+
+    #[test]
+    fn test_config_from_file() {
+        use crate::goods::{GoodsUnit, Productivity};
+
+        let path = std::env::temp_dir().join("crusoe_test_simulation_config.toml");
+        std::fs::write(&path, "[build_times]\nBoat = 5\n").unwrap();
+        let config = Config::from_toml_path(&path);
+        std::fs::remove_file(&path).unwrap();
+        let mut sim = Simulation::new(config.unwrap(), false);
+        let agent = &mut sim.agents[0];
+        agent.acquire(GoodsUnit::new(&Good::Berries), 20).unwrap();
+        agent.acquire(GoodsUnit::new(&Good::Timber), 5).unwrap();
+
+        // The loaded build time applies to the agents, so 5 Timber suffice for a Boat.
+        assert_eq!(agent.productivity(&Good::Boat), Productivity::Delayed(5));
+        for _ in 0..5 {
+            sim.step_forward_with(|_| Action::ProduceBoat.into());
+        }
+        assert_eq!(sim.agents[0].stock().count_units(&Good::Boat), 1);
+        assert_eq!(sim.agents[0].stock().count_units(&Good::Timber), 0);
+    }
+
+    #[test]
+    fn test_partial_endowment() {
+        use crate::goods::{GoodsUnit, PartialGoodsUnit};
+
+        // Returns the number of steps taken to build a boat, starting with the given endowment.
+        let steps_to_boat = |partial_endowment: Vec<PartialGoodsUnit>| {
+            let mut sim = Simulation::new(
+                Config {
+                    partial_endowment,
+                    ..Default::default()
+                },
+                false,
+            );
+            let agent = &mut sim.agents[0];
+            agent.acquire(GoodsUnit::new(&Good::Berries), 20).unwrap();
+            agent.acquire(GoodsUnit::new(&Good::Timber), 10).unwrap();
+            let mut steps = 0;
+            while !sim.agents[0].stock().contains(&Good::Boat) {
+                assert!(steps < 20);
+                sim.step_forward_with(|_| Action::ProduceBoat.into());
+                sim.time += 1;
+                steps += 1;
+            }
+            steps
+        };
+        let half_built = PartialGoodsUnit {
+            good: Good::Boat,
+            time_to_completion: 5,
+            build_time: 10,
+        };
+        assert_eq!(steps_to_boat(vec![]), 10);
+        assert_eq!(steps_to_boat(vec![half_built]), 5);
+    }
+
+    #[test]
+    fn test_heterogeneous_nutrition() {
+        use crate::goods::GoodsUnit;
+
+        let agents = [1, 3]
+            .into_iter()
+            .enumerate()
+            .map(|(id, daily_nutrition)| {
+                let mut agent = constant_agent(id as u64, daily_nutrition, Action::Leisure);
+                agent.acquire(GoodsUnit::new(&Good::Berries), 12).unwrap();
+                AgentType::Rl(agent)
+            })
+            .collect();
+        let mut sim = Simulation::with_agents(
+            Config {
+                max_time: 50,
+                stop_when_all_dead: true,
+                ..Default::default()
+            },
+            false,
+            agents,
+        );
+        let mut model = lazy_test_model(RLConfig::default());
+        sim.run(&mut model);
+
+        // The same stock sustains the agent with the smaller requirement for longer.
+        let summary = sim.summary();
+        assert_eq!(sim.agents[1].daily_nutrition(), 3);
+        assert!(summary.agents[1].survival_time < summary.agents[0].survival_time);
+    }
+
+    #[test]
+    fn test_checkpoint_and_resume() {
+        use crate::actions::Action as AgentAction;
+        use crate::learning::q_table::QKey;
+
+        let mut sim = Simulation::new(
+            Config {
+                max_time: 10,
+                seed: Some(0),
+                ..Default::default()
+            },
+            false,
+        );
+        // A model over a single state item keeps the checkpoint small.
+        let mut model = test_model(vec![StateItem::Partial]);
+        for action in [
+            AgentAction::ProduceGood(Good::Berries),
+            AgentAction::Leisure,
+        ] {
+            sim.agents[0].step_forward(Some(action));
+            sim.time += 1;
+        }
+        let q_key = QKey(vec![(StateItem::Partial, InvLevel::Low)], Action::Leisure);
+        model.get_table_by_id_mut(0).insert(q_key.clone(), 1.5);
+        model.config_fingerprint = Some(sim.config.fingerprint());
+
+        let dir = std::env::temp_dir().join(format!("crusoe-checkpoint-{}", std::process::id()));
+        sim.checkpoint(&model, &dir).unwrap();
+        let (resumed_sim, resumed_model) = Simulation::resume(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(resumed_sim.time, sim.time);
+        assert_eq!(resumed_sim.agents[0].stock(), sim.agents[0].stock());
+        assert_eq!(
+            resumed_sim.agents[0].action_history(),
+            sim.agents[0].action_history()
+        );
+        assert_eq!(resumed_model.get_table_by_id(0), model.get_table_by_id(0));
+    }
+
+    #[test]
+    fn test_resume_missing_checkpoint() {
+        let dir = std::env::temp_dir().join("crusoe-checkpoint-missing");
+        assert!(matches!(
+            Simulation::resume(&dir),
+            Err(CheckpointError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_resume_config_mismatch() {
+        let sim = Simulation::new(Config::default(), false);
+        let mut model = test_model(vec![StateItem::Partial]);
+        // The model was trained with a different discretisation threshold.
+        let mut training_config = Config::default();
+        training_config.agent.inv_level_med += 1;
+        assert_ne!(training_config.fingerprint(), sim.config.fingerprint());
+        model.config_fingerprint = Some(training_config.fingerprint());
+
+        let dir = std::env::temp_dir().join(format!("crusoe-mismatch-{}", std::process::id()));
+        sim.checkpoint(&model, &dir).unwrap();
+        let resumed = Simulation::resume(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(
+            resumed,
+            Err(CheckpointError::ConfigMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_finish() {
+        use crate::POSITIVE_REWARD;
+        use crate::config::AgentSpec;
+        use crate::goods::GoodsUnit;
+
+        let mut sim = Simulation::new(
+            Config {
+                agents: vec![AgentSpec {
+                    count: 2,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            false,
+        );
+        sim.agents[0]
+            .acquire(GoodsUnit::new(&Good::Berries), 10)
+            .unwrap();
+        for _ in 0..5 {
+            sim.step_forward_with(|_| Action::Leisure.into());
+            sim.time += 1;
+        }
+        let rewards: Vec<Vec<Reward>> = sim
+            .agents
+            .iter()
+            .map(|agent| Agent::reward_history(agent).to_vec())
+            .collect();
+        let stocks: Vec<Stock> = sim
+            .agents
+            .iter()
+            .map(|agent| agent.stock().clone())
+            .collect();
+        let death_count = sim.death_count;
+
+        let outcome = sim.finish();
+        assert_eq!(outcome.total_steps, 5);
+        // Only the agent without food dies, on every step.
+        assert_eq!(outcome.death_count, death_count);
+        assert_eq!(death_count, 5);
+        for (id, rewards) in rewards.iter().enumerate() {
+            let id = id as u64;
+            let total: i64 = rewards.iter().map(|reward| reward.val as i64).sum();
+            assert_eq!(outcome.total_rewards[&id], total);
+            assert_eq!(outcome.trailing_avg_rewards[&id], total as f32 / 5.0);
+            assert_eq!(outcome.final_stocks[&id], stocks[id as usize]);
+        }
+        assert_eq!(outcome.total_rewards[&0], 5 * POSITIVE_REWARD as i64);
+    }
+
+    #[test]
+    fn test_seasons() {
+        use crate::config::SeasonConfig;
+        use crate::goods::GoodsUnit;
+
+        let mut sim = Simulation::new(
+            Config {
+                seasons: Some(SeasonConfig {
+                    period: 2,
+                    multipliers: HashMap::from([(
+                        Season::Winter,
+                        HashMap::from([(Good::Fish, 0.5)]),
+                    )]),
+                }),
+                ..Default::default()
+            },
+            false,
+        );
+        sim.agents[0]
+            .acquire(GoodsUnit::new(&Good::Berries), 10)
+            .unwrap();
+        let mut fish_produced = Vec::new();
+        for time in [0, 6] {
+            sim.time = time;
+            let before = sim.agents[0]
+                .production_totals()
+                .get(&Good::Fish)
+                .copied()
+                .unwrap_or(0);
+            sim.step_forward_with(|_| Action::ProduceFish.into());
+            fish_produced.push(sim.agents[0].production_totals()[&Good::Fish] - before);
+        }
+        sim.time = 0;
+        assert_eq!(sim.season(), Some(Season::Spring));
+        sim.time = 6;
+        assert_eq!(sim.season(), Some(Season::Winter));
+        // Fish are half as plentiful in winter.
+        assert_eq!(fish_produced, vec![2, 1]);
+    }
+
+    #[test]
+    fn test_summary() {
+        // One agent forages every day, while the other, starting with nothing, only rests.
+        let agents = [Action::ProduceBerries, Action::Leisure]
+            .into_iter()
+            .enumerate()
+            .map(|(id, action)| AgentType::Rl(constant_agent(id as u64, 1, action)))
+            .collect();
+        let mut sim = Simulation::with_agents(
+            Config {
+                max_time: 5,
+                seed: Some(0),
+                ..Default::default()
+            },
+            false,
+            agents,
+        );
+        let mut model = lazy_test_model(RLConfig::default());
+        sim.run(&mut model);
+
+        let summary = sim.summary();
+        assert_eq!(summary.total_steps, 5);
+        // The resting agent fails to meet its requirement on every step.
+        assert_eq!(summary.death_count, 5);
+        let (forager, rester) = (&summary.agents[0], &summary.agents[1]);
+        assert_eq!(forager.survival_time, 5);
+        assert_eq!(
+            forager.production_totals,
+            BTreeMap::from([(Good::Berries, 20)])
+        );
+        assert_eq!(forager.total_reward, 0);
+        assert_eq!(forager.final_stock_value, 15.0);
+        // An agent survives until its first failure to meet its requirement.
+        assert_eq!(rester.survival_time, 0);
+        assert!(rester.production_totals.is_empty());
+        assert_eq!(rester.total_reward, 5 * crate::NEGATIVE_REWARD as i64);
+        assert_eq!(rester.final_stock_value, 0.0);
+        assert!(serde_json::to_string(&summary).is_ok());
+    }
 }
@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
 
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
@@ -8,15 +9,44 @@ use strum_macros::EnumIter;
 use crate::{
     UInt,
     actions::Action,
-    config::core_config,
+    config::{AgentConfig, Config, ConsumeTieBreak, SpoilageModel, core_config},
     goods::{Good, GoodsUnit, GoodsUnitLevel, PartialGoodsUnit},
+    learning::serde_utils,
 };
 
+/// Tolerance for rounding errors when comparing fractional nutritional units.
+const NUTRITION_TOLERANCE: f32 = 1e-4;
+
+/// Returns true if the nutrition provided meets the nutrition required.
+pub fn is_sufficient(provided: f32, required: f32) -> bool {
+    provided + NUTRITION_TOLERANCE >= required
+}
+
 #[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "StockFields")]
 pub struct Stock {
     #[serde(serialize_with = "serialize_hm")]
-    pub stock: HashMap<GoodsUnit, UInt>,
+    stock: HashMap<GoodsUnit, UInt>,
     pub partial_stock: Vec<PartialGoodsUnit>,
+    /// The (remaining lifetime, good) of every unit of a consumer good in the stock, ordered
+    /// so that the soonest to expire come first. Kept in sync with `stock` by the methods
+    /// that modify it, which is why `stock` is private.
+    #[serde(skip)]
+    consumables: BTreeSet<(UInt, Good)>,
+}
+
+/// The serialized fields of a stock, from which the index of consumables is rebuilt.
+#[derive(Deserialize)]
+struct StockFields {
+    #[serde(deserialize_with = "serde_utils::deserialize")]
+    stock: HashMap<GoodsUnit, UInt>,
+    partial_stock: Vec<PartialGoodsUnit>,
+}
+
+impl From<StockFields> for Stock {
+    fn from(fields: StockFields) -> Self {
+        Stock::new(fields.stock, fields.partial_stock)
+    }
 }
 
 fn serialize_hm<S>(hm: &HashMap<GoodsUnit, UInt>, serializer: S) -> Result<S::Ok, S::Error>
@@ -33,7 +63,52 @@ where
     seq.end()
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, EnumIter, Hash, Eq, Serialize, Deserialize)]
+/// Errors arising when modifying a stock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StockError {
+    /// A zero quantity of the good was added.
+    ZeroQuantity(Good),
+    /// A partial unit of the good was added when the stock already contains one.
+    DuplicatePartial(Good),
+    /// A partial unit was created for a good that completes in a single timestep.
+    SingleStepGood(Good),
+    /// A partial unit was added when the stock already contains the maximum number.
+    TooManyBuilds(UInt),
+    /// More units of the good were removed than the stock contains.
+    InsufficientStock(Good),
+}
+
+impl fmt::Display for StockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StockError::ZeroQuantity(good) => {
+                write!(f, "cannot add a zero quantity of {:?} to stock", good)
+            }
+            StockError::DuplicatePartial(good) => {
+                write!(f, "cannot add multiple partial units of {:?}", good)
+            }
+            StockError::SingleStepGood(good) => {
+                write!(f, "{:?} does not take multiple timesteps to complete", good)
+            }
+            StockError::TooManyBuilds(limit) => {
+                write!(
+                    f,
+                    "cannot have more than {} partial units in progress",
+                    limit
+                )
+            }
+            StockError::InsufficientStock(good) => {
+                write!(f, "insufficient units of {:?} in stock", good)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StockError {}
+
+#[derive(
+    Debug, Copy, Clone, PartialEq, EnumIter, Hash, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
 pub enum InvLevel {
     // quantity
     // Critical,
@@ -42,98 +117,171 @@ pub enum InvLevel {
     High,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, EnumIter, Hash, Eq, Serialize, Deserialize)]
+#[derive(
+    Debug, Copy, Clone, PartialEq, EnumIter, Hash, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
 pub enum RemainingLevel {
     // lifetime
     //Critical,
     Low,
     //Medium,
-    // High,
+    High,
+}
+
+impl RemainingLevel {
+    /// Returns the lifetime level of a goods unit.
+    ///
+    /// Only perishable (consumer) goods are bucketed by remaining lifetime. Capital goods always
+    /// map to `Low`, since every additional level multiplies the size of the `QTable` keyspace
+    /// by a further factor of `InvLevel::iter().count()` per good.
+    pub fn from_goods_unit(goods_unit: &GoodsUnit, config: &AgentConfig) -> Self {
+        if !goods_unit.good.is_consumer() {
+            return RemainingLevel::Low;
+        }
+        match goods_unit.remaining_lifetime {
+            lifetime if lifetime < config.remaining_level_high => RemainingLevel::Low,
+            _ => RemainingLevel::High,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct StockDiscrete {
     pub stock: HashMap<GoodsUnitLevel, InvLevel>,
+    /// Progress on the most advanced partially complete good: `Low` if none is in progress,
+    /// `Medium` if at most half complete and `High` if more than half complete.
+    pub partial: InvLevel,
 }
 
 impl Stock {
     pub fn discretise(&self) -> StockDiscrete {
-        let mut ds = HashMap::new();
-        let config = core_config();
+        self.discretise_with(&core_config().agent)
+    }
+
+    /// Discretises the stock at the inventory and lifetime levels of the given agent config.
+    pub fn discretise_with(&self, config: &AgentConfig) -> StockDiscrete {
+        // Aggregate the quantities of units falling into the same lifetime level before
+        // discretising, so that units with different remaining lifetimes are not overwritten.
+        let mut quantities: HashMap<GoodsUnitLevel, UInt> = HashMap::new();
         for (goods_unit, quantity) in &self.stock {
-            match (goods_unit, quantity) {
-                // (
-                //     GoodsUnit {
-                //         good,
-                //         remaining_lifetime,
-                //     },
-                //     qty,
-                // )
-                // if *qty < config.agent.inv_level_low => {
-                //     ds.insert(
-                //         GoodsUnitLevel::new(*good, RemainingLevel::Low),
-                //         InvLevel::Critical,
-                //     );
+            let goods_unit_level = GoodsUnitLevel::new(
+                goods_unit.good,
+                RemainingLevel::from_goods_unit(goods_unit, config),
+            );
+            *quantities.entry(goods_unit_level).or_insert(0) += quantity;
+        }
+        let mut ds = HashMap::new();
+        for (goods_unit_level, qty) in quantities {
+            match qty {
+                // qty if qty < config.inv_level_low => {
+                //     ds.insert(goods_unit_level, InvLevel::Critical);
                 // }
-                (
-                    GoodsUnit {
-                        good,
-                        remaining_lifetime,
-                    },
-                    qty,
-                ) if *qty < config.agent.inv_level_med => {
-                    ds.insert(
-                        GoodsUnitLevel::new(*good, RemainingLevel::Low),
-                        InvLevel::Low,
-                    );
+                qty if qty < config.inv_level_med => {
+                    ds.insert(goods_unit_level, InvLevel::Low);
                 }
-                (
-                    GoodsUnit {
-                        good,
-                        remaining_lifetime,
-                    },
-                    qty,
-                ) if *qty < config.agent.inv_level_high => {
-                    ds.insert(
-                        GoodsUnitLevel::new(*good, RemainingLevel::Low),
-                        InvLevel::Medium,
-                    );
+                qty if qty < config.inv_level_high => {
+                    ds.insert(goods_unit_level, InvLevel::Medium);
                 }
-                (
-                    GoodsUnit {
-                        good,
-                        remaining_lifetime,
-                    },
-                    qty,
-                ) => {
-                    ds.insert(
-                        GoodsUnitLevel::new(*good, RemainingLevel::Low),
-                        InvLevel::High,
-                    );
+                _ => {
+                    ds.insert(goods_unit_level, InvLevel::High);
                 }
             }
         }
-        StockDiscrete { stock: ds }
+        StockDiscrete {
+            stock: ds,
+            partial: self.partial_level(),
+        }
+    }
+
+    /// Returns the discretised progress on the most advanced partially complete good.
+    fn partial_level(&self) -> InvLevel {
+        let min_fraction_remaining = self
+            .partial_stock
+            .iter()
+            .map(|partial| partial.time_to_completion as f32 / partial.build_time as f32)
+            .reduce(f32::min);
+        match min_fraction_remaining {
+            None => InvLevel::Low,
+            Some(fraction) if fraction >= 0.5 => InvLevel::Medium,
+            Some(_) => InvLevel::High,
+        }
     }
 }
 
 impl Stock {
+    /// Returns a stock containing the given goods units and partial units.
+    pub fn new(stock: HashMap<GoodsUnit, UInt>, partial_stock: Vec<PartialGoodsUnit>) -> Self {
+        let mut new_stock = Stock {
+            partial_stock,
+            ..Stock::default()
+        };
+        for (goods_unit, quantity) in stock {
+            new_stock.insert_units(goods_unit, quantity);
+        }
+        new_stock
+    }
+
+    /// Returns a stock containing the given quantities of newly-produced units of each good.
+    /// Zero quantities are ignored, and the quantities of a repeated good accumulate.
+    pub fn from_goods(items: &[(Good, UInt)]) -> Self {
+        let units: Vec<(GoodsUnit, UInt)> = items
+            .iter()
+            .map(|(good, quantity)| (GoodsUnit::new(good), *quantity))
+            .collect();
+        Self::from_units(&units)
+    }
+
+    /// Returns a stock containing the given quantities of goods units, with their explicit
+    /// remaining lifetimes. Zero quantities are ignored, as in `from_goods`.
+    pub fn from_units(items: &[(GoodsUnit, UInt)]) -> Self {
+        let mut stock = Stock::default();
+        for (goods_unit, quantity) in items.iter().filter(|(_, quantity)| *quantity > 0) {
+            stock.insert_units(*goods_unit, *quantity);
+        }
+        stock
+    }
+
     /// Add units of a good to the stock.
-    pub fn add(&mut self, good: GoodsUnit, quantity: UInt) {
+    pub fn add(&mut self, good: GoodsUnit, quantity: UInt) -> Result<(), StockError> {
         if quantity == 0 {
-            panic!("Cannot add a zero quantity of goods to stock.");
+            return Err(StockError::ZeroQuantity(good.good));
         }
-        if let Some(existing_qty) = &self.stock.insert(good, quantity) {
-            let _ = &self.stock.insert(good, quantity + *existing_qty);
+        self.insert_units(good, quantity);
+        Ok(())
+    }
+
+    /// Adds units of a good to the stock, keeping the index of consumables in sync.
+    fn insert_units(&mut self, goods_unit: GoodsUnit, quantity: UInt) {
+        *self.stock.entry(goods_unit).or_default() += quantity;
+        if goods_unit.good.is_consumer() {
+            self.consumables
+                .insert((goods_unit.remaining_lifetime, goods_unit.good));
         }
     }
 
-    /// Add a unit of a partially complete good to the stock.
-    pub fn add_partial(&mut self, good: PartialGoodsUnit) {
+    /// Add a unit of a partially complete good to the stock, subject to the configured
+    /// limit on the number of partial units in progress.
+    pub fn add_partial(&mut self, good: PartialGoodsUnit) -> Result<(), StockError> {
+        self.add_partial_with_limit(good, core_config().agent.max_concurrent_builds)
+    }
+
+    /// Add a unit of a partially complete good to the stock, provided there are fewer
+    /// than `max_concurrent_builds` partial units already in progress (if given).
+    pub fn add_partial_with_limit(
+        &mut self,
+        good: PartialGoodsUnit,
+        max_concurrent_builds: Option<UInt>,
+    ) -> Result<(), StockError> {
         if let Some(_) = self.get_partial(good.good) {
-            panic!("Cannot add multiple partial units of the same good.")
+            return Err(StockError::DuplicatePartial(good.good));
+        }
+        if let Some(limit) = max_concurrent_builds
+            && self.partial_stock.len() as UInt >= limit
+        {
+            return Err(StockError::TooManyBuilds(limit));
         }
         let _ = &self.partial_stock.push(good);
+        Ok(())
     }
 
     /// Remove a units of a good from the stock.
@@ -145,6 +293,8 @@ impl Stock {
             }
             Some(&&qty) if qty == quantity => {
                 self.stock.remove(goods_unit);
+                self.consumables
+                    .remove(&(goods_unit.remaining_lifetime, goods_unit.good));
             }
             Some(_) | None => panic!("Good not found in stock"),
         };
@@ -154,6 +304,17 @@ impl Stock {
         // }
     }
 
+    /// Returns true if the stock contains no goods units and no partial units.
+    pub fn is_empty(&self) -> bool {
+        self.stock.is_empty() && self.partial_stock.is_empty()
+    }
+
+    /// Returns the number of distinct goods units (i.e. goods with distinct remaining
+    /// lifetimes) in the stock, excluding partial units.
+    pub fn num_distinct_units(&self) -> usize {
+        self.stock.len()
+    }
+
     /// Returns true if the stock contains any units of the given good.
     pub fn contains(&self, good: &Good) -> bool {
         for goods_unit in self.stock.keys() {
@@ -164,6 +325,59 @@ impl Stock {
         false
     }
 
+    /// Returns the number of units in the stock of the given good at the given remaining
+    /// lifetime.
+    pub fn quantity_of(&self, goods_unit: &GoodsUnit) -> UInt {
+        self.stock.get(goods_unit).copied().unwrap_or(0)
+    }
+
+    /// Returns the total number of units of the given good in the stock.
+    pub fn count_units(&self, good: &Good) -> UInt {
+        self.stock
+            .iter()
+            .filter(|(goods_unit, _)| &goods_unit.good == good)
+            .map(|(_, qty)| qty)
+            .sum()
+    }
+
+    /// Returns the quantity of the given good held at each remaining lifetime.
+    pub fn lifetime_histogram(&self, good: &Good) -> BTreeMap<UInt, UInt> {
+        let mut histogram = BTreeMap::new();
+        for (goods_unit, qty) in &self.stock {
+            if &goods_unit.good == good && *qty > 0 {
+                *histogram.entry(goods_unit.remaining_lifetime).or_default() += qty;
+            }
+        }
+        histogram
+    }
+
+    /// Returns the unit of the given good with the least remaining lifetime, if any.
+    pub fn oldest_unit(&self, good: &Good) -> Option<GoodsUnit> {
+        let (remaining_lifetime, _) = self.lifetime_histogram(good).pop_first()?;
+        Some(GoodsUnit {
+            good: *good,
+            remaining_lifetime,
+        })
+    }
+
+    /// Returns the unit of the given good with the greatest remaining lifetime, if any.
+    pub fn freshest_unit(&self, good: &Good) -> Option<GoodsUnit> {
+        let (remaining_lifetime, _) = self.lifetime_histogram(good).pop_last()?;
+        Some(GoodsUnit {
+            good: *good,
+            remaining_lifetime,
+        })
+    }
+
+    /// Returns the value of the stock at the given prices.
+    /// Goods without a listed price do not contribute.
+    pub fn value(&self, prices: &HashMap<Good, f32>) -> f32 {
+        prices
+            .iter()
+            .map(|(good, price)| self.count_units(good) as f32 * price)
+            .sum()
+    }
+
     /// Returns a partial unit of the given good, if the stock contains one.
     pub fn get_partial(&self, good: Good) -> Option<PartialGoodsUnit> {
         for partial_unit in &self.partial_stock {
@@ -174,71 +388,393 @@ impl Stock {
         None
     }
 
+    /// Do the next step of production of the partial unit of the given good. If production is
+    /// complete, the partial unit is replaced by a newly-produced unit of the good.
+    pub fn progress_partial(&mut self, good: &Good) {
+        let Some(idx) = self
+            .partial_stock
+            .iter()
+            .position(|unit| &unit.good == good)
+        else {
+            panic!("Partial good not found in stock")
+        };
+        self.partial_stock[idx].increment_production();
+        if self.partial_stock[idx].is_complete() {
+            self.partial_stock.remove(idx);
+            self.add(GoodsUnit::new(good), 1)
+                .expect("A single unit is a nonzero quantity.");
+        }
+    }
+
+    /// Removes the materials used up by one timestep of production of the given good,
+    /// taking the units closest to expiry first.
+    pub fn consume_material_inputs(&mut self, good: &Good) {
+        self.consume_material_inputs_with(good, &core_config())
+    }
+
+    /// Removes the materials used up by one timestep of production of the given good, as in
+    /// `consume_material_inputs`, given the materials and fuel of the given config.
+    pub fn consume_material_inputs_with(&mut self, good: &Good, config: &Config) {
+        // Transforming a good uses up every unit of its input.
+        if let Some(input) = good.transforms_from_with(&config.transforms) {
+            self.remove_all(&input);
+        }
+        for (material, quantity) in good.materials_per_step_with(config, self) {
+            let units: Vec<(GoodsUnit, UInt)> = self
+                .next_capital_goods_units(&material)
+                .into_iter()
+                .map(|(goods_unit, qty)| (*goods_unit, *qty))
+                .collect();
+            let mut outstanding = quantity;
+            for (goods_unit, qty) in units {
+                if outstanding == 0 {
+                    break;
+                }
+                let removed = UInt::min(qty, outstanding);
+                self.remove(&goods_unit, removed);
+                outstanding -= removed;
+            }
+        }
+    }
+
+    /// Removes every unit of the given good from the stock and returns the number removed.
+    pub fn remove_all(&mut self, good: &Good) -> UInt {
+        let removed = self.count_units(good);
+        self.stock.retain(|goods_unit, _| &goods_unit.good != good);
+        self.consumables.retain(|(_, g)| g != good);
+        removed
+    }
+
+    /// Returns the number of timesteps for which the stock provides the given daily nutrition,
+    /// assuming only consumption (i.e. no production of new goods).
+    pub fn survival_days(&self, daily_nutrition: UInt) -> UInt {
+        let mut stock = self.clone();
+        let mut count = 0;
+        loop {
+            if stock.next_consumables().is_empty()
+                || !is_sufficient(
+                    stock.consume_nutrition(daily_nutrition as f32, |_| true),
+                    daily_nutrition as f32,
+                )
+            {
+                break; // Break out as soon as death happens.
+            }
+            stock = stock.step_forward(Action::Leisure);
+            count += 1;
+        }
+        count
+    }
+
+    /// Returns true if the given capital good is used by taking the action with this stock:
+    /// that is, if the action produces a good using it and the stock holds sufficient
+    /// quantities of every required input (see `Good::required_input_quantities`) for
+    /// production to take place.
+    pub fn is_used(&self, good: &Good, action: Action) -> bool {
+        let config = core_config();
+        let build_time = action.good().and_then(|produced_good| {
+            produced_good.multiple_timesteps_to_complete_with(&config.build_times)
+        });
+        self.is_used_with(good, action, build_time, &config)
+    }
+
+    /// Returns true if the given capital good is used by taking the action with this stock,
+    /// as in `is_used`, given the build time of the good produced and the given config.
+    pub fn is_used_with(
+        &self,
+        good: &Good,
+        action: Action,
+        build_time: Option<UInt>,
+        config: &Config,
+    ) -> bool {
+        match action {
+            Action::ProduceGood(produced_good) | Action::ProduceGoodFor(produced_good, _) => {
+                produced_good.is_produced_using(good)
+                    && produced_good.can_begin_build_with(self, &[], build_time, config)
+            }
+            Action::Leisure | Action::Consume => false,
+        }
+    }
+
     /// Takes in the current action of the agent and updates the stock accordingly.
     pub fn step_forward(&self, action: Action) -> Stock {
+        self.step_forward_with_usage(
+            action,
+            |good| self.is_used(good, action),
+            core_config().partial_abandon_grace,
+        )
+    }
+
+    /// Takes in the current action of the agent and updates the stock accordingly, degrading
+    /// only the capital goods for which `is_used` is true (e.g. as evaluated on the stock
+    /// before production, so that tools are not degraded by a failed attempt at production).
+    /// Partially complete goods are abandoned after the given grace period of idle days (see
+    /// `PartialGoodsUnit::step_forward_with`).
+    pub fn step_forward_with_usage(
+        &self,
+        action: Action,
+        is_used: impl Fn(&Good) -> bool,
+        partial_abandon_grace: UInt,
+    ) -> Stock {
         let mut new_stock = Stock::default();
         // Degrade all goods by 1 time unit.
         for (goods_unit, quantity) in &self.stock {
-            if let Some(new_goods_unit) = goods_unit.step_forward(action) {
-                let mut new_quantity = *quantity;
-                // If the goods unit is a material *and* is used by this action,
-                // remove one unit of it.
-                if goods_unit.good.is_material() {
-                    match action {
-                        Action::ProduceGood(good) => {
-                            if good.is_produced_using(&goods_unit.good) {
-                                new_quantity = new_quantity - 1;
-                            }
-                        }
-                        _ => {}
-                    }
-                }
-                // Handle improving capital goods by increasing the remaining lifetime of the
-                // goods_unit.
-                // TODO: for simplicity, improved goods never expire (as long as the improving
-                // capital good is available).
-                let mut is_improved_good = false;
-                for g in Good::iter() {
-                    if goods_unit.good.is_improved_using(&g) && self.contains(&g) {
-                        let lifetime_increment = g.lifetime_improvement_increment(&goods_unit.good);
-                        let max_lifetime = GoodsUnit::new(&goods_unit.good).remaining_lifetime
-                            + lifetime_increment;
-                        let new_lifetime = u32::min(
-                            goods_unit.remaining_lifetime + lifetime_increment,
-                            max_lifetime,
-                        );
-                        let improved_goods_unit = GoodsUnit {
-                            good: goods_unit.good,
-                            remaining_lifetime: new_lifetime,
-                        };
-                        new_stock.stock.insert(improved_goods_unit, new_quantity);
-                        is_improved_good = true;
-                        break;
-                    }
+            // Note: materials used by this action were already removed when production
+            // took place (see `consume_material_inputs`).
+
+            // Handle improving capital goods by increasing the remaining lifetime of the
+            // goods_unit. The improvement applies in place of degradation, so that units
+            // about to expire (e.g. freshly caught fish) are also preserved.
+            // TODO: for simplicity, improved goods never expire (as long as the improving
+            // capital good is available).
+            let improving_good =
+                Good::iter().find(|g| goods_unit.good.is_improved_using(g) && self.contains(g));
+            let new_goods_unit = match improving_good {
+                Some(g) => {
+                    let lifetime_increment = g.lifetime_improvement_increment(&goods_unit.good);
+                    let max_lifetime =
+                        GoodsUnit::new(&goods_unit.good).remaining_lifetime + lifetime_increment;
+                    let new_lifetime = u32::min(
+                        goods_unit.remaining_lifetime + lifetime_increment,
+                        max_lifetime,
+                    );
+                    Some(GoodsUnit {
+                        good: goods_unit.good,
+                        remaining_lifetime: new_lifetime,
+                    })
                 }
-                if !is_improved_good {
-                    new_stock.stock.insert(new_goods_unit, new_quantity);
+                None if goods_unit.good.is_consumer() || goods_unit.good.is_material() => {
+                    goods_unit.step_forward(action)
                 }
+                None => match is_used(&goods_unit.good) {
+                    true => goods_unit.step_forward(action),
+                    // A capital good that is not used is unchanged.
+                    false => Some(*goods_unit),
+                },
+            };
+            // Distinct units may reach the same (capped) lifetime, so quantities are summed.
+            if let Some(new_goods_unit) = new_goods_unit {
+                new_stock.insert_units(new_goods_unit, *quantity);
             }
         }
         // Degrade all partial goods by 1 time unit.
         for partial_goods_unit in &self.partial_stock {
-            if let Some(new_partial_goods_unit) = partial_goods_unit.step_forward(action) {
+            if let Some(new_partial_goods_unit) =
+                partial_goods_unit.step_forward_with(action, partial_abandon_grace)
+            {
                 new_stock.partial_stock.push(new_partial_goods_unit);
             }
         }
         new_stock
     }
 
-    /// Returns a vector of units of consumer goods, ordered by their remaining lifetime.
+    /// Returns the goods whose partially complete units would be abandoned on stepping
+    /// forward the stock with the given action (see `PartialGoodsUnit::step_forward`).
+    pub fn abandoned_partials(&self, action: Action) -> Vec<Good> {
+        self.abandoned_partials_with(action, core_config().partial_abandon_grace)
+    }
+
+    /// Returns the goods whose partially complete units would be abandoned on stepping
+    /// forward the stock with the given action, given the grace period of idle days.
+    pub fn abandoned_partials_with(
+        &self,
+        action: Action,
+        partial_abandon_grace: UInt,
+    ) -> Vec<Good> {
+        self.partial_stock
+            .iter()
+            .filter(|partial_goods_unit| {
+                partial_goods_unit
+                    .step_forward_with(action, partial_abandon_grace)
+                    .is_none()
+            })
+            .map(|partial_goods_unit| partial_goods_unit.good)
+            .collect()
+    }
+
+    /// Describes the change from an earlier stock to this one, with one line per change,
+    /// for use in test assertion messages.
+    ///
+    /// Units whose remaining lifetime fell by one are reported as degraded in preference
+    /// to being reported as removed and added, as happens when the stock steps forward.
+    pub fn describe_change(&self, before: &Stock) -> String {
+        let mut removed = before.stock.clone();
+        let mut added = self.stock.clone();
+        let mut lines = Vec::new();
+
+        // Match units in the earlier stock with units one step closer to expiry.
+        for goods_unit in before
+            .stock
+            .keys()
+            .sorted_by_key(|unit| (unit.good, unit.remaining_lifetime))
+        {
+            let degraded_unit = GoodsUnit {
+                good: goods_unit.good,
+                remaining_lifetime: goods_unit.remaining_lifetime.saturating_sub(1),
+            };
+            let quantity = match added.get(&degraded_unit) {
+                Some(added_qty) if degraded_unit != *goods_unit => {
+                    u32::min(removed[goods_unit], *added_qty)
+                }
+                _ => 0,
+            };
+            if quantity > 0 {
+                *removed.get_mut(goods_unit).unwrap() -= quantity;
+                *added.get_mut(&degraded_unit).unwrap() -= quantity;
+                lines.push(format!(
+                    "degraded {} x {:?} (lifetime {} -> {})",
+                    quantity,
+                    goods_unit.good,
+                    goods_unit.remaining_lifetime,
+                    degraded_unit.remaining_lifetime
+                ));
+            }
+        }
+        // Units present in both stocks are unchanged.
+        for (goods_unit, qty) in removed.iter_mut() {
+            if let Some(added_qty) = added.get_mut(goods_unit) {
+                let unchanged = u32::min(*qty, *added_qty);
+                *qty -= unchanged;
+                *added_qty -= unchanged;
+            }
+        }
+        for (label, units) in [("removed", &removed), ("added", &added)] {
+            for (goods_unit, qty) in units
+                .iter()
+                .filter(|(_, qty)| **qty > 0)
+                .sorted_by_key(|(unit, _)| (unit.good, unit.remaining_lifetime))
+            {
+                lines.push(format!(
+                    "{} {} x {:?} (lifetime {})",
+                    label, qty, goods_unit.good, goods_unit.remaining_lifetime
+                ));
+            }
+        }
+
+        for good in Good::iter() {
+            match (before.get_partial(good), self.get_partial(good)) {
+                (Some(old), Some(new)) if old.time_to_completion != new.time_to_completion => lines
+                    .push(format!(
+                        "progressed partial {:?} ({} -> {} steps to completion)",
+                        good, old.time_to_completion, new.time_to_completion
+                    )),
+                (Some(old), None) => lines.push(format!(
+                    "removed partial {:?} ({} steps to completion)",
+                    good, old.time_to_completion
+                )),
+                (None, Some(new)) => lines.push(format!(
+                    "added partial {:?} ({} steps to completion)",
+                    good, new.time_to_completion
+                )),
+                _ => {}
+            }
+        }
+
+        match lines.is_empty() {
+            true => String::from("no change"),
+            false => lines.join("\n"),
+        }
+    }
+
+    /// Returns a vector of units of consumer goods, ordered by their remaining lifetime and
+    /// then by the order in which the goods are declared.
     pub fn next_consumables(&self) -> Vec<(&GoodsUnit, &u32)> {
-        self.stock
+        self.consumables
             .iter()
-            .filter(|(good, _)| good.good.is_consumer())
-            .sorted_by_key(|(good, _)| good.remaining_lifetime)
+            .filter_map(|&(remaining_lifetime, good)| {
+                self.stock.get_key_value(&GoodsUnit {
+                    good,
+                    remaining_lifetime,
+                })
+            })
             .collect()
     }
 
+    /// Consumes units of consumer goods satisfying the predicate, earliest expiry first (with
+    /// ties broken as configured), until the given number of nutritional units is provided.
+    /// Returns the number of nutritional units provided, which may exceed the number required
+    /// if a unit provides several.
+    pub fn consume_nutrition(
+        &mut self,
+        nutritional_units: f32,
+        predicate: impl Fn(&Good) -> bool,
+    ) -> f32 {
+        self.consume_nutrition_units(nutritional_units, predicate).0
+    }
+
+    /// Consumes units as in `consume_nutrition`, returning the number of nutritional units
+    /// provided together with the units consumed, in the order in which they were consumed.
+    pub fn consume_nutrition_units(
+        &mut self,
+        nutritional_units: f32,
+        predicate: impl Fn(&Good) -> bool,
+    ) -> (f32, Vec<(GoodsUnit, UInt)>) {
+        let config = core_config();
+        self.consume_nutrition_units_with(
+            nutritional_units,
+            predicate,
+            &config.spoilage,
+            config.consume_tie_break,
+        )
+    }
+
+    /// Consumes units as in `consume_nutrition_units`, valuing each unit under the given
+    /// spoilage model and breaking ties between units expiring together as given.
+    pub fn consume_nutrition_units_with(
+        &mut self,
+        nutritional_units: f32,
+        predicate: impl Fn(&Good) -> bool,
+        spoilage: &SpoilageModel,
+        tie_break: ConsumeTieBreak,
+    ) -> (f32, Vec<(GoodsUnit, UInt)>) {
+        self.consume_units_valued(
+            nutritional_units,
+            predicate,
+            |goods_unit| goods_unit.nutrition_value(spoilage),
+            tie_break,
+        )
+    }
+
+    fn consume_units_valued(
+        &mut self,
+        nutritional_units: f32,
+        predicate: impl Fn(&Good) -> bool,
+        nutrition_value: impl Fn(&GoodsUnit) -> f32,
+        tie_break: ConsumeTieBreak,
+    ) -> (f32, Vec<(GoodsUnit, UInt)>) {
+        let mut consumables: Vec<(GoodsUnit, UInt)> = self
+            .next_consumables()
+            .into_iter()
+            .filter(|(goods_unit, _)| predicate(&goods_unit.good))
+            .map(|(goods_unit, qty)| (*goods_unit, *qty))
+            .collect();
+        if tie_break == ConsumeTieBreak::NutritionValue {
+            // The sort is stable, so units of equal value remain in the order of their goods.
+            consumables.sort_by(|(a, _), (b, _)| {
+                a.remaining_lifetime
+                    .cmp(&b.remaining_lifetime)
+                    .then(nutrition_value(b).total_cmp(&nutrition_value(a)))
+            });
+        }
+        let mut provided = 0.0;
+        let mut consumed_units = Vec::new();
+        for (goods_unit, qty) in consumables {
+            let outstanding = nutritional_units - provided;
+            if outstanding <= NUTRITION_TOLERANCE {
+                break;
+            }
+            let value = nutrition_value(&goods_unit);
+            if value <= 0.0 {
+                continue;
+            }
+            let required = ((outstanding - NUTRITION_TOLERANCE) / value).ceil() as UInt;
+            let consumed = qty.min(required);
+            self.remove(&goods_unit, consumed);
+            provided += consumed as f32 * value;
+            consumed_units.push((goods_unit, consumed));
+        }
+        (provided, consumed_units)
+    }
+
     /// Returns a vector of units of consumer goods, ordered by their remaining lifetime.
     pub fn next_capital_goods_units(&self, capital_good: &Good) -> Vec<(&GoodsUnit, &u32)> {
         self.stock
@@ -256,8 +792,10 @@ mod tests {
     use crate::{
         UInt,
         actions::Action,
-        goods::{Good, GoodsUnit},
+        config::SpoilageModel,
+        goods::{Good, GoodsUnit, GoodsUnitLevel},
     };
+    use rand::{Rng, SeedableRng, rngs::StdRng};
 
     #[test]
     fn test_add() {
@@ -269,13 +807,15 @@ mod tests {
             }),
             None
         );
-        stock.add(
-            GoodsUnit {
-                good: Good::Berries,
-                remaining_lifetime: 10,
-            },
-            2,
-        );
+        stock
+            .add(
+                GoodsUnit {
+                    good: Good::Berries,
+                    remaining_lifetime: 10,
+                },
+                2,
+            )
+            .unwrap();
         assert_eq!(
             stock.stock.get(&GoodsUnit {
                 good: Good::Berries,
@@ -290,13 +830,15 @@ mod tests {
             }),
             None
         );
-        stock.add(
-            GoodsUnit {
-                good: Good::Berries,
-                remaining_lifetime: 10,
-            },
-            3,
-        );
+        stock
+            .add(
+                GoodsUnit {
+                    good: Good::Berries,
+                    remaining_lifetime: 10,
+                },
+                3,
+            )
+            .unwrap();
         assert_eq!(
             stock.stock.get(&GoodsUnit {
                 good: Good::Berries,
@@ -311,13 +853,15 @@ mod tests {
             }),
             None
         );
-        stock.add(
-            GoodsUnit {
-                good: Good::Berries,
-                remaining_lifetime: 9,
-            },
-            1,
-        );
+        stock
+            .add(
+                GoodsUnit {
+                    good: Good::Berries,
+                    remaining_lifetime: 9,
+                },
+                1,
+            )
+            .unwrap();
         assert_eq!(
             stock.stock.get(&GoodsUnit {
                 good: Good::Berries,
@@ -327,6 +871,345 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_consume_nutrition_valued() {
+        let mut stock = Stock::default();
+        stock.add(GoodsUnit::new(&Good::Berries), 2).unwrap();
+        stock.add(GoodsUnit::new(&Good::Fish), 2).unwrap();
+        let nutrition_value = |goods_unit: &GoodsUnit| match goods_unit.good {
+            Good::Fish => 3.0,
+            _ => 1.0,
+        };
+        // One unit of a high-nutrition good satisfies a 3-unit requirement.
+        let provided = stock
+            .consume_units_valued(
+                3.0,
+                |good| *good == Good::Fish,
+                nutrition_value,
+                ConsumeTieBreak::GoodOrder,
+            )
+            .0;
+        assert_eq!(provided, 3.0);
+        assert_eq!(stock.count_units(&Good::Fish), 1);
+        assert_eq!(stock.count_units(&Good::Berries), 2);
+
+        // Whole units are consumed, so the nutrition provided may exceed the requirement.
+        let provided = stock
+            .consume_units_valued(
+                2.0,
+                |good| *good == Good::Fish,
+                nutrition_value,
+                ConsumeTieBreak::GoodOrder,
+            )
+            .0;
+        assert_eq!(provided, 3.0);
+        assert_eq!(stock.count_units(&Good::Fish), 0);
+    }
+
+    #[test]
+    fn test_consume_nutrition_linear_spoilage() {
+        let near_expiry = GoodsUnit {
+            good: Good::Berries,
+            remaining_lifetime: 2,
+        };
+        let nutrition_value =
+            |goods_unit: &GoodsUnit| goods_unit.nutrition_value(&SpoilageModel::Linear);
+
+        // Under the cliff-edge model, near-expiry units provide full sustenance.
+        let mut stock = Stock::default();
+        stock.add(near_expiry, 3).unwrap();
+        let provided = stock
+            .consume_units_valued(
+                3.0,
+                |_| true,
+                |goods_unit| goods_unit.nutrition_value(&SpoilageModel::CliffEdge),
+                ConsumeTieBreak::GoodOrder,
+            )
+            .0;
+        assert_eq!(provided, 3.0);
+
+        // Under the linear model, near-expiry units provide less sustenance.
+        let mut stock = Stock::default();
+        stock.add(near_expiry, 3).unwrap();
+        let provided = stock
+            .consume_units_valued(3.0, |_| true, nutrition_value, ConsumeTieBreak::GoodOrder)
+            .0;
+        assert!(!is_sufficient(provided, 3.0));
+        assert!(stock.is_empty());
+
+        // Fresh units provide full sustenance.
+        let mut stock = Stock::default();
+        stock.add(GoodsUnit::new(&Good::Berries), 3).unwrap();
+        let provided = stock
+            .consume_units_valued(3.0, |_| true, nutrition_value, ConsumeTieBreak::GoodOrder)
+            .0;
+        assert!(is_sufficient(provided, 3.0));
+    }
+
+    #[test]
+    fn test_consume_tie_break() {
+        let mut stock = Stock::default();
+        for good in [Good::Fish, Good::Berries] {
+            let goods_unit = GoodsUnit {
+                good,
+                remaining_lifetime: 2,
+            };
+            stock.add(goods_unit, 1).unwrap();
+        }
+        let consume_one = |tie_break| {
+            let mut stock = stock.clone();
+            stock.consume_units_valued(
+                0.1,
+                |_| true,
+                |goods_unit| goods_unit.nutrition_value(&SpoilageModel::Linear),
+                tie_break,
+            );
+            Good::iter()
+                .find(|good| stock.count_units(good) == 0 && good.is_consumer())
+                .unwrap()
+        };
+        // Of units expiring together, berries are declared first.
+        assert_eq!(consume_one(ConsumeTieBreak::GoodOrder), Good::Berries);
+        // Two days from expiry, fish retain more of their (shorter) lifetime than berries.
+        assert_eq!(consume_one(ConsumeTieBreak::NutritionValue), Good::Fish);
+    }
+
+    #[test]
+    fn test_from_goods() {
+        let mut expected = Stock::default();
+        expected.add(GoodsUnit::new(&Good::Berries), 5).unwrap();
+        expected.add(GoodsUnit::new(&Good::Spear), 1).unwrap();
+        expected.add(GoodsUnit::new(&Good::Berries), 2).unwrap();
+        let stock = Stock::from_goods(&[
+            (Good::Berries, 5),
+            (Good::Spear, 1),
+            (Good::Fish, 0),
+            (Good::Berries, 2),
+        ]);
+        assert_eq!(stock, expected);
+        assert!(!stock.contains(&Good::Fish));
+
+        let fish = GoodsUnit {
+            good: Good::Fish,
+            remaining_lifetime: 3,
+        };
+        expected.add(fish, 4).unwrap();
+        let stock = Stock::from_units(&[
+            (GoodsUnit::new(&Good::Berries), 7),
+            (GoodsUnit::new(&Good::Spear), 1),
+            (fish, 4),
+        ]);
+        assert_eq!(stock, expected);
+        assert_eq!(stock.next_consumables()[0], (&fish, &4));
+    }
+
+    #[test]
+    fn test_add_errors() {
+        let mut stock = Stock::default();
+        assert_eq!(
+            stock.add(GoodsUnit::new(&Good::Berries), 0),
+            Err(StockError::ZeroQuantity(Good::Berries))
+        );
+        assert_eq!(stock, Stock::default());
+
+        let partial = PartialGoodsUnit::new(&Good::Boat).unwrap();
+        assert_eq!(stock.add_partial(partial), Ok(()));
+        assert_eq!(
+            stock.add_partial(partial),
+            Err(StockError::DuplicatePartial(Good::Boat))
+        );
+        assert_eq!(stock.partial_stock.len(), 1);
+    }
+
+    #[test]
+    fn test_remove_all() {
+        let mut stock = Stock::default();
+        stock.add(GoodsUnit::new(&Good::Fish), 3).unwrap();
+        stock
+            .add(
+                GoodsUnit {
+                    good: Good::Fish,
+                    remaining_lifetime: 5,
+                },
+                2,
+            )
+            .unwrap();
+        stock.add(GoodsUnit::new(&Good::Berries), 4).unwrap();
+
+        // All units of the good are removed, whatever their remaining lifetime.
+        assert_eq!(stock.remove_all(&Good::Fish), 5);
+        assert!(!stock.contains(&Good::Fish));
+        assert_eq!(stock.count_units(&Good::Berries), 4);
+        assert_eq!(stock.remove_all(&Good::Fish), 0);
+    }
+
+    #[test]
+    fn test_is_empty() {
+        let stock = Stock::default();
+        assert!(stock.is_empty());
+        assert_eq!(stock.num_distinct_units(), 0);
+
+        let mut stock = Stock::default();
+        stock.add(GoodsUnit::new(&Good::Berries), 3).unwrap();
+        stock
+            .add(
+                GoodsUnit {
+                    good: Good::Berries,
+                    remaining_lifetime: 1,
+                },
+                1,
+            )
+            .unwrap();
+        assert!(!stock.is_empty());
+        assert_eq!(stock.num_distinct_units(), 2);
+
+        let mut stock = Stock::default();
+        stock
+            .add_partial_with_limit(PartialGoodsUnit::new(&Good::Axe).unwrap(), None)
+            .unwrap();
+        assert!(!stock.is_empty());
+        assert_eq!(stock.num_distinct_units(), 0);
+    }
+
+    #[test]
+    fn test_lifetime_histogram() {
+        let mut stock = Stock::default();
+        for (remaining_lifetime, qty) in [(10, 3), (9, 2), (8, 1)] {
+            let goods_unit = GoodsUnit {
+                good: Good::Berries,
+                remaining_lifetime,
+            };
+            stock.add(goods_unit, qty).unwrap();
+        }
+        stock.add(GoodsUnit::new(&Good::Fish), 4).unwrap();
+
+        assert_eq!(
+            stock.lifetime_histogram(&Good::Berries),
+            BTreeMap::from([(8, 1), (9, 2), (10, 3)])
+        );
+        assert_eq!(
+            stock
+                .oldest_unit(&Good::Berries)
+                .unwrap()
+                .remaining_lifetime,
+            8
+        );
+        assert_eq!(
+            stock
+                .freshest_unit(&Good::Berries)
+                .unwrap()
+                .remaining_lifetime,
+            10
+        );
+        assert!(stock.lifetime_histogram(&Good::Spear).is_empty());
+        assert_eq!(stock.oldest_unit(&Good::Spear), None);
+    }
+
+    #[test]
+    fn test_survival_days() {
+        let mut stock = Stock::default();
+        assert_eq!(stock.survival_days(3), 0);
+        stock.add(GoodsUnit::new(&Good::Berries), 7).unwrap();
+        assert_eq!(stock.survival_days(3), 2);
+        // Survival is limited by the lifetime of the goods.
+        stock.add(GoodsUnit::new(&Good::Berries), 100).unwrap();
+        assert_eq!(stock.survival_days(3), 10);
+    }
+
+    #[test]
+    fn test_value() {
+        let mut stock = Stock::default();
+        stock.add(GoodsUnit::new(&Good::Berries), 4).unwrap();
+        stock
+            .add(
+                GoodsUnit {
+                    good: Good::Berries,
+                    remaining_lifetime: 2,
+                },
+                2,
+            )
+            .unwrap();
+        stock.add(GoodsUnit::new(&Good::Fish), 3).unwrap();
+        stock.add(GoodsUnit::new(&Good::Spear), 1).unwrap();
+
+        // Spears have no listed price and Timber is not in the stock.
+        let prices = HashMap::from([
+            (Good::Berries, 0.5),
+            (Good::Fish, 2.0),
+            (Good::Timber, 10.0),
+        ]);
+        assert_eq!(stock.value(&prices), 9.0);
+        assert_eq!(Stock::default().value(&prices), 0.0);
+    }
+
+    #[test]
+    fn test_max_concurrent_builds() {
+        let limit = Some(2);
+        let mut stock = Stock::default();
+        for good in [Good::Boat, Good::Axe] {
+            let partial = PartialGoodsUnit::new(&good).unwrap();
+            assert_eq!(stock.add_partial_with_limit(partial, limit), Ok(()));
+        }
+        let partial = PartialGoodsUnit::new(&Good::Smoker).unwrap();
+        assert_eq!(
+            stock.add_partial_with_limit(partial, limit),
+            Err(StockError::TooManyBuilds(2))
+        );
+        assert_eq!(stock.get_partial(Good::Smoker), None);
+        // Without a limit, the build may begin.
+        assert_eq!(stock.add_partial_with_limit(partial, None), Ok(()));
+    }
+
+    #[test]
+    fn test_discretise_partial() {
+        let mut stock = Stock::default();
+        assert_eq!(stock.discretise().partial, InvLevel::Low);
+        stock
+            .add_partial(PartialGoodsUnit::new(&Good::Boat).unwrap())
+            .unwrap();
+        assert_eq!(stock.discretise().partial, InvLevel::Medium);
+        // The most advanced partial good determines the level.
+        let mut axe = PartialGoodsUnit::new(&Good::Axe).unwrap();
+        axe.increment_production();
+        stock.add_partial(axe).unwrap();
+        assert_eq!(stock.discretise().partial, InvLevel::Medium);
+        for _ in 0..6 {
+            stock.progress_partial(&Good::Boat);
+        }
+        assert_eq!(stock.discretise().partial, InvLevel::High);
+    }
+
+    #[test]
+    fn test_discretise_remaining_lifetime() {
+        let mut stock = Stock::default();
+        stock.add(GoodsUnit::new(&Good::Berries), 2).unwrap();
+        stock
+            .add(
+                GoodsUnit {
+                    good: Good::Berries,
+                    remaining_lifetime: 1,
+                },
+                2,
+            )
+            .unwrap();
+        let discrete = stock.discretise();
+        let fresh = GoodsUnitLevel::new(Good::Berries, RemainingLevel::High);
+        let spoiling = GoodsUnitLevel::new(Good::Berries, RemainingLevel::Low);
+        assert_ne!(fresh, spoiling);
+        assert_eq!(discrete.stock.get(&fresh), Some(&InvLevel::Low));
+        assert_eq!(discrete.stock.get(&spoiling), Some(&InvLevel::Low));
+
+        // Capital goods are not bucketed by remaining lifetime.
+        let mut stock = Stock::default();
+        stock.add(GoodsUnit::new(&Good::Boat), 1).unwrap();
+        let discrete = stock.discretise();
+        assert!(
+            discrete
+                .stock
+                .contains_key(&GoodsUnitLevel::new(Good::Boat, RemainingLevel::Low))
+        );
+    }
+
     #[test]
     fn test_next_consumables() {
         let mut stock = HashMap::<GoodsUnit, UInt>::new();
@@ -334,10 +1217,7 @@ mod tests {
         stock.insert(GoodsUnit::new(&Good::Berries), 2);
         stock.insert(GoodsUnit::new(&Good::Fish), 1);
 
-        let stock = Stock {
-            stock: stock,
-            partial_stock: vec![],
-        };
+        let stock = Stock::new(stock, vec![]);
 
         let mut result = stock.next_consumables();
         assert_eq!(result.len(), 2);
@@ -351,6 +1231,188 @@ mod tests {
         assert_eq!(second.1, &1);
     }
 
+    #[test]
+    fn test_next_consumables_index() {
+        // The consumables in order of remaining lifetime, found by sorting the whole stock.
+        let sorted_consumables = |stock: &Stock| -> Vec<(GoodsUnit, UInt)> {
+            stock
+                .stock
+                .iter()
+                .filter(|(goods_unit, _)| goods_unit.good.is_consumer())
+                .map(|(goods_unit, qty)| (*goods_unit, *qty))
+                .sorted_by_key(|(goods_unit, _)| goods_unit.remaining_lifetime)
+                .collect()
+        };
+        // Units with equal lifetimes may appear in either order, so compare the lifetimes in
+        // order and the units as a set.
+        let assert_matches = |stock: &Stock| {
+            let actual: Vec<(GoodsUnit, UInt)> = stock
+                .next_consumables()
+                .into_iter()
+                .map(|(goods_unit, qty)| (*goods_unit, *qty))
+                .collect();
+            let expected = sorted_consumables(stock);
+            let lifetimes = |units: &Vec<(GoodsUnit, UInt)>| {
+                units
+                    .iter()
+                    .map(|(goods_unit, _)| goods_unit.remaining_lifetime)
+                    .collect_vec()
+            };
+            assert_eq!(lifetimes(&actual), lifetimes(&expected));
+            let as_set = |units: Vec<(GoodsUnit, UInt)>| {
+                units
+                    .into_iter()
+                    .sorted_by_key(|(goods_unit, _)| {
+                        (goods_unit.remaining_lifetime, goods_unit.good)
+                    })
+                    .collect_vec()
+            };
+            assert_eq!(as_set(actual), as_set(expected));
+        };
+
+        // Apply a long random sequence of modifications, checking the index after each.
+        let mut rng = StdRng::seed_from_u64(0);
+        let goods: Vec<Good> = Good::iter().collect();
+        let mut stock = Stock::default();
+        for _ in 0..2000 {
+            let good = goods[rng.random_range(0..goods.len())];
+            match rng.random_range(0..5) {
+                0 | 1 => {
+                    let goods_unit = GoodsUnit {
+                        good,
+                        remaining_lifetime: rng.random_range(1..=5),
+                    };
+                    stock.add(goods_unit, rng.random_range(1..=3)).unwrap();
+                }
+                2 => {
+                    let units: Vec<(GoodsUnit, UInt)> = stock
+                        .stock
+                        .iter()
+                        .map(|(unit, qty)| (*unit, *qty))
+                        .collect();
+                    if let Some((goods_unit, qty)) = units.into_iter().next() {
+                        stock.remove(&goods_unit, rng.random_range(1..=qty));
+                    }
+                }
+                3 => {
+                    stock.remove_all(&good);
+                    stock.consume_nutrition(2.0, |_| true);
+                }
+                _ => stock = stock.step_forward(Action::Leisure),
+            }
+            assert_matches(&stock);
+        }
+
+        // The index is rebuilt when a stock is deserialized.
+        stock.add(GoodsUnit::new(&Good::Berries), 2).unwrap();
+        let deserialized: Stock =
+            serde_json::from_str(&serde_json::to_string(&stock).unwrap()).unwrap();
+        assert_eq!(deserialized, stock);
+        assert_matches(&deserialized);
+    }
+
+    #[test]
+    #[ignore = "benchmark on a large stock; run with --ignored"]
+    fn test_next_consumables_benchmark() {
+        // A large stock, with a distinct unit of each consumer good at every lifetime.
+        let mut stock = Stock::default();
+        for remaining_lifetime in 1..=500 {
+            for good in [Good::Berries, Good::Fish] {
+                let goods_unit = GoodsUnit {
+                    good,
+                    remaining_lifetime,
+                };
+                stock.add(goods_unit, 1).unwrap();
+            }
+        }
+        // Finding the soonest-to-expire unit needs no sorting, so is fast even on a large stock.
+        for _ in 0..1000 {
+            let (next, _) = stock.next_consumables()[0];
+            assert_eq!(next.remaining_lifetime, 1);
+        }
+    }
+
+    #[test]
+    fn test_is_used() {
+        let mut stock = Stock::default();
+        stock.add(GoodsUnit::new(&Good::Axe), 1).unwrap();
+        stock.add(GoodsUnit::new(&Good::Timber), 2).unwrap();
+        assert!(stock.is_used(&Good::Axe, Action::ProduceGood(Good::Timber)));
+        assert!(!stock.is_used(&Good::Axe, Action::Leisure));
+        // A smoker needs three units of timber, so the attempt to build one fails.
+        assert!(!stock.is_used(&Good::Timber, Action::ProduceGood(Good::Smoker)));
+        stock.add(GoodsUnit::new(&Good::Timber), 1).unwrap();
+        assert!(stock.is_used(&Good::Timber, Action::ProduceGood(Good::Smoker)));
+    }
+
+    #[test]
+    fn test_multiple_material_depletion() {
+        // Configure an axe to be built from both timber (for its handle) and spears (for its
+        // head), so that it needs enough of each to begin.
+        let config = Config {
+            materials: HashMap::from([(
+                Good::Axe,
+                HashMap::from([(Good::Timber, 1), (Good::Spear, 1)]),
+            )]),
+            ..Config::default()
+        };
+        let build_time = Good::Axe.multiple_timesteps_to_complete();
+        let mut stock = Stock::default();
+        stock.add(GoodsUnit::new(&Good::Timber), 5).unwrap();
+        stock.add(GoodsUnit::new(&Good::Spear), 1).unwrap();
+        assert!(!Good::Axe.can_begin_build_with(&stock, &[], build_time, &config));
+        assert_eq!(
+            Good::Axe.missing_input_with(&stock, build_time, &config),
+            Some(Good::Spear)
+        );
+        stock.add(GoodsUnit::new(&Good::Spear), 1).unwrap();
+        assert!(Good::Axe.can_begin_build_with(&stock, &[], build_time, &config));
+
+        // One unit of each material is used up at every step of the build.
+        stock
+            .add_partial(PartialGoodsUnit::new(&Good::Axe).unwrap())
+            .unwrap();
+        for step in 1..=build_time.unwrap() {
+            stock.progress_partial(&Good::Axe);
+            stock.consume_material_inputs_with(&Good::Axe, &config);
+            assert_eq!(stock.count_units(&Good::Timber), 5 - step);
+            assert_eq!(stock.count_units(&Good::Spear), 2 - step);
+        }
+        assert_eq!(stock.get_partial(Good::Axe), None);
+        assert_eq!(stock.count_units(&Good::Axe), 1);
+    }
+
+    #[test]
+    fn test_describe_change() {
+        let mut before = Stock::default();
+        before.add(GoodsUnit::new(&Good::Berries), 4).unwrap();
+        let stale_fish = GoodsUnit {
+            good: Good::Fish,
+            remaining_lifetime: 1,
+        };
+        before.add(stale_fish, 1).unwrap();
+        before
+            .add_partial(PartialGoodsUnit::new(&Good::Boat).unwrap())
+            .unwrap();
+        before.progress_partial(&Good::Boat);
+        before.progress_partial(&Good::Boat);
+        let time_to_completion = before.get_partial(Good::Boat).unwrap().time_to_completion;
+
+        assert_eq!(before.describe_change(&before), "no change");
+
+        let mut after = before.step_forward(Action::Leisure);
+        after.add(GoodsUnit::new(&Good::Berries), 2).unwrap();
+        let expected = format!(
+            "degraded 4 x Berries (lifetime 10 -> 9)\n\
+             removed 1 x Fish (lifetime 1)\n\
+             added 2 x Berries (lifetime 10)\n\
+             progressed partial Boat ({} -> {} steps to completion)",
+            time_to_completion,
+            time_to_completion + 1
+        );
+        assert_eq!(after.describe_change(&before), expected);
+    }
+
     #[test]
     fn test_step_forward() {
         let mut stock = HashMap::<GoodsUnit, UInt>::new();
@@ -369,10 +1431,7 @@ mod tests {
             },
             1,
         );
-        let stock = Stock {
-            stock: stock,
-            partial_stock: vec![],
-        };
+        let stock = Stock::new(stock, vec![]);
 
         assert_eq!(
             stock.stock.get(&GoodsUnit {
@@ -446,10 +1505,7 @@ mod tests {
             },
             5,
         );
-        let mut stock = Stock {
-            stock: stock,
-            partial_stock: vec![],
-        };
+        let mut stock = Stock::new(stock, vec![]);
 
         assert_eq!(
             stock.stock.get(&GoodsUnit {
@@ -485,10 +1541,7 @@ mod tests {
             },
             1,
         );
-        let mut stock = Stock {
-            stock: stock,
-            partial_stock: vec![],
-        };
+        let mut stock = Stock::new(stock, vec![]);
 
         assert_eq!(
             stock.stock.get(&GoodsUnit {
@@ -510,5 +1563,18 @@ mod tests {
             }),
             Some(&5)
         );
+
+        // Freshly caught fish, which would otherwise expire, are also preserved and
+        // combined with the existing smoked fish.
+        stock.add(GoodsUnit::new(&Good::Fish), 3).unwrap();
+        stock = stock.step_forward(Action::Leisure);
+        assert_eq!(stock.count_units(&Good::Fish), 8);
+        assert_eq!(
+            stock.stock.get(&GoodsUnit {
+                good: Good::Fish,
+                remaining_lifetime: 21
+            }),
+            Some(&8)
+        );
     }
 }
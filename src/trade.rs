@@ -0,0 +1,54 @@
+use crate::UInt;
+use crate::agent::Agent;
+use crate::goods::GoodsUnit;
+use crate::stock::StockError;
+
+/// Transfers a quantity of units of a good from one agent's stock to another's.
+///
+/// The transfer is all or nothing: if the giving agent holds fewer than `quantity` units,
+/// neither stock is changed.
+pub fn transfer(
+    from: &mut dyn Agent,
+    to: &mut dyn Agent,
+    goods_unit: GoodsUnit,
+    quantity: UInt,
+) -> Result<(), StockError> {
+    if quantity == 0 {
+        return Err(StockError::ZeroQuantity(goods_unit.good));
+    }
+    let held = from.stock().quantity_of(&goods_unit);
+    if held < quantity {
+        return Err(StockError::InsufficientStock(goods_unit.good));
+    }
+    to.acquire(goods_unit, quantity)?;
+    from.stock_mut().remove(&goods_unit, quantity);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::CrusoeAgent;
+    use crate::goods::Good;
+
+    #[test]
+    fn test_transfer() {
+        let berries = GoodsUnit::new(&Good::Berries);
+        let mut giver = CrusoeAgent::new(1);
+        let mut receiver = CrusoeAgent::new(2);
+        giver.acquire(berries, 5).unwrap();
+
+        transfer(&mut giver, &mut receiver, berries, 3).unwrap();
+        assert_eq!(giver.stock().count_units(&Good::Berries), 2);
+        assert_eq!(receiver.stock().count_units(&Good::Berries), 3);
+
+        // A transfer of more units than are held leaves both stocks unchanged.
+        let (giver_stock, receiver_stock) = (giver.stock().clone(), receiver.stock().clone());
+        assert_eq!(
+            transfer(&mut giver, &mut receiver, berries, 3),
+            Err(StockError::InsufficientStock(Good::Berries))
+        );
+        assert_eq!(giver.stock(), &giver_stock);
+        assert_eq!(receiver.stock(), &receiver_stock);
+    }
+}
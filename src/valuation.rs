@@ -1,25 +1,52 @@
-use rand::{SeedableRng, rngs::StdRng};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::sync::Mutex;
 use strum::IntoEnumIterator;
 
 use crate::{
     Model, UInt,
-    actions::Action,
-    agent::Agent,
+    actions::{Action, ActionFlattened},
+    agent::{Agent, AgentCore, DecisionState},
     goods::{Good, GoodsUnit, PartialGoodsUnit, Productivity},
     learning::{agent_state::DiscrRep, reward::Reward},
-    stock::Stock,
+    stock::{Stock, StockError},
 };
 
+/// The (capital good, consumer good) pairs already warned of the capital good not increasing
+/// the productivity of the consumer good.
+static NO_IMPROVEMENT_WARNED: Mutex<BTreeSet<(Good, Good)>> = Mutex::new(BTreeSet::new());
+
+/// The marginal benefit of an action to a rational agent, given its stock, with the
+/// reasoning behind it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenefitReport {
+    pub action: Action,
+    /// The marginal benefit of the action, if it can be computed.
+    pub benefit: Option<f32>,
+    /// Whether the agent can produce the good (always true for actions other than production).
+    pub is_producible: bool,
+    /// The first input required for production that is missing from the stock, if any.
+    pub missing_input: Option<Good>,
+}
+
+/// The reason a rational agent chose its action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DecisionTrace {
+    /// Production of a consumer good had the greatest marginal benefit, exceeding leisure.
+    HighestMarginalBenefit,
+    /// No production action had a marginal benefit exceeding that of leisure.
+    LeisurePreferred,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RationalAgent {
     id: u64,
     stock: Stock,
-    is_alive: bool,
     action_history: Vec<Action>,
     stock_history: Vec<Stock>,
     reward_history: Vec<Reward>,
-    daily_nutrition: UInt,
+    #[serde(flatten)]
+    core: AgentCore,
 }
 
 impl RationalAgent {
@@ -27,11 +54,32 @@ impl RationalAgent {
         RationalAgent {
             id,
             stock: Stock::default(),
-            is_alive: true,
             action_history: vec![],
             stock_history: vec![],
             reward_history: vec![],
-            daily_nutrition,
+            core: AgentCore::new(daily_nutrition),
+        }
+    }
+
+    /// Returns a scratch agent with the given decision state and no history.
+    pub fn from_decision_state(id: u64, state: DecisionState) -> Self {
+        RationalAgent {
+            stock: state.stock,
+            ..Self::new(id, state.daily_nutrition)
+        }
+    }
+
+    /// Returns a scratch copy of this agent without its history, for rollouts.
+    fn scratch(&self) -> Self {
+        RationalAgent {
+            core: AgentCore {
+                rng: self.core.rng.clone(),
+                config: self.core.config.clone(),
+                season: self.core.season,
+                resources: self.core.resources.clone(),
+                ..AgentCore::new(self.core.daily_nutrition)
+            },
+            ..Self::from_decision_state(self.id, self.decision_snapshot())
         }
     }
 
@@ -49,15 +97,142 @@ impl RationalAgent {
         // i.e. disount over the interval of production (but nott the intervals between uses).
 
         let good = match action {
-            Action::ProduceGood(good) => Some(good),
-            Action::Leisure => None,
+            Action::ProduceGood(good) | Action::ProduceGoodFor(good, _) => Some(good),
+            Action::Leisure | Action::Consume => None,
         };
         match good {
             Some(good) => match good.is_consumer() {
                 true => self.marginal_benefit_of_producing_consumer_goods(good),
                 false => todo!(),
             },
-            None => 0.0,
+            None => match action {
+                Action::Leisure => self.core.config.agent.leisure_value,
+                _ => 0.0,
+            },
+        }
+    }
+
+    /// Returns a report of the marginal benefit of every action, given the existing stock.
+    pub fn benefit_report(&self) -> Vec<BenefitReport> {
+        ActionFlattened::enabled_with(&self.core.config)
+            .into_iter()
+            .map(Action::from)
+            .map(|action| match action {
+                Action::ProduceGood(good) | Action::ProduceGoodFor(good, _) => {
+                    let is_producible = self.productivity(&good) != Productivity::None;
+                    // TODO: include the benefit of capital goods once it is available.
+                    let benefit = (good.is_consumer() && is_producible)
+                        .then(|| self.marginal_benefit_of_action(&action));
+                    BenefitReport {
+                        action,
+                        benefit,
+                        is_producible,
+                        missing_input: self.missing_input(&good),
+                    }
+                }
+                Action::Leisure | Action::Consume => BenefitReport {
+                    action,
+                    benefit: Some(self.marginal_benefit_of_action(&action)),
+                    is_producible: true,
+                    missing_input: None,
+                },
+            })
+            .collect()
+    }
+
+    /// Returns the opportunity cost of leisure, measured in the survival days forgone by not
+    /// taking the production action with the greatest marginal benefit instead.
+    pub fn leisure_opportunity_cost(&self) -> f32 {
+        self.benefit_report()
+            .into_iter()
+            .filter(|report| matches!(report.action, Action::ProduceGood(_)))
+            .filter_map(|report| report.benefit)
+            .fold(0.0, f32::max)
+    }
+
+    /// Chooses the action with the greatest marginal benefit, given the marginal benefit of
+    /// leisure. Production is only chosen if its benefit strictly exceeds that of leisure.
+    pub fn choose_action_with_leisure_value(&mut self, leisure_value: f32) -> Action {
+        self.choose_action_traced_with_leisure_value(leisure_value)
+            .0
+    }
+
+    /// Chooses an action as in `choose_action`, also returning the reason for the choice.
+    pub fn choose_action_traced(&mut self) -> (Action, DecisionTrace) {
+        self.choose_action_traced_with_leisure_value(self.core.config.agent.leisure_value)
+    }
+
+    /// Chooses an action as in `choose_action_with_leisure_value`, also returning the reason
+    /// for the choice.
+    pub fn choose_action_traced_with_leisure_value(
+        &mut self,
+        leisure_value: f32,
+    ) -> (Action, DecisionTrace) {
+        // TODO: include the production of capital goods once their marginal benefit is available.
+        let mut action = Action::Leisure;
+        let mut max_benefit = leisure_value;
+        let enabled_goods = &self.core.config.enabled_goods;
+        for good in Good::iter().filter(|good| good.is_consumer() && enabled_goods.contains(good)) {
+            let candidate = Action::ProduceGood(good);
+            let benefit = self.marginal_benefit_of_action(&candidate);
+            if benefit > max_benefit {
+                action = candidate;
+                max_benefit = benefit;
+            }
+        }
+        self.action_history.push(action);
+        let trace = match action {
+            Action::Leisure => DecisionTrace::LeisurePreferred,
+            _ => DecisionTrace::HighestMarginalBenefit,
+        };
+        (action, trace)
+    }
+
+    /// Returns the sequence of actions the agent would take over the given horizon, by
+    /// rolling out its choice of action on a copy of the agent. The plan ends early if the
+    /// agent would not meet its nutritional requirement.
+    pub fn plan(&self, horizon: UInt) -> Vec<Action> {
+        let mut dummy_agent = self.scratch();
+        let mut actions = Vec::new();
+        for _ in 0..horizon {
+            let action = dummy_agent.choose_action();
+            actions.push(action);
+            if !dummy_agent.step_forward(Some(action)) {
+                break;
+            }
+        }
+        actions
+    }
+
+    /// Returns every good the agent could eventually produce, starting from its current stock
+    /// and given enough time (ignoring survival). Unlike `producible_goods`, this considers
+    /// first producing the inputs required by other goods.
+    pub fn reachable_goods(&self) -> HashSet<Good> {
+        self.reachable_goods_with(&self.core.config.enabled_goods)
+    }
+
+    /// Returns every good the agent could eventually produce when only the given goods exist.
+    pub fn reachable_goods_with(&self, enabled_goods: &HashSet<Good>) -> HashSet<Good> {
+        let mut reachable: HashSet<Good> = HashSet::new();
+        // An input is available if it can be produced, or if the stock already holds enough.
+        let is_available = |input: &Good, quantity: UInt, reachable: &HashSet<Good>| {
+            reachable.contains(input) || self.stock().count_units(input) >= quantity
+        };
+        // Add goods whose inputs are all available until no more can be added.
+        loop {
+            let newly_reachable: Vec<Good> = Good::iter()
+                .filter(|good| enabled_goods.contains(good) && !reachable.contains(good))
+                .filter(|good| {
+                    good.required_input_quantities()
+                        .into_iter()
+                        .chain(good.transforms_from().map(|input| (input, 1)))
+                        .all(|(input, quantity)| is_available(&input, quantity, &reachable))
+                })
+                .collect();
+            if newly_reachable.is_empty() {
+                return reachable;
+            }
+            reachable.extend(newly_reachable);
         }
     }
 
@@ -70,7 +245,8 @@ impl RationalAgent {
         // producing all lower-order goods.
 
         // Return the maximum value of the capital good at all orders (some capital
-        // goods may be multiple-order).
+        // goods may be multiple-order). Every downstream good has a lower `Good::order`,
+        // so the recursion terminates at consumer goods (order 0).
         Good::iter()
             // .inspect(|x| println!("before filter: {:?}", x))
             .filter(|g| g.is_downsteam_of(good))
@@ -166,14 +342,16 @@ impl RationalAgent {
             panic!("Expected first-order producer.")
         }
         let capital_goods_unit = GoodsUnit::new(capital_good);
-        let mut dummy_agent = self.clone();
+        let mut dummy_agent = self.scratch();
         // Get the productivity of the consumer good with and without the capital good.
         let productivity_sans = match dummy_agent.productivity(consumer_good) {
             Productivity::Immediate(quantity) => quantity,
             Productivity::Delayed(_) => unreachable!("Consumer goods have immediate productivity"),
             Productivity::None => unreachable!("Consumer goods have immediate productivity"),
         };
-        dummy_agent.acquire(capital_goods_unit, 1);
+        dummy_agent
+            .acquire(capital_goods_unit, 1)
+            .expect("A single unit is a nonzero quantity.");
         let productivity_with = match dummy_agent.productivity(consumer_good) {
             Productivity::Immediate(quantity) => quantity,
             Productivity::Delayed(_) => unreachable!("Consumer goods have immediate productivity"),
@@ -182,8 +360,19 @@ impl RationalAgent {
         // Remove the capital good again.
         dummy_agent.stock_mut().remove(&capital_goods_unit, 1);
 
-        // Check that the productivity with the capital good exceeds that without.
-        assert!(productivity_with > productivity_sans);
+        // A capital good that does not increase productivity (e.g. because the agent already
+        // holds a unit of it) generates no first-order value.
+        if productivity_with <= productivity_sans {
+            // Warn only once per pair, to keep long runs quiet.
+            let pair = (*capital_good, *consumer_good);
+            if NO_IMPROVEMENT_WARNED.lock().unwrap().insert(pair) {
+                eprintln!(
+                    "Warning: {:?} does not increase the productivity of {:?}",
+                    capital_good, consumer_good
+                );
+            }
+            return 0.0;
+        }
 
         let mut sum: f32 = 0.0;
         let mut count = 0;
@@ -193,7 +382,9 @@ impl RationalAgent {
             // Add the marginal value of one unit of the consumer good, given a stock
             // that contains `count` additional units of the consumer good.
             sum = sum + dummy_agent.marginal_unit_value_of_consumer_good(consumer_good);
-            dummy_agent.acquire(GoodsUnit::new(consumer_good), 1);
+            dummy_agent
+                .acquire(GoodsUnit::new(consumer_good), 1)
+                .expect("A single unit is a nonzero quantity.");
 
             count = count + 1;
         }
@@ -210,16 +401,13 @@ impl RationalAgent {
         if !consumer_good.is_improved_using(capital_good) {
             panic!("Expected first-order improver.")
         }
-        let capital_goods_unit = GoodsUnit::new(capital_good);
-        let mut dummy_agent = self.clone();
-
-        // TODO NEXT.
-        // Get the additional of the consumer good with and without the capital good.
-        // Using similar methodology to productivity case, but with additional longevity instead
-        // of productivity.
-
-        // Temporary workaround: hard-code a Smoker valuation to 2.0.
-        2.0
+        // The value of an improving capital good lies in the additional longevity of the
+        // consumer goods in the stock, measured (like the value of a unit of a consumer good)
+        // by the additional days of survival it affords.
+        // TODO: include the value of preserving consumer goods produced in future.
+        let survival_sans = self.count_timesteps_till_death(None);
+        let survival_with = self.count_timesteps_till_death(Some(capital_good));
+        factor * survival_with.saturating_sub(survival_sans) as f32
     }
 
     // fn times_of_most_productive_first_order_use(&self, capital_good: &Good, consumer_good: &Good) ->  {
@@ -269,11 +457,13 @@ impl RationalAgent {
         };
         let mut sum: f32 = 0.0;
         let mut count = 0;
-        let mut dummy_agent = self.clone();
+        let mut dummy_agent = self.scratch();
         while count != productivity {
             // TODO: discounting.
             sum = sum + dummy_agent.marginal_unit_value_of_consumer_good(good);
-            dummy_agent.acquire(GoodsUnit::new(good), 1);
+            dummy_agent
+                .acquire(GoodsUnit::new(good), 1)
+                .expect("A single unit is a nonzero quantity.");
             count = count + 1;
         }
         sum
@@ -289,6 +479,16 @@ impl RationalAgent {
     /// unit of g (given stock S). If 1 additional unit of g (given stock S) produces no additional
     /// sustenance, it's marginal unit value is zero.
     fn marginal_unit_value_of_consumer_good(&self, good: &Good) -> f32 {
+        self.marginal_unit_value_of_consumer_good_with(good, &|g| self.productivity(g))
+    }
+
+    /// Returns the marginal value of a unit of a consumer good, given the productivity of
+    /// the agent for each good. The value is zero if no consumer good can be produced.
+    fn marginal_unit_value_of_consumer_good_with(
+        &self,
+        good: &Good,
+        productivity: &dyn Fn(&Good) -> Productivity,
+    ) -> f32 {
         if !good.is_consumer() {
             panic!("Expected consumer good.")
         }
@@ -326,13 +526,12 @@ impl RationalAgent {
         }
 
         // 2. Initialise the minimum time to produce equivalent sustenance, to the value for
-        // this good. Return zero value if the agent's productivity for this good is None.
-        let productivity_per_unit_time = self.productivity(good).per_unit_time();
-        if productivity_per_unit_time.is_none() {
-            return 0.0;
-        }
+        // this good. If the agent's productivity for this good is None, fall back to the
+        // alternative goods below.
         // Time to produce 1 unit of the good is (1 / amount produced in one day's production).
-        let mut min_equiv = (1 as f32) / productivity_per_unit_time.unwrap();
+        let mut min_equiv = productivity(good)
+            .per_unit_time()
+            .map_or(f32::INFINITY, |productivity| (1 as f32) / productivity);
 
         // 3. For every consumer good, compute the time taken to produce the same number of
         // days of sustenance.
@@ -341,15 +540,21 @@ impl RationalAgent {
             if alt_good == *good {
                 continue;
             }
-            if let Some(t) =
-                self.time_to_equiv_sustenance(alt_good, additional_sustenance, min_equiv)
-            {
+            if let Some(t) = self.time_to_equiv_sustenance(
+                alt_good,
+                additional_sustenance,
+                min_equiv,
+                productivity,
+            ) {
                 if t < min_equiv {
                     min_equiv = t;
                 }
             }
         }
-        // 3. Return the minium equivalent.
+        // 3. Return the minium equivalent, or zero if no consumer good can be produced.
+        if min_equiv.is_infinite() {
+            return 0.0;
+        }
         min_equiv
     }
 
@@ -360,24 +565,27 @@ impl RationalAgent {
         alt_good: Good,
         target_sustenance: u32,
         max: f32,
+        productivity: &dyn Fn(&Good) -> Productivity,
     ) -> Option<f32> {
         if target_sustenance == 0 {
             panic!("ERROR: target sustenance must be greater than zero.");
         }
-        let mut dummy_agent = self.clone();
+        let mut stock = self.stock.clone();
         let survival_time = self.count_timesteps_till_death(None);
         match alt_good.is_consumer() {
             true => {
-                if let Some(productivity) = self.productivity(&alt_good).per_unit_time() {
+                if let Some(productivity) = productivity(&alt_good).per_unit_time() {
                     let mut count_days = 0;
                     loop {
                         // Simulate one day of action to produce the alternative good.
                         // NB: we truncate productivity as this will be an integer for Productivity::Immediate consumer goods.
-                        dummy_agent.acquire(GoodsUnit::new(&alt_good), productivity.trunc() as u32);
+                        stock
+                            .add(GoodsUnit::new(&alt_good), productivity.trunc() as u32)
+                            .expect("Consumer goods have nonzero immediate productivity.");
                         count_days += 1;
 
                         // Compute the new survival time with the extra units of the alternative goods.
-                        let new_survival_time = dummy_agent.count_timesteps_till_death(None);
+                        let new_survival_time = self.count_timesteps_till_death_with(&stock, None);
 
                         // If the additional sustenance exceeds the target sustenance, return
                         // the equivalent day count necessasry to produce the additional goods.
@@ -389,6 +597,16 @@ impl RationalAgent {
                         if ((count_days as f32) / productivity) > max {
                             return None;
                         }
+                        // If enough has been produced to cover every day up to the target by
+                        // itself, the shortfall is due to expiry and further production cannot
+                        // help, so return None.
+                        let produced =
+                            count_days * productivity.trunc() as u32 * alt_good.nutrition_value();
+                        if produced
+                            >= (survival_time + target_sustenance + 1) * self.core.daily_nutrition
+                        {
+                            return None;
+                        }
                     }
                 }
                 // If the marginal productivity of the alt_good is None, return None
@@ -405,24 +623,129 @@ impl RationalAgent {
         additional_survival_days - survival_days
     }
 
+    /// Returns the total time taken to produce one unit of a good, including the time to produce
+    /// any required inputs (and, recursively, their inputs) missing from the current stock.
+    /// Returns None if the good cannot be produced.
+    pub fn total_production_time(&self, good: &Good) -> Option<f32> {
+        let mut available: Vec<Good> = Good::iter().filter(|g| self.stock.contains(g)).collect();
+        self.production_time(good, 1, &mut available, &mut Vec::new())
+    }
+
+    /// Returns the consumer goods the agent can produce, ranked by the days of sustenance
+    /// produced per unit time given its current stock (e.g. tools), most efficient first.
+    /// Goods of equal efficiency are in the order in which they are declared.
+    pub fn goods_by_efficiency(&self) -> Vec<(Good, f32)> {
+        let mut goods: Vec<(Good, f32)> = Good::iter()
+            .filter(|good| good.is_consumer())
+            .filter_map(|good| {
+                let productivity = self.productivity(&good).per_unit_time()?;
+                let sustenance = good.nutrition_value() as f32 / self.core.daily_nutrition as f32;
+                Some((good, productivity * sustenance))
+            })
+            .collect();
+        goods.sort_by(|(_, x), (_, y)| y.partial_cmp(x).unwrap());
+        goods
+    }
+
+    /// Returns the number of uses of a new unit of a capital good needed for the extra
+    /// production of a consumer good to offset the production forgone while building it
+    /// (including any missing inputs). If the capital good increases the productivity of
+    /// several consumer goods, the shortest payback is returned. Returns None if the
+    /// capital good cannot be produced or does not increase the productivity of any
+    /// consumer good.
+    pub fn capital_good_payback(&self, good: &Good) -> Option<f32> {
+        let build_time = self.total_production_time(good)?;
+        let mut dummy_agent = self.scratch();
+        dummy_agent.acquire(GoodsUnit::new(good), 1).ok()?;
+        Good::iter()
+            .filter(|consumer_good| {
+                consumer_good.is_consumer() && consumer_good.is_produced_using(good)
+            })
+            .filter_map(|consumer_good| {
+                let productivity_sans = self.productivity(&consumer_good).per_unit_time()?;
+                let productivity_with = dummy_agent.productivity(&consumer_good).per_unit_time()?;
+                (productivity_with > productivity_sans).then(|| {
+                    build_time * productivity_sans / (productivity_with - productivity_sans)
+                })
+            })
+            .min_by(|x, y| x.partial_cmp(y).unwrap())
+    }
+
+    /// Returns the time taken to produce a quantity of a good, given the goods already available,
+    /// after first producing any missing required inputs (which are then added to the available
+    /// goods). The path of goods currently being produced is tracked to guard against cycles.
+    fn production_time(
+        &self,
+        good: &Good,
+        quantity: UInt,
+        available: &mut Vec<Good>,
+        path: &mut Vec<Good>,
+    ) -> Option<f32> {
+        if path.contains(good) {
+            return None;
+        }
+        path.push(*good);
+        let mut time = 0.0;
+        for required_input in good.required_inputs() {
+            // Materials are used up in production, whereas a single unit of a capital good
+            // that is not a material suffices.
+            let required_qty = match required_input.is_material() {
+                true => good
+                    .total_material_cost()
+                    .iter()
+                    .filter(|(material, _)| *material == required_input)
+                    .map(|(_, qty)| qty * quantity)
+                    .sum(),
+                false => 1,
+            };
+            let held_qty = self.stock.count_units(&required_input);
+            if held_qty < required_qty {
+                time += self.production_time(
+                    &required_input,
+                    required_qty - held_qty,
+                    available,
+                    path,
+                )?;
+            }
+        }
+        time += match good.productivity_with_boosts(available, &self.core.config.tool_boosts) {
+            Productivity::Immediate(qty) => quantity as f32 / qty as f32,
+            Productivity::Delayed(interval) => {
+                (quantity * self.build_time(good).unwrap_or(interval)) as f32
+            }
+            Productivity::None => return None,
+        };
+        path.pop();
+        if !available.contains(good) {
+            available.push(*good);
+        }
+        Some(time)
+    }
+
     /// Counts the number of timesteps that the agent can survive with the current
     /// stock, plus one unit of an optional additional good, assuming only consumption
     /// (i.e. no production/acquision of new goods).
     fn count_timesteps_till_death(&self, additional_good: Option<&Good>) -> UInt {
-        let mut dummy_agent = self.clone();
-        if let Some(good) = additional_good {
-            dummy_agent.acquire(GoodsUnit::new(good), 1);
-        }
-        let mut count = 0;
-        loop {
-            let action = Action::Leisure;
-            if !dummy_agent.consume(self.daily_nutrition) {
-                break; // Break out as soon as death happens.
+        self.count_timesteps_till_death_with(&self.stock, additional_good)
+    }
+
+    /// Counts the number of timesteps that the agent could survive with the given stock, plus
+    /// one unit of an optional additional good. Only the stock is copied, not the whole agent.
+    fn count_timesteps_till_death_with(
+        &self,
+        stock: &Stock,
+        additional_good: Option<&Good>,
+    ) -> UInt {
+        match additional_good {
+            Some(good) => {
+                let mut stock = stock.clone();
+                stock
+                    .add(GoodsUnit::new(good), 1)
+                    .expect("A single unit is a nonzero quantity.");
+                stock.survival_days(self.core.daily_nutrition)
             }
-            dummy_agent.set_stock(dummy_agent.stock().step_forward(action));
-            count += 1;
+            None => stock.survival_days(self.core.daily_nutrition),
         }
-        count
     }
 }
 
@@ -435,6 +758,14 @@ impl Agent for RationalAgent {
         "Rational"
     }
 
+    fn core(&self) -> &AgentCore {
+        &self.core
+    }
+
+    fn core_mut(&mut self) -> &mut AgentCore {
+        &mut self.core
+    }
+
     fn stock(&self) -> &Stock {
         &self.stock
     }
@@ -448,12 +779,12 @@ impl Agent for RationalAgent {
     }
 
     fn choose_action(&mut self) -> Action {
-        todo!()
+        self.choose_action_with_leisure_value(self.core.config.agent.leisure_value)
     }
 
     fn choose_action_with_model(&mut self, model: &Model) -> Action {
-        let action =
-            model.sample_action_by_id(0, &self.stock.representation(), &mut StdRng::from_os_rng());
+        let state = self.stock.representation_with(&self.core.config);
+        let action = model.sample_action_by_id(self.id as u32, &state, &mut self.core.rng);
         self.action_history.push(action.into());
         action.into()
     }
@@ -476,20 +807,13 @@ impl Agent for RationalAgent {
         &mut self.reward_history
     }
 
-    fn is_alive(&self) -> bool {
-        self.is_alive
+    fn acquire(&mut self, goods_unit: GoodsUnit, quantity: UInt) -> Result<(), StockError> {
+        self.stock.add(goods_unit, quantity)
     }
 
-    fn set_liveness(&mut self, value: bool) {
-        self.is_alive = value;
-    }
-
-    fn acquire(&mut self, goods_unit: GoodsUnit, quantity: UInt) {
-        self.stock.add(goods_unit, quantity);
-    }
-
-    fn acquire_partial(&mut self, partial_goods_unit: PartialGoodsUnit) {
-        self.stock.add_partial(partial_goods_unit);
+    fn acquire_partial(&mut self, partial_goods_unit: PartialGoodsUnit) -> Result<(), StockError> {
+        let limit = self.core.config.agent.max_concurrent_builds;
+        self.stock.add_partial_with_limit(partial_goods_unit, limit)
     }
 
     fn get_partial(&self, good: Good) -> Option<PartialGoodsUnit> {
@@ -500,7 +824,9 @@ impl Agent for RationalAgent {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::{Config, SharedConfig};
     use crate::goods::{Good, GoodsUnit};
+    use std::collections::HashMap;
 
     #[test]
     fn test_value_generated_by_higher_order_good() {
@@ -555,6 +881,211 @@ mod tests {
         assert_eq!(result, 2.5);
     }
 
+    #[test]
+    fn test_value_of_first_order_improvement() {
+        let daily_nutrition = 3;
+
+        // Without fish in stock, a smoker has nothing to preserve.
+        let agent = RationalAgent::new(1, daily_nutrition);
+        assert_eq!(
+            agent.marginal_unit_value_of_capital_good(&Good::Smoker),
+            0.0
+        );
+
+        // Freshly caught fish expire after one day, unless preserved by a smoker. Six fish
+        // last one day without a smoker and two days with one.
+        let mut agent = RationalAgent::new(1, daily_nutrition);
+        agent.acquire(GoodsUnit::new(&Good::Fish), 6).unwrap();
+        assert_eq!(agent.count_timesteps_till_death(None), 1);
+        assert_eq!(agent.count_timesteps_till_death(Some(&Good::Smoker)), 2);
+        assert_eq!(
+            agent.marginal_unit_value_of_capital_good(&Good::Smoker),
+            1.0
+        );
+        // A second smoker preserves nothing more.
+        agent.acquire(GoodsUnit::new(&Good::Smoker), 1).unwrap();
+        assert_eq!(
+            agent.marginal_unit_value_of_capital_good(&Good::Smoker),
+            0.0
+        );
+
+        // With five fish, a sixth extends survival only if the fish can be preserved
+        // until the second day.
+        let mut agent = RationalAgent::new(1, daily_nutrition);
+        agent.acquire(GoodsUnit::new(&Good::Fish), 5).unwrap();
+        assert_eq!(agent.marginal_unit_value_of_consumer_good(&Good::Fish), 0.0);
+        agent.acquire(GoodsUnit::new(&Good::Smoker), 1).unwrap();
+        assert!(agent.marginal_unit_value_of_consumer_good(&Good::Fish) > 0.0);
+    }
+
+    #[test]
+    fn test_reachable_goods() {
+        // From an empty stock, every good is eventually reachable (e.g. Axe, then Timber,
+        // then Boat), although only goods without inputs are immediately producible.
+        let agent = RationalAgent::new(1, 3);
+        let all_goods: HashSet<Good> = Good::iter().collect();
+        assert_eq!(agent.reachable_goods_with(&all_goods), all_goods);
+        assert!(!agent.producible_goods().contains(&Good::Boat));
+
+        // Without the Axe, no timber can be produced, nor anything built from it.
+        let enabled_goods: HashSet<Good> = Good::iter().filter(|good| *good != Good::Axe).collect();
+        let expected = HashSet::from([Good::Berries, Good::Fish, Good::Basket, Good::Spear]);
+        assert_eq!(agent.reachable_goods_with(&enabled_goods), expected);
+
+        // Timber already in stock makes the goods built from it reachable, if there is enough.
+        let mut agent = RationalAgent::new(1, 3);
+        agent.acquire(GoodsUnit::new(&Good::Timber), 3).unwrap();
+        let reachable = agent.reachable_goods_with(&enabled_goods);
+        assert!(reachable.contains(&Good::Smoker));
+        assert!(!reachable.contains(&Good::Boat));
+        assert!(!reachable.contains(&Good::Timber));
+    }
+
+    #[test]
+    fn test_decision_snapshot() {
+        let mut agent = RationalAgent::new(1, 3);
+        agent.acquire(GoodsUnit::new(&Good::Berries), 10).unwrap();
+        agent.step_forward(Some(Action::ProduceGood(Good::Spear)));
+        assert_eq!(agent.reward_history().len(), 1);
+
+        let snapshot = agent.decision_snapshot();
+        assert_eq!(&snapshot.stock, agent.stock());
+        assert_eq!(snapshot.daily_nutrition, 3);
+
+        // A scratch agent has the same stock and nutrition, but no history.
+        let scratch = RationalAgent::from_decision_state(1, snapshot);
+        assert_eq!(scratch.stock(), agent.stock());
+        assert_eq!(scratch.daily_nutrition(), agent.daily_nutrition());
+        assert!(scratch.reward_history().is_empty());
+        assert!(scratch.stock_history().is_empty());
+        assert!(scratch.production_totals().is_empty());
+    }
+
+    #[test]
+    fn test_capital_good_payback() {
+        let mut agent = RationalAgent::new(1, 3);
+        // A spear takes one day to make, in which two fish could have been caught instead,
+        // and each use of the spear catches eight more fish.
+        assert_eq!(
+            agent.capital_good_payback(&Good::Spear),
+            Some(1.0 * 2.0 / 8.0)
+        );
+        // A boat takes 17 days to make, including the axe (2 days) and timber (5 days).
+        assert_eq!(
+            agent.capital_good_payback(&Good::Boat),
+            Some(17.0 * 2.0 / 18.0)
+        );
+        // A smoker improves fish but does not increase the catch.
+        assert_eq!(agent.capital_good_payback(&Good::Smoker), None);
+        // A second spear adds nothing.
+        agent.acquire(GoodsUnit::new(&Good::Spear), 1).unwrap();
+        assert_eq!(agent.capital_good_payback(&Good::Spear), None);
+    }
+
+    #[test]
+    fn test_goods_by_efficiency() {
+        let mut agent = RationalAgent::new(1, 2);
+        // Without tools, a day's foraging yields four berries but a day's fishing only two fish.
+        assert_eq!(
+            agent.goods_by_efficiency(),
+            vec![(Good::Berries, 2.0), (Good::Fish, 1.0)]
+        );
+        // With a boat, a day's fishing yields twenty fish.
+        agent.acquire(GoodsUnit::new(&Good::Boat), 1).unwrap();
+        assert_eq!(
+            agent.goods_by_efficiency(),
+            vec![(Good::Fish, 10.0), (Good::Berries, 2.0)]
+        );
+    }
+
+    #[test]
+    fn test_benefit_report() {
+        let agent = RationalAgent::new(1, 3);
+        let report = agent.benefit_report();
+        assert_eq!(report.len(), ActionFlattened::enabled().len());
+
+        let smoker = report
+            .iter()
+            .find(|r| r.action == Action::ProduceGood(Good::Smoker))
+            .unwrap();
+        assert!(!smoker.is_producible);
+        assert_eq!(smoker.missing_input, Some(Good::Timber));
+        assert_eq!(smoker.benefit, None);
+
+        let berries = report
+            .iter()
+            .find(|r| r.action == Action::ProduceGood(Good::Berries))
+            .unwrap();
+        assert!(berries.is_producible);
+        assert_eq!(berries.benefit, Some(0.25));
+
+        // The report is serializable for logging.
+        assert!(serde_json::to_string(&report).is_ok());
+    }
+
+    #[test]
+    fn test_choose_action_leisure_value() {
+        let daily_nutrition = 3;
+        // Run an agent for a number of days, returning the count of days of leisure.
+        let count_leisure = |leisure_value: f32| {
+            let mut agent = RationalAgent::new(1, daily_nutrition);
+            agent.acquire(GoodsUnit::new(&Good::Berries), 30).unwrap();
+            (0..10)
+                .filter(|_| {
+                    let action = agent.choose_action_with_leisure_value(leisure_value);
+                    agent.act(action);
+                    agent.consume(daily_nutrition);
+                    let stock = agent.stock().step_forward(action);
+                    agent.set_stock(stock);
+                    action == Action::Leisure
+                })
+                .count()
+        };
+        let low = count_leisure(0.0);
+        let high = count_leisure(100.0);
+        assert_eq!(high, 10);
+        assert!(high > low);
+
+        // With an empty stock, production is chosen unless leisure is valued more highly.
+        let mut agent = RationalAgent::new(1, daily_nutrition);
+        assert_ne!(agent.choose_action_with_leisure_value(0.0), Action::Leisure);
+        assert_eq!(agent.choose_action_with_leisure_value(1.0), Action::Leisure);
+    }
+
+    #[test]
+    fn test_choose_action_traced() {
+        // With an empty stock, producing food outweighs leisure.
+        let mut agent = RationalAgent::new(1, 3);
+        let (action, trace) = agent.choose_action_traced_with_leisure_value(0.0);
+        assert!(matches!(action, Action::ProduceGood(_)));
+        assert_eq!(trace, DecisionTrace::HighestMarginalBenefit);
+
+        // A well-stocked agent prefers leisure.
+        let mut agent = RationalAgent::new(1, 3);
+        agent.acquire(GoodsUnit::new(&Good::Berries), 30).unwrap();
+        agent.acquire(GoodsUnit::new(&Good::Fish), 30).unwrap();
+        let (action, trace) = agent.choose_action_traced_with_leisure_value(1.0);
+        assert_eq!(action, Action::Leisure);
+        assert_eq!(trace, DecisionTrace::LeisurePreferred);
+        assert_eq!(agent.action_history(), &[Action::Leisure]);
+    }
+
+    #[test]
+    fn test_leisure_opportunity_cost() {
+        // Near starvation, leisure forgoes the sustenance that production would provide.
+        let agent = RationalAgent::new(1, 3);
+        let starving_cost = agent.leisure_opportunity_cost();
+        assert!(starving_cost > 0.0);
+
+        // A well-fed agent forgoes little by taking leisure.
+        let mut agent = RationalAgent::new(1, 3);
+        agent.acquire(GoodsUnit::new(&Good::Berries), 30).unwrap();
+        agent.acquire(GoodsUnit::new(&Good::Fish), 30).unwrap();
+        let fed_cost = agent.leisure_opportunity_cost();
+        assert!(fed_cost < starving_cost);
+        assert_eq!(fed_cost, 0.0);
+    }
+
     #[test]
     fn test_marginal_benefit_of_action() {
         let daily_nutrition = 3;
@@ -576,7 +1107,7 @@ mod tests {
         // is zero. So the marginal benefit of the action to produce fish is 0.
         assert_eq!(agent.marginal_benefit_of_action(&action), 0.0);
 
-        agent.acquire(GoodsUnit::new(&Good::Berries), 1);
+        agent.acquire(GoodsUnit::new(&Good::Berries), 1).unwrap();
 
         // Given an initial stock of 1 unit of berries, the marginal value of the first unit
         // of fish is zero but the value of the second is 1/4. So the marginal benefit of the
@@ -584,6 +1115,57 @@ mod tests {
         assert_eq!(agent.marginal_benefit_of_action(&action), 0.25);
     }
 
+    #[test]
+    fn test_value_of_first_order_productivity_without_improvement() {
+        // An additional basket does not increase the productivity of berries for an agent
+        // that already holds one.
+        let mut agent = RationalAgent::new(1, 3);
+        agent.acquire(GoodsUnit::new(&Good::Basket), 1).unwrap();
+        agent.acquire(GoodsUnit::new(&Good::Berries), 10).unwrap();
+        let value = agent.value_of_first_order_productivity(&Good::Basket, &Good::Berries, 1.0);
+        assert_eq!(value, 0.0);
+
+        // Nor does a spear configured to catch no more fish than are caught by hand.
+        let mut agent = RationalAgent::new(1, 3);
+        agent.set_config(SharedConfig::new(Config {
+            tool_boosts: HashMap::from([(Good::Spear, HashMap::from([(Good::Fish, 2)]))]),
+            ..Config::default()
+        }));
+        agent.acquire(GoodsUnit::new(&Good::Fish), 10).unwrap();
+        let value = agent.value_of_first_order_productivity(&Good::Spear, &Good::Fish, 1.0);
+        assert_eq!(value, 0.0);
+    }
+
+    #[test]
+    fn test_plan() {
+        let agent = RationalAgent::new(1, 3);
+        let plan = agent.plan(5);
+        assert!(!plan.is_empty() && plan.len() <= 5);
+        assert_eq!(plan[0], Action::ProduceGood(Good::Berries));
+        // Planning does not change the agent.
+        assert!(agent.action_history().is_empty());
+        assert_eq!(agent.stock(), &Stock::default());
+    }
+
+    #[test]
+    fn test_marginal_unit_value_zero_productivity() {
+        let mut agent = RationalAgent::new(1, 3);
+        agent.acquire(GoodsUnit::new(&Good::Fish), 2).unwrap();
+
+        // With no consumer good producible, the marginal unit value is zero.
+        let value =
+            agent.marginal_unit_value_of_consumer_good_with(&Good::Fish, &|_| Productivity::None);
+        assert_eq!(value, 0.0);
+
+        // If fish cannot be produced, the value falls back to producing berries.
+        let berries_only = |good: &Good| match good {
+            Good::Berries => Productivity::Immediate(4),
+            _ => Productivity::None,
+        };
+        let value = agent.marginal_unit_value_of_consumer_good_with(&Good::Fish, &berries_only);
+        assert_eq!(value, 0.25);
+    }
+
     #[test]
     fn test_marginal_unit_value() {
         // Test marginal unit value of berries, given zero stock.
@@ -599,7 +1181,7 @@ mod tests {
             0.0
         );
 
-        agent.acquire(berries_unit, 1);
+        agent.acquire(berries_unit, 1).unwrap();
         // 1 additional unit of berries provides no additional sustenance when stock is 1 unit of berries.
         // So the marginal value of 1 unit of berries is zero.
         assert_eq!(
@@ -607,7 +1189,7 @@ mod tests {
             0.0
         );
 
-        agent.acquire(berries_unit, 1);
+        agent.acquire(berries_unit, 1).unwrap();
 
         // 1 additional unit of berries provides 1 day of additional sustenance when
         // stock is 2 units of berries. Minimum time required to produce sustanance
@@ -627,12 +1209,12 @@ mod tests {
         // So the marginal value of 1 unit of fish is zero.
         assert_eq!(agent.marginal_unit_value_of_consumer_good(&Good::Fish), 0.0);
 
-        agent.acquire(fish_unit, 1);
+        agent.acquire(fish_unit, 1).unwrap();
         // 1 additional unit of fish provides no additional sustenance when stock is 1 unit of fish.
         // So the marginal value of 1 unit of fish is zero.
         assert_eq!(agent.marginal_unit_value_of_consumer_good(&Good::Fish), 0.0);
 
-        agent.acquire(fish_unit, 1);
+        agent.acquire(fish_unit, 1).unwrap();
         // 1 additional unit of fish provides 1 day of additional sustenance when
         // stock is 2 units of fish. Minimum time required to produce sustanance
         // equivalent to additional 1 unit of fish is 1/4 days (by producing berries, not fish!).
@@ -647,6 +1229,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_total_production_time() {
+        let mut agent = RationalAgent::new(1, 3);
+
+        // One unit of berries takes a quarter of a day to produce.
+        assert_eq!(agent.total_production_time(&Good::Berries), Some(0.25));
+
+        // From an empty stock, a boat requires an axe (2 days), 10 units of timber produced
+        // using the axe (5 days) and the boat itself (10 days).
+        assert_eq!(agent.total_production_time(&Good::Boat), Some(17.0));
+
+        // With an axe and some timber already in stock, only the remaining timber and the
+        // boat itself are produced.
+        agent.acquire(GoodsUnit::new(&Good::Axe), 1).unwrap();
+        agent.acquire(GoodsUnit::new(&Good::Timber), 4).unwrap();
+        assert_eq!(agent.total_production_time(&Good::Boat), Some(13.0));
+    }
+
     #[test]
     fn test_additional_sustenance() {
         // Test additional sustenance from berries.
@@ -659,11 +1259,11 @@ mod tests {
         assert_eq!(agent.additional_sustenance(&Good::Berries), expected);
 
         // No additional sustenance from 1 unit of berries (when starting from 1 unit).
-        agent.acquire(berries_unit, 1);
+        agent.acquire(berries_unit, 1).unwrap();
         let expected = 0;
         assert_eq!(agent.additional_sustenance(&Good::Berries), expected);
 
-        agent.acquire(berries_unit, 1);
+        agent.acquire(berries_unit, 1).unwrap();
 
         let expected = 1;
         // One additional day's sustenance from 1 unit of berries (when starting from 2 units).
@@ -685,13 +1285,13 @@ mod tests {
         let berries_unit = GoodsUnit::new(&Good::Berries);
         let fish_unit = GoodsUnit::new(&Good::Fish);
 
-        agent.acquire(berries_unit, 1);
+        agent.acquire(berries_unit, 1).unwrap();
 
         // With one unit of berries the timesteps till death is zero.
         let expected = 0;
         assert_eq!(agent.count_timesteps_till_death(None), expected);
 
-        agent.acquire(berries_unit, 1);
+        agent.acquire(berries_unit, 1).unwrap();
 
         // With two units of berries the timesteps till death is zero.
         let expected = 0;
@@ -712,19 +1312,19 @@ mod tests {
             expected
         );
 
-        agent.acquire(berries_unit, 1);
+        agent.acquire(berries_unit, 1).unwrap();
 
         // With three units of berries the timesteps till death is one.
         let expected = 1;
         assert_eq!(agent.count_timesteps_till_death(None), expected);
 
-        agent.acquire(berries_unit, 1);
+        agent.acquire(berries_unit, 1).unwrap();
 
         // With four units of berries the timesteps till death is one.
         let expected = 1;
         assert_eq!(agent.count_timesteps_till_death(None), expected);
 
-        agent.acquire(berries_unit, 1);
+        agent.acquire(berries_unit, 1).unwrap();
 
         // With five units of berries the timesteps till death is one.
         let expected = 1;
@@ -738,13 +1338,13 @@ mod tests {
             expected
         );
 
-        agent.acquire(berries_unit, 1);
+        agent.acquire(berries_unit, 1).unwrap();
 
         // With six units of berries the timesteps till death is two.
         let expected = 2;
         assert_eq!(agent.count_timesteps_till_death(None), expected);
 
-        agent.acquire(fish_unit, 2);
+        agent.acquire(fish_unit, 2).unwrap();
 
         // With 6 units of berries & 2 units of fish the timesteps till death is two.
         let expected = 2;